@@ -0,0 +1,4 @@
+name="Mod A"
+version="1.0"
+supported_version="1.35.*"
+replace_path="common/units"
@@ -17,7 +17,9 @@
  */
 extern crate clausewitz_parser;
 extern crate encoding;
+extern crate serde_json;
 
+use clausewitz_parser::json::to_json;
 use clausewitz_parser::Parser;
 use clausewitz_parser::Tokenizer;
 use encoding::all::WINDOWS_1252;
@@ -30,7 +32,7 @@ fn main() {
     let mut args = args();
     args.next();
     let name = args.next().unwrap();
-    println!("open: {}", name);
+    eprintln!("open: {}", name);
     let mut file = File::open(name).unwrap();
     let mut buf = Vec::new();
     file.read_to_end(&mut buf).unwrap();
@@ -48,5 +50,5 @@ fn main() {
     let mut parser = Parser::new(tokens);
     let clvals = parser.parse().unwrap();
 
-    println!("CL Values:\n{:#?}", clvals);
+    println!("{}", serde_json::to_string_pretty(&to_json(&clvals)).unwrap());
 }
@@ -0,0 +1,122 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+extern crate clausewitz_parser;
+
+use clausewitz_parser::cst::SyntaxTree;
+use clausewitz_parser::diagnostic::{LineIndex, Severity};
+use clausewitz_parser::format::format;
+use std::env::args;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{stdin, stdout, Error, ErrorKind};
+use std::process::exit;
+
+const USAGE: &str = "usage: clausewitz-fmt [--check] [FILE...]
+
+Formats Clausewitz script files in place.
+Without files the script is read from stdin and written to stdout.
+Files with syntax errors or which can't be read are reported and left unchanged,
+the other files are still formatted and the exit status is 2.
+
+options:
+    --check    don't write anything, exit with 1 if a file is not formatted";
+
+fn read(name: &str) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    if name == "-" {
+        stdin().read_to_end(&mut buf)?;
+    } else {
+        File::open(name)?.read_to_end(&mut buf)?;
+    }
+    Ok(buf)
+}
+
+/// Format a single file, returns `true` if it was formatted already
+///
+/// Fails without writing anything if the file has syntax errors.
+fn run(name: &str, check: bool) -> std::io::Result<bool> {
+    let buf = read(name)?;
+    let tree = SyntaxTree::parse(&buf);
+    let errors: Vec<_> = tree
+        .diagnostics()
+        .into_iter()
+        .filter(|d| d.severity == Severity::Error)
+        .collect();
+    if !errors.is_empty() {
+        let index = LineIndex::new(&buf);
+        for error in &errors {
+            eprintln!("{}", error.to_string_in(name, &index));
+        }
+        return Err(Error::new(ErrorKind::InvalidData, "syntax errors, not formatted"));
+    }
+    let formatted = format(&tree);
+    let unchanged = formatted == buf;
+
+    if check {
+        if !unchanged {
+            eprintln!("{} is not formatted", name);
+        }
+    } else if name == "-" {
+        stdout().write_all(&formatted)?;
+    } else if !unchanged {
+        File::create(name)?.write_all(&formatted)?;
+    }
+
+    Ok(unchanged)
+}
+
+fn main() {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with("--") => {
+                eprintln!("unknown option: {}\n\n{}", arg, USAGE);
+                exit(2);
+            }
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        files.push("-".to_string());
+    }
+
+    let mut formatted = true;
+    let mut failed = false;
+    for name in &files {
+        match run(name, check) {
+            Ok(unchanged) => formatted &= unchanged,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        exit(2);
+    }
+    if check && !formatted {
+        exit(1);
+    }
+}
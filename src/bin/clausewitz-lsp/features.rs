@@ -0,0 +1,226 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The language features on a single document
+
+use clausewitz_parser::cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxTree};
+use clausewitz_parser::diagnostic::{self, Severity};
+use clausewitz_parser::format::format;
+use index::{DefinitionKind, Index};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, DocumentSymbol, FoldingRange, FoldingRangeKind, Location, NumberOrString,
+    Position, SymbolKind, TextEdit,
+};
+use position::Positions;
+
+/// The syntax errors of a document
+pub fn diagnostics(tree: &SyntaxTree, positions: &Positions) -> Vec<Diagnostic> {
+    tree.diagnostics()
+        .into_iter()
+        .map(|d: diagnostic::Diagnostic| Diagnostic {
+            range: positions.range(d.span),
+            severity: Some(match d.severity {
+                Severity::Error => DiagnosticSeverity::ERROR,
+                Severity::Warning => DiagnosticSeverity::WARNING,
+                Severity::Info => DiagnosticSeverity::INFORMATION,
+            }),
+            code: Some(NumberOrString::String(d.code)),
+            source: Some("clausewitz".to_string()),
+            message: d.message,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The top level entries as outline
+pub fn document_symbols(tree: &SyntaxTree, positions: &Positions) -> Vec<DocumentSymbol> {
+    tree.root()
+        .entries()
+        .filter_map(|entry| {
+            let key = entry.key()?;
+            let kind = match entry.value().map(|v| v.kind()) {
+                Some(SyntaxKind::Block) => SymbolKind::OBJECT,
+                _ => SymbolKind::PROPERTY,
+            };
+            #[allow(deprecated)]
+            Some(DocumentSymbol {
+                name: tree.scalar(key).to_string(),
+                detail: None,
+                kind,
+                tags: None,
+                deprecated: None,
+                range: positions.range(entry.span()),
+                selection_range: positions.range(key.span),
+                children: None,
+            })
+        })
+        .collect()
+}
+
+/// A folding range for every block spanning multiple lines
+pub fn folding_ranges(tree: &SyntaxTree, positions: &Positions) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    collect_folding_ranges(tree.root(), positions, &mut ranges);
+    ranges
+}
+
+fn collect_folding_ranges(node: &SyntaxNode, positions: &Positions, ranges: &mut Vec<FoldingRange>) {
+    if node.kind() == SyntaxKind::Block {
+        let range = positions.range(node.span());
+        if range.end.line > range.start.line {
+            ranges.push(FoldingRange {
+                start_line: range.start.line,
+                start_character: Some(range.start.character),
+                end_line: range.end.line,
+                end_character: Some(range.end.character),
+                kind: Some(FoldingRangeKind::Region),
+                collapsed_text: None,
+            });
+        }
+    }
+    for child in node.children().iter().filter_map(SyntaxElement::as_node) {
+        collect_folding_ranges(child, positions, ranges);
+    }
+}
+
+/// The edit formatting the whole document, if it isn't formatted
+///
+/// Documents with syntax errors aren't formatted, the formatter would close unclosed blocks.
+pub fn formatting(tree: &SyntaxTree, positions: &Positions) -> Vec<TextEdit> {
+    if tree.diagnostics().iter().any(|d| d.severity == Severity::Error) {
+        return Vec::new();
+    }
+    let formatted = format(tree);
+    if formatted == tree.source() {
+        return Vec::new();
+    }
+    vec![TextEdit::new(
+        positions.full_range(),
+        String::from_utf8_lossy(&formatted).into_owned(),
+    )]
+}
+
+/// The definitions of the scripted trigger, effect or event at a position
+///
+/// Keys are looked up as scripted triggers and effects, values as event ids.
+pub fn definition(tree: &SyntaxTree, positions: &Positions, index: &Index, position: Position) -> Vec<Location> {
+    let offset = positions.offset(position);
+    let (token, is_key) = match token_at(tree.root(), offset, false) {
+        Some(found) => found,
+        None => return Vec::new(),
+    };
+    index
+        .find(&tree.scalar(&token))
+        .iter()
+        .filter(|d| is_key != (d.kind == DefinitionKind::Event))
+        .map(|d| d.location.clone())
+        .collect()
+}
+
+/// The scalar token at an offset, including its end, and whether it is the key of an entry
+fn token_at(
+    node: &SyntaxNode,
+    offset: usize,
+    in_entry: bool,
+) -> Option<(clausewitz_parser::cst::SyntaxToken, bool)> {
+    for (index, child) in node.children().iter().enumerate() {
+        let span = child.span();
+        if offset < span.start || offset > span.end {
+            continue;
+        }
+        match child {
+            SyntaxElement::Node(child) => {
+                if let Some(found) = token_at(child, offset, child.kind() == SyntaxKind::Entry) {
+                    return Some(found);
+                }
+            }
+            SyntaxElement::Token(token) if token.kind.is_scalar() => {
+                return Some((*token, in_entry && index == 0));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Range, Url};
+
+    #[test]
+    fn test_document_symbols() {
+        let text = "a = 1\nb = {\n\tc = 2\n}\n";
+        let tree = SyntaxTree::parse(text.as_bytes());
+        let symbols = document_symbols(&tree, &Positions::new(text));
+        let names: Vec<_> = symbols.iter().map(|s| (s.name.as_str(), s.kind)).collect();
+        assert_eq!(names, vec![("a", SymbolKind::PROPERTY), ("b", SymbolKind::OBJECT)]);
+        assert_eq!(symbols[1].range, Range::new(Position::new(1, 0), Position::new(3, 1)));
+        assert_eq!(symbols[1].selection_range, Range::new(Position::new(1, 0), Position::new(1, 1)));
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let text = "a = { 1 }\nb = {\n\tc = {\n\t\td = 1\n\t}\n}\n";
+        let tree = SyntaxTree::parse(text.as_bytes());
+        let ranges: Vec<_> = folding_ranges(&tree, &Positions::new(text))
+            .iter()
+            .map(|r| (r.start_line, r.end_line))
+            .collect();
+        assert_eq!(ranges, vec![(1, 5), (2, 4)]);
+    }
+
+    #[test]
+    fn test_formatting() {
+        let text = "a=1\nb={c=2}\n";
+        let tree = SyntaxTree::parse(text.as_bytes());
+        let edits = formatting(&tree, &Positions::new(text));
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range, Range::new(Position::new(0, 0), Position::new(2, 0)));
+        let formatted = edits[0].new_text.clone();
+        let tree = SyntaxTree::parse(formatted.as_bytes());
+        assert!(formatting(&tree, &Positions::new(&formatted)).is_empty());
+
+        let text = "a = {\n\tb = c\n";
+        let tree = SyntaxTree::parse(text.as_bytes());
+        assert!(formatting(&tree, &Positions::new(text)).is_empty());
+    }
+
+    #[test]
+    fn test_definition() {
+        let mut index = Index::default();
+        let triggers = Url::parse("file:///mod/common/scripted_triggers/t.txt").unwrap();
+        let events = Url::parse("file:///mod/events/e.txt").unwrap();
+        index.update(&triggers, "is_rich = { }\n");
+        index.update(&events, "country_event = { id = is_rich }\n");
+
+        let text = "effect = {\n\tif = { limit = { is_rich = yes } country_event = { id = is_rich } }\n}\n";
+        let tree = SyntaxTree::parse(text.as_bytes());
+        let positions = Positions::new(text);
+        let find = |line, character| definition(&tree, &positions, &index, Position::new(line, character));
+
+        // the key is the trigger, the value the event with the same name
+        assert_eq!(find(1, 18).iter().map(|l| &l.uri).collect::<Vec<_>>(), vec![&triggers]);
+        assert_eq!(find(1, 25).iter().map(|l| &l.uri).collect::<Vec<_>>(), vec![&triggers]);
+        assert_eq!(find(1, 28), vec![]);
+        assert_eq!(find(1, 60).iter().map(|l| &l.uri).collect::<Vec<_>>(), vec![&events]);
+        assert_eq!(find(1, 64).iter().map(|l| &l.uri).collect::<Vec<_>>(), vec![&events]);
+        assert_eq!(find(1, 67), vec![]);
+        assert_eq!(find(0, 2), vec![]);
+    }
+}
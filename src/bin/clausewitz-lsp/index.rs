@@ -0,0 +1,165 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The definitions of a workspace
+//!
+//! Scripted triggers and effects are the top level keys of the files in
+//! `common/scripted_triggers` and `common/scripted_effects`, events are the `id`s of the top
+//! level blocks in `events`.
+
+use clausewitz_parser::cst::{SyntaxElement, SyntaxTree};
+use encoding::all::WINDOWS_1252;
+use encoding::{DecoderTrap, Encoding};
+use lsp_types::{Location, Url};
+use position::Positions;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    ScriptedTrigger,
+    ScriptedEffect,
+    Event,
+}
+
+impl DefinitionKind {
+    /// The kind of the definitions in a file, by its directory
+    fn of(uri: &Url) -> Option<DefinitionKind> {
+        let path = uri.path();
+        if path.contains("/common/scripted_triggers/") {
+            Some(DefinitionKind::ScriptedTrigger)
+        } else if path.contains("/common/scripted_effects/") {
+            Some(DefinitionKind::ScriptedEffect)
+        } else if path.contains("/events/") {
+            Some(DefinitionKind::Event)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub kind: DefinitionKind,
+    pub location: Location,
+}
+
+#[derive(Debug, Default)]
+pub struct Index {
+    definitions: HashMap<String, Vec<Definition>>,
+}
+
+impl Index {
+    /// Index the files of a workspace
+    pub fn scan(root: &Path) -> Index {
+        let mut index = Index::default();
+        for dir in &["common/scripted_triggers", "common/scripted_effects", "events"] {
+            index.scan_dir(&root.join(dir));
+        }
+        index
+    }
+
+    fn scan_dir(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path.is_dir() {
+                self.scan_dir(&path);
+            } else if path.extension().map(|e| e == "txt").unwrap_or(false) {
+                if let (Ok(buf), Ok(uri)) = (fs::read(&path), Url::from_file_path(&path)) {
+                    self.update(&uri, &decode(buf));
+                }
+            }
+        }
+    }
+
+    /// Replace the definitions of a file
+    pub fn update(&mut self, uri: &Url, text: &str) {
+        for definitions in self.definitions.values_mut() {
+            definitions.retain(|d| d.location.uri != *uri);
+        }
+        let kind = match DefinitionKind::of(uri) {
+            Some(kind) => kind,
+            None => return,
+        };
+
+        let tree = SyntaxTree::parse(text.as_bytes());
+        let positions = Positions::new(text);
+        for entry in tree.root().entries() {
+            let token = match kind {
+                DefinitionKind::ScriptedTrigger | DefinitionKind::ScriptedEffect => entry.key(),
+                DefinitionKind::Event => entry
+                    .value()
+                    .and_then(SyntaxElement::as_node)
+                    .and_then(|block| block.entries().find(|e| e.key().map(|k| tree.scalar(k) == "id").unwrap_or(false)))
+                    .and_then(|id| id.value())
+                    .and_then(SyntaxElement::as_token),
+            };
+            if let Some(token) = token {
+                self.definitions
+                    .entry(tree.scalar(token).to_string())
+                    .or_default()
+                    .push(Definition {
+                        kind,
+                        location: Location::new(uri.clone(), positions.range(token.span)),
+                    });
+            }
+        }
+    }
+
+    pub fn find(&self, name: &str) -> &[Definition] {
+        self.definitions.get(name).map(|d| &d[..]).unwrap_or(&[])
+    }
+}
+
+/// Decode a file, everything which isn't valid UTF-8 is treated as Windows-1252
+pub fn decode(buf: Vec<u8>) -> String {
+    match String::from_utf8(buf) {
+        Ok(s) => s,
+        Err(e) => WINDOWS_1252
+            .decode(e.as_bytes(), DecoderTrap::Replace)
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let mut index = Index::default();
+        let triggers = Url::parse("file:///mod/common/scripted_triggers/t.txt").unwrap();
+        let events = Url::parse("file:///mod/events/e.txt").unwrap();
+        let other = Url::parse("file:///mod/common/ideas/i.txt").unwrap();
+        index.update(&triggers, "is_rich = { treasury = 1000 }\n");
+        index.update(&events, "namespace = test\ncountry_event = {\n\tid = test.1\n}\n");
+        index.update(&other, "is_poor = { }\n");
+
+        assert_eq!(index.find("is_rich")[0].kind, DefinitionKind::ScriptedTrigger);
+        assert_eq!(index.find("test.1")[0].kind, DefinitionKind::Event);
+        assert_eq!(index.find("test.1")[0].location.range.start.line, 2);
+        assert!(index.find("is_poor").is_empty());
+
+        index.update(&triggers, "");
+        assert!(index.find("is_rich").is_empty());
+    }
+}
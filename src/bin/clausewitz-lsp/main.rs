@@ -0,0 +1,43 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+extern crate clausewitz_parser;
+extern crate encoding;
+extern crate lsp_server;
+extern crate lsp_types;
+extern crate serde_json;
+
+mod features;
+mod index;
+mod position;
+mod server;
+
+use lsp_server::Connection;
+use std::error::Error;
+use std::process::exit;
+
+pub type Result<T> = ::std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+fn main() {
+    let (connection, io_threads) = Connection::stdio();
+    let result = server::run(connection).and_then(|_| io_threads.join().map_err(|e| e.into()));
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        exit(2);
+    }
+}
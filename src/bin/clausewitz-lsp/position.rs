@@ -0,0 +1,88 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Mapping between byte offsets and LSP positions
+
+use clausewitz_parser::diagnostic::{self, LineIndex};
+use clausewitz_parser::Span;
+use lsp_types::{Position, Range};
+
+/// The positions of a document, LSP columns count UTF-16 code units
+pub struct Positions<'a> {
+    text: &'a str,
+    index: LineIndex,
+}
+
+impl<'a> Positions<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            index: LineIndex::new(text.as_bytes()),
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let position = self.index.position(offset);
+        let line = &self.text[offset - position.column..offset];
+        Position::new(position.line as u32, line.encode_utf16().count() as u32)
+    }
+
+    pub fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+
+    /// The byte offset of a position, clamped to the end of its line
+    pub fn offset(&self, position: Position) -> usize {
+        let start = diagnostic::Position {
+            line: position.line as usize,
+            column: 0,
+        };
+        let start = self.index.offset(start, self.text.len());
+        let mut units = 0;
+        for (offset, c) in self.text[start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return start + offset;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    /// The range of the whole document
+    pub fn full_range(&self) -> Range {
+        Range::new(Position::new(0, 0), self.position(self.text.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions() {
+        let text = "a = \"Ö𝄞\"\nb = 1";
+        let positions = Positions::new(text);
+        let b = text.find('b').unwrap();
+        assert_eq!(positions.position(b), Position::new(1, 0));
+        assert_eq!(positions.position(b - 2), Position::new(0, 8));
+        assert_eq!(positions.offset(Position::new(0, 8)), b - 2);
+        assert_eq!(positions.offset(Position::new(0, 20)), b - 1);
+        assert_eq!(positions.offset(Position::new(1, 4)), text.len() - 1);
+        assert_eq!(positions.full_range(), Range::new(Position::new(0, 0), Position::new(1, 5)));
+    }
+}
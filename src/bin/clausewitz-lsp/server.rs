@@ -0,0 +1,369 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The server loop
+
+use clausewitz_parser::cst::SyntaxTree;
+use features;
+use index::Index;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as LspNotification,
+    PublishDiagnostics,
+};
+use lsp_types::request::{DocumentSymbolRequest, FoldingRangeRequest, Formatting, GotoDefinition, Request as LspRequest};
+use lsp_types::{
+    DocumentSymbolResponse, FoldingRangeProviderCapability, GotoDefinitionResponse, InitializeParams, OneOf,
+    PublishDiagnosticsParams, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use position::Positions;
+use serde_json;
+use std::collections::HashMap;
+use Result;
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+/// Initialize the connection and serve requests until the client shuts the server down
+pub fn run(connection: Connection) -> Result<()> {
+    let params = connection.initialize(serde_json::to_value(capabilities())?)?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+    #[allow(deprecated)]
+    let root = params.root_uri.and_then(|uri| uri.to_file_path().ok());
+
+    let mut server = Server {
+        index: root.map(|root| Index::scan(&root)).unwrap_or_default(),
+        documents: HashMap::new(),
+        connection,
+    };
+    server.main_loop()
+}
+
+/// The params of a request, or the `InvalidParams` error to answer it with
+fn params<R: LspRequest>(request: Request) -> ::std::result::Result<R::Params, Response> {
+    let id = request.id.clone();
+    request
+        .extract(R::METHOD)
+        .map(|(_, params)| params)
+        .map_err(|e| Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()))
+}
+
+fn notification_params<N: LspNotification>(notification: Notification) -> Option<N::Params> {
+    match notification.extract(N::METHOD) {
+        Ok(params) => Some(params),
+        // there is no way to answer a notification, so a bad one is skipped
+        Err(e) => {
+            eprintln!("ignoring notification: {}", e);
+            None
+        }
+    }
+}
+
+struct Server {
+    connection: Connection,
+    /// The text of the open documents
+    documents: HashMap<Url, String>,
+    index: Index,
+}
+
+impl Server {
+    fn main_loop(&mut self) -> Result<()> {
+        let receiver = self.connection.receiver.clone();
+        for message in &receiver {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.request(request).unwrap_or_else(|response| response);
+                    self.connection.sender.send(response.into())?;
+                }
+                Message::Notification(notification) => self.notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer a request, `Err` holds the error response for invalid params
+    fn request(&self, request: Request) -> ::std::result::Result<Response, Response> {
+        let id = request.id.clone();
+        let response = match request.method.as_str() {
+            DocumentSymbolRequest::METHOD => {
+                let params = params::<DocumentSymbolRequest>(request)?;
+                let symbols = self.with_document(&params.text_document.uri, |tree, positions| {
+                    features::document_symbols(tree, positions)
+                });
+                Response::new_ok(id, symbols.map(DocumentSymbolResponse::Nested))
+            }
+            FoldingRangeRequest::METHOD => {
+                let params = params::<FoldingRangeRequest>(request)?;
+                let ranges = self.with_document(&params.text_document.uri, features::folding_ranges);
+                Response::new_ok(id, ranges)
+            }
+            Formatting::METHOD => {
+                let params = params::<Formatting>(request)?;
+                let edits = self.with_document(&params.text_document.uri, features::formatting);
+                Response::new_ok(id, edits)
+            }
+            GotoDefinition::METHOD => {
+                let params = params::<GotoDefinition>(request)?;
+                let position = params.text_document_position_params;
+                let locations = self.with_document(&position.text_document.uri, |tree, positions| {
+                    features::definition(tree, positions, &self.index, position.position)
+                });
+                Response::new_ok(id, locations.map(GotoDefinitionResponse::Array))
+            }
+            method => Response::new_err(
+                id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unknown method: {}", method),
+            ),
+        };
+        Ok(response)
+    }
+
+    /// Run a feature on an open document, `None` if the document isn't open
+    fn with_document<T, F>(&self, uri: &Url, f: F) -> Option<T>
+    where
+        F: FnOnce(&SyntaxTree, &Positions) -> T,
+    {
+        let text = self.documents.get(uri)?;
+        let tree = SyntaxTree::parse(text.as_bytes());
+        Some(f(&tree, &Positions::new(text)))
+    }
+
+    fn notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = match notification_params::<DidOpenTextDocument>(notification) {
+                    Some(params) => params,
+                    None => return Ok(()),
+                };
+                self.open(params.text_document.uri, params.text_document.text)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = match notification_params::<DidChangeTextDocument>(notification) {
+                    Some(params) => params,
+                    None => return Ok(()),
+                };
+                // the documents are synced in full, so the last change is the whole text
+                match params.content_changes.into_iter().last() {
+                    Some(change) => self.open(params.text_document.uri, change.text),
+                    None => Ok(()),
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = match notification_params::<DidCloseTextDocument>(notification) {
+                    Some(params) => params,
+                    None => return Ok(()),
+                };
+                self.documents.remove(&params.text_document.uri);
+                self.publish_diagnostics(params.text_document.uri, Vec::new())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Store the text of a document and publish its diagnostics
+    fn open(&mut self, uri: Url, text: String) -> Result<()> {
+        self.index.update(&uri, &text);
+        let diagnostics = {
+            let tree = SyntaxTree::parse(text.as_bytes());
+            features::diagnostics(&tree, &Positions::new(&text))
+        };
+        self.documents.insert(uri.clone(), text);
+        self.publish_diagnostics(uri, diagnostics)
+    }
+
+    fn publish_diagnostics(&self, uri: Url, diagnostics: Vec<lsp_types::Diagnostic>) -> Result<()> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.connection.sender.send(notification.into())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::RequestId;
+    use lsp_types::notification::{Exit, Initialized};
+    use lsp_types::request::{Initialize, Shutdown};
+    use lsp_types::{
+        DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentFormattingParams,
+        DocumentSymbolParams, FoldingRangeParams, FormattingOptions, GotoDefinitionParams, InitializedParams, Position,
+        TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams,
+        VersionedTextDocumentIdentifier,
+    };
+    use std::path::Path;
+    use std::thread;
+
+    /// A client talking to a server running in another thread
+    struct Client {
+        connection: Connection,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn send_request(&mut self, method: &str, params: serde_json::Value) -> Response {
+            self.next_id += 1;
+            let id = RequestId::from(self.next_id);
+            let request = Request::new(id.clone(), method.to_string(), params);
+            self.connection.sender.send(request.into()).unwrap();
+            match self.connection.receiver.recv().unwrap() {
+                Message::Response(response) if response.id == id => response,
+                message => panic!("unexpected message: {:?}", message),
+            }
+        }
+
+        fn request<R: LspRequest>(&mut self, params: R::Params) -> R::Result {
+            let response = self.send_request(R::METHOD, serde_json::to_value(params).unwrap());
+            assert!(response.error.is_none(), "{:?}", response.error);
+            serde_json::from_value(response.result.unwrap_or(serde_json::Value::Null)).unwrap()
+        }
+
+        fn notify<N: LspNotification>(&self, params: N::Params) {
+            let notification = Notification::new(N::METHOD.to_string(), params);
+            self.connection.sender.send(notification.into()).unwrap();
+        }
+
+        fn receive<N: LspNotification>(&self) -> N::Params {
+            match self.connection.receiver.recv().unwrap() {
+                Message::Notification(notification) => notification.extract(N::METHOD).unwrap(),
+                message => panic!("unexpected message: {:?}", message),
+            }
+        }
+    }
+
+    fn document(uri: &Url) -> TextDocumentIdentifier {
+        TextDocumentIdentifier::new(uri.clone())
+    }
+
+    #[test]
+    fn test_session() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/lsp");
+        let (server, connection) = Connection::memory();
+        let server = thread::spawn(move || run(server).map_err(|e| e.to_string()));
+        let mut client = Client { connection, next_id: 0 };
+
+        #[allow(deprecated)]
+        let params = InitializeParams {
+            root_uri: Some(Url::from_file_path(&root).unwrap()),
+            ..Default::default()
+        };
+        let result = client.request::<Initialize>(params);
+        assert_eq!(result.capabilities.definition_provider, Some(OneOf::Left(true)));
+        client.notify::<Initialized>(InitializedParams {});
+
+        // diagnostics
+        let uri = Url::from_file_path(root.join("events/test.txt")).unwrap();
+        let text = "namespace = test\ncountry_event = {\n\tid = test.1\n\ttrigger = { is_rich = yes }\n}\nfoo = }\n";
+        client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "clausewitz".to_string(), 1, text.to_string()),
+        });
+        let diagnostics = client.receive::<PublishDiagnostics>();
+        assert_eq!(diagnostics.uri, uri);
+        let messages: Vec<_> = diagnostics
+            .diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.message.as_str()))
+            .collect();
+        assert_eq!(messages, vec![(5, "`foo` has no value"), (5, "unexpected `}`")]);
+
+        // document symbols
+        let params = DocumentSymbolParams {
+            text_document: document(&uri),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let symbols = match client.request::<DocumentSymbolRequest>(params) {
+            Some(DocumentSymbolResponse::Nested(symbols)) => symbols,
+            response => panic!("unexpected response: {:?}", response),
+        };
+        let names: Vec<_> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["namespace", "country_event", "foo"]);
+
+        // folding ranges
+        let params = FoldingRangeParams {
+            text_document: document(&uri),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ranges = client.request::<FoldingRangeRequest>(params).unwrap();
+        assert_eq!((ranges[0].start_line, ranges[0].end_line), (1, 4));
+
+        // go to definition
+        let params = GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams::new(document(&uri), Position::new(3, 14)),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let locations = match client.request::<GotoDefinition>(params) {
+            Some(GotoDefinitionResponse::Array(locations)) => locations,
+            response => panic!("unexpected response: {:?}", response),
+        };
+        assert_eq!(locations.len(), 1);
+        assert!(locations[0].uri.path().ends_with("/common/scripted_triggers/triggers.txt"));
+        assert_eq!(locations[0].range.start, Position::new(0, 0));
+
+        // changes and formatting
+        client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "namespace=test\n".to_string(),
+            }],
+        });
+        assert!(client.receive::<PublishDiagnostics>().diagnostics.is_empty());
+        let params = DocumentFormattingParams {
+            text_document: document(&uri),
+            options: FormattingOptions::default(),
+            work_done_progress_params: Default::default(),
+        };
+        let edits = client.request::<Formatting>(params).unwrap();
+        assert_eq!(edits[0].new_text, "namespace = test\n");
+
+        client.notify::<DidCloseTextDocument>(DidCloseTextDocumentParams {
+            text_document: document(&uri),
+        });
+        assert!(client.receive::<PublishDiagnostics>().diagnostics.is_empty());
+
+        // bad messages are answered or skipped, the server keeps running
+        let notification = Notification::new(DidOpenTextDocument::METHOD.to_string(), serde_json::Value::Null);
+        client.connection.sender.send(notification.into()).unwrap();
+        let response = client.send_request(Formatting::METHOD, serde_json::Value::Null);
+        assert_eq!(response.error.unwrap().code, ErrorCode::InvalidParams as i32);
+
+        // unknown requests and shutdown
+        let response = client.send_request("custom/unknown", serde_json::Value::Null);
+        assert_eq!(response.error.unwrap().code, ErrorCode::MethodNotFound as i32);
+        client.request::<Shutdown>(());
+        client.notify::<Exit>(());
+        assert_eq!(server.join().unwrap(), Ok(()));
+    }
+}
@@ -0,0 +1,84 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The `convert` subcommand
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use clausewitz_parser::json::{to_json, to_json_reversible};
+use clausewitz_parser::Error;
+use input::parse_file;
+use output::write_output;
+use serde_json;
+use serde_yaml;
+use toml;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("convert")
+        .about("Converts a Clausewitz file to JSON, YAML or TOML")
+        .arg(
+            Arg::with_name("to")
+                .short("t")
+                .long("to")
+                .takes_value(true)
+                .possible_values(&["json", "yaml", "toml"])
+                .default_value("json")
+                .help("The output format"),
+        )
+        .arg(Arg::with_name("compact").long("compact").help("Write JSON without indentation"))
+        .arg(
+            Arg::with_name("reversible")
+                .long("reversible")
+                .help("Keep the quotes of quoted strings, so `clausewitz import` gives the same values back"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Write to this file instead of stdout"),
+        )
+        .arg(Arg::with_name("FILE").default_value("-").help("The input file, - for stdin"))
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    write_output(matches.value_of("output"), convert(matches)?.as_bytes())
+}
+
+/// Convert the input file to the output format
+pub fn convert(matches: &ArgMatches) -> Result<String, Error> {
+    let values = parse_file(matches.value_of("FILE").unwrap())?;
+    let json = if matches.is_present("reversible") {
+        to_json_reversible(&values)
+    } else {
+        to_json(&values)
+    };
+
+    let mut out = match matches.value_of("to").unwrap() {
+        "yaml" => serde_yaml::to_string(&json).map_err(|e| e.to_string())?,
+        // toml::Value writes plain values before tables, which TOML requires
+        "toml" => toml::Value::try_from(&json)
+            .and_then(|v| toml::to_string_pretty(&v))
+            .map_err(|e| e.to_string())?,
+        _ if matches.is_present("compact") => serde_json::to_string(&json)?,
+        _ => serde_json::to_string_pretty(&json)?,
+    };
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+    Ok(out)
+}
@@ -0,0 +1,66 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The `diff` subcommand
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use clausewitz_parser::diff::{diff_with, DiffOptions};
+use clausewitz_parser::Error;
+use input::parse_file;
+use serde_json::{self, Value};
+use std::process::exit;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("diff")
+        .about("Prints the added, removed and changed values between two files")
+        .after_help("Exits with 1 if the files differ, like diff.")
+        .arg(Arg::with_name("json").long("json").help("Print the changes as a JSON array"))
+        .arg(
+            Arg::with_name("id")
+                .long("id")
+                .value_name("KEY")
+                .help("Match the dicts in lists by the value of KEY instead of their position"),
+        )
+        .arg(Arg::with_name("OLD").required(true).help("The old file, - for stdin"))
+        .arg(Arg::with_name("NEW").required(true).help("The new file, - for stdin"))
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let old = parse_file(matches.value_of("OLD").unwrap())?;
+    let new = parse_file(matches.value_of("NEW").unwrap())?;
+
+    let mut options = DiffOptions::new();
+    if let Some(key) = matches.value_of("id") {
+        options = options.match_by(key);
+    }
+    let changes = diff_with(&old, &new, &options);
+
+    if matches.is_present("json") {
+        let json = Value::Array(changes.iter().map(|c| c.to_json()).collect());
+        println!("{}", serde_json::to_string_pretty(&json)?);
+    } else {
+        for change in &changes {
+            println!("{}", change);
+        }
+    }
+
+    if !changes.is_empty() {
+        exit(1);
+    }
+    Ok(())
+}
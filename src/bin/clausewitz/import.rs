@@ -0,0 +1,81 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The `import` subcommand
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use clausewitz_parser::json::from_json_reader;
+use clausewitz_parser::{Error, Writer};
+use input::read;
+use output::write_output;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("import")
+        .about("Converts JSON to a Clausewitz file")
+        .arg(Arg::with_name("bom").long("bom").help("Start the output with a UTF-8 BOM"))
+        .arg(
+            Arg::with_name("repeated-key")
+                .long("repeated-key")
+                .value_name("KEY")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Write lists under KEY as repeated keys (add_core=PRU add_core=BRA), can be given more than once"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Write to this file instead of stdout"),
+        )
+        .arg(Arg::with_name("FILE").default_value("-").help("The JSON file, - for stdin"))
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let buf = read(matches.value_of("FILE").unwrap())?;
+    write_output(matches.value_of("output"), &import(&buf, matches)?)
+}
+
+/// Convert a JSON document to a Clausewitz file
+fn import(buf: &[u8], matches: &ArgMatches) -> Result<Vec<u8>, Error> {
+    let values = from_json_reader(buf)?;
+    Writer::new()
+        .bom(matches.is_present("bom"))
+        .repeated_keys(matches.values_of("repeated-key").into_iter().flatten())
+        .write(&values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convert;
+    use std::path::Path;
+
+    #[test]
+    fn test_convert_and_import() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/import/country.txt");
+        let args = vec!["convert", "--reversible", file.to_str().unwrap()];
+        let json = convert::convert(&convert::subcommand().get_matches_from(args)).unwrap();
+        let args = vec!["import", "--repeated-key", "add_core"];
+        let out = import(json.as_bytes(), &subcommand().get_matches_from(args)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "capital=1\ncolor={ 23 50 150 }\nhistory={\n\tadd_core=PRU\n\tadd_core=BRA\n}\nname=\"Prussia\"\n"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Reading input files
+
+use clausewitz_parser::{parse, ClVal, Error};
+use encoding::all::WINDOWS_1252;
+use encoding::{DecoderTrap, Encoding};
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::stdin;
+
+/// Read a file, `-` reads stdin
+pub fn read(name: &str) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    let result = if name == "-" {
+        stdin().read_to_end(&mut buf)
+    } else {
+        File::open(name).and_then(|mut f| f.read_to_end(&mut buf))
+    };
+    result.map_err(|e| format!("{}: {}", name, e))?;
+    Ok(buf)
+}
+
+/// Decode a buffer to UTF-8
+///
+/// Save games and most game files are Windows-1252, everything which isn't valid UTF-8 is
+/// treated as such. The magic number of plain text saves (`EU4txt`, `CK2txt`, ...) is stripped
+/// if it is on a line of its own.
+pub fn decode(buf: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let buf = match String::from_utf8(buf) {
+        Ok(s) => s.into_bytes(),
+        Err(e) => WINDOWS_1252
+            .decode(e.as_bytes(), DecoderTrap::Strict)
+            .map_err(|e| e.to_string())?
+            .into_bytes(),
+    };
+    Ok(strip_magic(buf))
+}
+
+/// The magic numbers of plain text saves
+const MAGIC: &[&[u8]] = &[b"EU4txt", b"CK2txt", b"CK3txt", b"HOI4txt", b"STLtxt", b"IRtxt", b"VIC3txt"];
+
+/// Strip a magic number on a line of its own, a key like `mytxt = yes` stays
+fn strip_magic(mut buf: Vec<u8>) -> Vec<u8> {
+    let magic = MAGIC.iter().find(|magic| {
+        buf.starts_with(magic) && {
+            let rest = &buf[magic.len()..];
+            rest.is_empty() || rest.starts_with(b"\n") || rest.starts_with(b"\r\n")
+        }
+    });
+    if let Some(magic) = magic {
+        buf.drain(..magic.len());
+    }
+    buf
+}
+
+/// Read, decode and parse a file
+pub fn parse_file(name: &str) -> Result<ClVal, Error> {
+    let buf = decode(read(name)?)?;
+    parse(&buf).map_err(|e| format!("{}: {}", name, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"EU4txt\ndate=1597.1.1".to_vec()).unwrap(), b"\ndate=1597.1.1");
+        assert_eq!(decode(b"CK3txt\r\ndate=867.1.1".to_vec()).unwrap(), b"\r\ndate=867.1.1");
+        assert_eq!(decode(b"EU4txtfoo=bar".to_vec()).unwrap(), b"EU4txtfoo=bar");
+        assert_eq!(decode(b"EU4txt foo=bar".to_vec()).unwrap(), b"EU4txt foo=bar");
+        assert_eq!(decode(b"mytxt = yes".to_vec()).unwrap(), b"mytxt = yes");
+        assert_eq!(decode(b"mytxt\n= yes".to_vec()).unwrap(), b"mytxt\n= yes");
+        assert_eq!(decode(b"name=\"Svenska Arm\xe9n\"".to_vec()).unwrap(), "name=\"Svenska Armén\"".as_bytes());
+    }
+}
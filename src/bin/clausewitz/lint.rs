@@ -0,0 +1,90 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The `lint` subcommand
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use clausewitz_parser::cst::SyntaxTree;
+use clausewitz_parser::diagnostic::{LineIndex, Severity};
+use clausewitz_parser::lint::{apply_fixes, LintConfig, Linter};
+use clausewitz_parser::Error;
+use input::read;
+use output::write_output;
+use std::path::Path;
+use std::process::exit;
+
+/// The configuration used when there is no `--config`
+const DEFAULT_CONFIG: &str = "clausewitz-lint.txt";
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("lint")
+        .about("Checks script files for common mistakes")
+        .after_help("Exits with 1 if there are any errors.")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .help("The rule configuration, defaults to clausewitz-lint.txt if it exists"),
+        )
+        .arg(Arg::with_name("fix").long("fix").help("Fix the files in place where possible, not for stdin"))
+        .arg(
+            Arg::with_name("FILE")
+                .multiple(true)
+                .default_value("-")
+                .help("The files to lint, - for stdin"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let mut linter = Linter::new();
+    match matches.value_of("config") {
+        Some(config) => linter.configure(&LintConfig::load(config)?)?,
+        None if Path::new(DEFAULT_CONFIG).exists() => linter.configure(&LintConfig::load(DEFAULT_CONFIG)?)?,
+        None => (),
+    }
+
+    let fix = matches.is_present("fix");
+    if fix && matches.values_of("FILE").unwrap().any(|f| f == "-") {
+        return Err("--fix can't be used with stdin".into());
+    }
+
+    let mut errors = false;
+    for file in matches.values_of("FILE").unwrap() {
+        // The raw bytes, so fixes keep the encoding and the magic number of the file
+        let mut buf = read(file)?;
+        let mut tree = SyntaxTree::parse(&buf);
+        let mut lints = linter.lint(&tree);
+        if fix && lints.iter().any(|l| l.fix.is_some()) {
+            buf = apply_fixes(&tree, &lints);
+            write_output(Some(file), &buf)?;
+            tree = SyntaxTree::parse(&buf);
+            lints = linter.lint(&tree);
+        }
+
+        let index = LineIndex::new(&buf);
+        for lint in lints {
+            errors |= lint.diagnostic.severity == Severity::Error;
+            println!("{}", lint.diagnostic.to_string_in(file, &index));
+        }
+    }
+
+    if errors {
+        exit(1);
+    }
+    Ok(())
+}
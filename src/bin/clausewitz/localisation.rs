@@ -0,0 +1,73 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The `localisation` subcommand
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use clausewitz_parser::coverage::{Checker, REFERENCE_KEYS};
+use clausewitz_parser::diagnostic::{LineIndex, Severity};
+use clausewitz_parser::Error;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::exit;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("localisation")
+        .about("Checks the localisation of a mod for missing, extra and inconsistent keys")
+        .after_help("Exits with 1 if there are any errors.")
+        .arg(
+            Arg::with_name("base")
+                .long("base")
+                .value_name("LANGUAGE")
+                .default_value("english")
+                .help("The language the others are compared with"),
+        )
+        .arg(
+            Arg::with_name("keys")
+                .long("keys")
+                .value_name("KEY,...")
+                .use_delimiter(true)
+                .help("The keys of script files whose values are localisation keys [default: title,desc,text,tooltip,custom_tooltip]"),
+        )
+        .arg(Arg::with_name("DIR").default_value(".").help("The directory of the mod"))
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let keys: Vec<&str> = match matches.values_of("keys") {
+        Some(keys) => keys.collect(),
+        None => REFERENCE_KEYS.to_vec(),
+    };
+    let mut checker = Checker::new(matches.value_of("base").unwrap()).reference_keys(&keys);
+    checker.load(Path::new(matches.value_of("DIR").unwrap()))?;
+
+    let mut indexes = HashMap::new();
+    let mut errors = false;
+    for problem in checker.check() {
+        let index = indexes
+            .entry(problem.file.clone())
+            .or_insert_with(|| LineIndex::new(checker.source(&problem.file).unwrap_or_default()));
+        errors |= problem.diagnostic.severity == Severity::Error;
+        let file = problem.file.display().to_string();
+        println!("{}", problem.diagnostic.to_string_in(&file, index));
+    }
+
+    if errors {
+        exit(1);
+    }
+    Ok(())
+}
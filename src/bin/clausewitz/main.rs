@@ -0,0 +1,71 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+extern crate clausewitz_parser;
+#[macro_use]
+extern crate clap;
+extern crate encoding;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate toml;
+
+mod convert;
+mod diff;
+mod import;
+mod input;
+mod lint;
+mod localisation;
+mod merge;
+mod output;
+mod query;
+mod validate;
+
+use clap::{App, AppSettings};
+use std::process::exit;
+
+fn main() {
+    let matches = App::new("clausewitz")
+        .version(crate_version!())
+        .about("Tools for Clausewitz (Paradox) files")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(convert::subcommand())
+        .subcommand(diff::subcommand())
+        .subcommand(import::subcommand())
+        .subcommand(lint::subcommand())
+        .subcommand(localisation::subcommand())
+        .subcommand(merge::subcommand())
+        .subcommand(query::subcommand())
+        .subcommand(validate::subcommand())
+        .get_matches();
+
+    let result = match matches.subcommand() {
+        ("convert", Some(matches)) => convert::run(matches),
+        ("diff", Some(matches)) => diff::run(matches),
+        ("import", Some(matches)) => import::run(matches),
+        ("lint", Some(matches)) => lint::run(matches),
+        ("localisation", Some(matches)) => localisation::run(matches),
+        ("merge", Some(matches)) => merge::run(matches),
+        ("query", Some(matches)) => query::run(matches),
+        ("validate", Some(matches)) => validate::run(matches),
+        _ => unreachable!(),
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        exit(2);
+    }
+}
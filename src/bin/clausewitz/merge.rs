@@ -0,0 +1,119 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The `merge` subcommand
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use clausewitz_parser::diff::DiffOptions;
+use clausewitz_parser::merge::{merge_with, Merge};
+use clausewitz_parser::{Error, Writer};
+use input::parse_file;
+use output::write_output;
+use std::process::exit;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("merge")
+        .about("Merges the changes of two files to a common base file")
+        .after_help(
+            "Conflicts are printed to stderr and take the value from OURS, \
+             the command exits with 1 if there are any.",
+        )
+        .arg(
+            Arg::with_name("id")
+                .long("id")
+                .value_name("KEY")
+                .help("Match the dicts in lists by the value of KEY instead of their position"),
+        )
+        .arg(
+            Arg::with_name("repeated-key")
+                .long("repeated-key")
+                .value_name("KEY")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Write lists under KEY as repeated keys (add_core=PRU add_core=BRA), can be given more than once"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Write to this file instead of stdout"),
+        )
+        .arg(Arg::with_name("BASE").required(true).help("The common base file"))
+        .arg(Arg::with_name("OURS").required(true).help("The first changed file"))
+        .arg(Arg::with_name("THEIRS").required(true).help("The second changed file"))
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let (out, merge) = merge_files(matches)?;
+
+    write_output(matches.value_of("output"), &out)?;
+    for conflict in &merge.conflicts {
+        eprintln!("conflict: {}", conflict);
+    }
+    if !merge.is_clean() {
+        exit(1);
+    }
+    Ok(())
+}
+
+/// Merge the files, returns the written result and the merge
+fn merge_files(matches: &ArgMatches) -> Result<(Vec<u8>, Merge), Error> {
+    let base = parse_file(matches.value_of("BASE").unwrap())?;
+    let ours = parse_file(matches.value_of("OURS").unwrap())?;
+    let theirs = parse_file(matches.value_of("THEIRS").unwrap())?;
+
+    let mut options = DiffOptions::new();
+    if let Some(key) = matches.value_of("id") {
+        options = options.match_by(key);
+    }
+    let merge = merge_with(&base, &ours, &theirs, &options);
+
+    let writer = Writer::new().repeated_keys(matches.values_of("repeated-key").into_iter().flatten());
+    Ok((writer.write(&merge.merged)?, merge))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_merge_repeated_keys() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/merge");
+        let path = |name: &str| fixtures.join(name).to_str().unwrap().to_string();
+        let args = vec![
+            "merge".to_string(),
+            "--repeated-key".to_string(),
+            "add_core".to_string(),
+            "--repeated-key".to_string(),
+            "color".to_string(),
+            path("base.txt"),
+            path("ours.txt"),
+            path("theirs.txt"),
+        ];
+
+        let (out, merge) = merge_files(&subcommand().get_matches_from(args)).unwrap();
+        assert!(merge.is_clean());
+        // top level lists stay blocks even under a repeated key
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "c={\n\tadd_core=PRU\n\tadd_core=BRA\n\tadd_core=SWE\n}\ncolor={ 23 50 150 }\n"
+        );
+    }
+}
@@ -0,0 +1,33 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Writing output files
+
+use clausewitz_parser::Error;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::stdout;
+
+/// Write to a file or to stdout
+pub fn write_output(name: Option<&str>, buf: &[u8]) -> Result<(), Error> {
+    let result = match name {
+        Some(name) if name != "-" => File::create(name).and_then(|mut f| f.write_all(buf)),
+        _ => stdout().write_all(buf),
+    };
+    result.map_err(|e| e.to_string().into())
+}
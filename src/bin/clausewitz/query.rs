@@ -0,0 +1,65 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The `query` subcommand
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use clausewitz_parser::json::to_json;
+use clausewitz_parser::query::Query;
+use clausewitz_parser::Error;
+use input::parse_file;
+use serde_json;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("query")
+        .about("Prints the values matching a query as JSON, one per line")
+        .after_help("Example: clausewitz query 'provinces.*[owner=PRU].name' save.eu4")
+        .arg(Arg::with_name("pretty").long("pretty").help("Indent the JSON output"))
+        .arg(Arg::with_name("QUERY").required(true).help("The query, like countries.PRU.ledger.income"))
+        .arg(
+            Arg::with_name("FILE")
+                .multiple(true)
+                .default_value("-")
+                .help("The input files, - for stdin"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let query: Query = matches.value_of("QUERY").unwrap().parse()?;
+    let files: Vec<_> = matches.values_of("FILE").unwrap().collect();
+
+    for file in &files {
+        let values = parse_file(file)?;
+        for value in query.select(&values) {
+            let json = to_json(value);
+            let json = if matches.is_present("pretty") {
+                serde_json::to_string_pretty(&json)?
+            } else {
+                serde_json::to_string(&json)?
+            };
+            // like grep, prefix the file name if there is more than one
+            if files.len() > 1 {
+                println!("{}: {}", file, json);
+            } else {
+                println!("{}", json);
+            }
+        }
+    }
+
+    Ok(())
+}
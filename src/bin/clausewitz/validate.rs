@@ -0,0 +1,67 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The `validate` subcommand
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use clausewitz_parser::cst::SyntaxTree;
+use clausewitz_parser::diagnostic::{LineIndex, Severity};
+use clausewitz_parser::schema::Schema;
+use clausewitz_parser::Error;
+use input::{decode, read};
+use std::process::exit;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("validate")
+        .about("Validates script files against a schema")
+        .after_help("Syntax errors are printed before the schema diagnostics, exits with 1 if there are any errors.")
+        .arg(
+            Arg::with_name("schema")
+                .long("schema")
+                .value_name("PATH")
+                .required(true)
+                .help("The schema file, or a directory of .rules and CWTools .cwt files"),
+        )
+        .arg(
+            Arg::with_name("FILE")
+                .multiple(true)
+                .default_value("-")
+                .help("The files to validate, - for stdin"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let schema = Schema::load(matches.value_of("schema").unwrap())?;
+
+    let mut errors = false;
+    for file in matches.values_of("FILE").unwrap() {
+        let buf = decode(read(file)?)?;
+        let tree = SyntaxTree::parse(&buf);
+        let index = LineIndex::new(&buf);
+        // the schema is checked against the recovered tree, so the syntax errors come first
+        for diagnostic in tree.diagnostics().into_iter().chain(schema.validate_file(file, &tree)) {
+            errors |= diagnostic.severity == Severity::Error;
+            println!("{}", diagnostic.to_string_in(file, &index));
+        }
+    }
+
+    if errors {
+        exit(1);
+    }
+    Ok(())
+}
@@ -32,11 +32,15 @@
 //!
 
 use error::*;
+use parser::untyped_key;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::str::FromStr;
 use regex::Regex;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::ops::Index;
+use std::slice::{self, Iter};
 
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 /// Date type
@@ -116,6 +120,37 @@ impl ClKey {
             bail!(ErrorKind::InvalidValue("identifier".to_string()))
         }
     }
+
+    /// Returns `true` if the key matches `s`, no matter how the key was parsed
+    ///
+    /// `foo` matches the identifier `foo` and the string `"foo"`, `12` the integer `12`
+    /// and the string `"12"`, `1444.11.11` the date `1444.11.11`.
+    pub fn matches(&self, s: &str) -> bool {
+        match self {
+            ClKey::Identifier(k) | ClKey::String(k) => k == s,
+            ClKey::Integer(i) => s.parse::<i32>().map(|s| s == *i).unwrap_or(false),
+            ClKey::Date(d) => Date::from_str(s).map(|s| s == *d).unwrap_or(false),
+        }
+    }
+}
+
+impl Display for ClKey {
+    /// The text of the key, quoted strings are written without quotes
+    fn fmt(&self, f: &mut Formatter) -> ::std::fmt::Result {
+        match self {
+            ClKey::Integer(i) => write!(f, "{}", i),
+            ClKey::String(s) => write!(f, "{}", s),
+            ClKey::Date(d) => write!(f, "{}", d),
+            ClKey::Identifier(i) => write!(f, "{}", i),
+        }
+    }
+}
+
+impl From<&str> for ClKey {
+    /// Type a key the way the parser types unquoted keys: integer, date or identifier
+    fn from(key: &str) -> Self {
+        untyped_key(key.as_bytes())
+    }
 }
 
 impl Into<ClVal> for ClKey {
@@ -199,6 +234,14 @@ impl ClVal {
         }
     }
 
+    pub fn as_dict_mut(&mut self) -> Result<&mut HashMap<ClKey, ClVal>> {
+        if let ClVal::Dict(ref mut dict) = self {
+            Ok(dict)
+        } else {
+            bail!(ErrorKind::InvalidValue("dict".to_string()))
+        }
+    }
+
     pub fn as_identifier(&self) -> Result<&str> {
         if let ClVal::Identifier(ref string) = self {
             Ok(string)
@@ -214,6 +257,309 @@ impl ClVal {
             bail!(ErrorKind::InvalidValue("identifier".to_string()))
         }
     }
+
+    /// Coerce the value into an integer
+    ///
+    /// Accepts integers, floats without a fractional part and strings or identifiers holding an integer.
+    pub fn to_i64(&self) -> Option<i64> {
+        match self {
+            ClVal::Integer(i) => Some(i64::from(*i)),
+            ClVal::Float(f) if f.fract() == 0.0 && f.is_finite() => Some(*f as i64),
+            ClVal::String(s) | ClVal::Identifier(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Coerce the value into a float
+    ///
+    /// Accepts integers, floats and strings or identifiers holding a number.
+    pub fn to_f64(&self) -> Option<f64> {
+        match self {
+            ClVal::Integer(i) => Some(f64::from(*i)),
+            // go through the shortest decimal representation, otherwise 12.34 becomes 12.34000015258789
+            ClVal::Float(f) => Some(f.to_string().parse().unwrap_or_else(|_| f64::from(*f))),
+            ClVal::String(s) | ClVal::Identifier(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The text of a string or an identifier
+    pub fn to_str(&self) -> Option<&str> {
+        match self {
+            ClVal::String(s) | ClVal::Identifier(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Coerce the value into a bool
+    ///
+    /// Accepts bools, `yes` / `no` as string or identifier and `1` / `0` as integer or string.
+    pub fn to_bool(&self) -> Option<bool> {
+        match self {
+            ClVal::Bool(b) => Some(*b),
+            ClVal::Integer(1) => Some(true),
+            ClVal::Integer(0) => Some(false),
+            ClVal::String(s) | ClVal::Identifier(s) => match s.as_str() {
+                "yes" | "1" => Some(true),
+                "no" | "0" => Some(false),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Iterate over the items of a `List`, or over the value itself if it isn't one
+    ///
+    /// A key which occurs once is parsed into a single value, more occurrences make it a `List`.
+    /// This iterates over both the same way.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate clausewitz_parser;
+    ///
+    /// use clausewitz_parser::parse;
+    ///
+    /// fn main() {
+    ///     let values = parse(b"PRU={ add_core=PRU } FRA={ add_core=FRA add_core=NAV }").unwrap();
+    ///
+    ///     assert_eq!(values["PRU"]["add_core"].as_list_or_single().count(), 1);
+    ///     assert_eq!(values["FRA"]["add_core"].as_list_or_single().count(), 2);
+    /// }
+    /// ```
+    pub fn as_list_or_single(&self) -> Iter<'_, ClVal> {
+        match self {
+            ClVal::List(list) => list.iter(),
+            _ => slice::from_ref(self).iter(),
+        }
+    }
+
+    /// Look up a key of a `Dict`
+    ///
+    /// The key matches no matter how it was parsed, see [`ClKey::matches`](enum.ClKey.html#method.matches).
+    /// Returns `None` if the value isn't a `Dict` or the key doesn't exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate clausewitz_parser;
+    ///
+    /// use clausewitz_parser::{parse, ClVal};
+    ///
+    /// fn main() {
+    ///     let values = parse(b"player=\"PRU\" \"12\"=yes savegame_version={ first=1 }").unwrap();
+    ///
+    ///     assert_eq!(values.get("player"), Some(&ClVal::String("PRU".to_string())));
+    ///     assert_eq!(values.get("12"), Some(&ClVal::Bool(true)));
+    ///     assert_eq!(values.get_path(&["savegame_version", "first"]), Some(&ClVal::Integer(1)));
+    ///     assert_eq!(values["savegame_version"]["first"], ClVal::Integer(1));
+    /// }
+    /// ```
+    pub fn get(&self, key: &str) -> Option<&ClVal> {
+        let key = self.find_key(key)?;
+        match self {
+            ClVal::Dict(dict) => dict.get(&key),
+            _ => None,
+        }
+    }
+
+    /// Look up a path of keys through nested `Dict`s
+    pub fn get_path(&self, path: &[&str]) -> Option<&ClVal> {
+        path.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    /// Get an item of a `List`
+    ///
+    /// Returns `None` if the value isn't a `List` or the index is out of bounds.
+    pub fn get_index(&self, index: usize) -> Option<&ClVal> {
+        match self {
+            ClVal::List(list) => list.get(index),
+            _ => None,
+        }
+    }
+}
+
+impl ClVal {
+    /// The existing key of a `Dict` matching `key`
+    ///
+    /// An identifier is tried first, then a string, an integer and a date.
+    fn find_key(&self, key: &str) -> Option<ClKey> {
+        let dict = match self {
+            ClVal::Dict(dict) => dict,
+            _ => return None,
+        };
+        let candidates = [
+            Some(ClKey::Identifier(key.to_string())),
+            Some(ClKey::String(key.to_string())),
+            key.parse().ok().map(ClKey::Integer),
+            Date::from_str(key).ok().map(ClKey::Date),
+        ];
+        candidates.iter().flatten().find(|k| dict.contains_key(k)).cloned()
+    }
+
+    /// Look up a key of a `Dict` for modification
+    ///
+    /// Like [`get`](#method.get), but returns a mutable reference.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut ClVal> {
+        let key = self.find_key(key)?;
+        match self {
+            ClVal::Dict(dict) => dict.get_mut(&key),
+            _ => None,
+        }
+    }
+
+    /// Look up a path of keys through nested `Dict`s for modification
+    pub fn get_path_mut(&mut self, path: &[&str]) -> Option<&mut ClVal> {
+        path.iter().try_fold(self, |value, key| value.get_mut(key))
+    }
+
+    /// The entry of `key` in a `Dict` for in-place manipulation
+    ///
+    /// An existing key matching `key` is used, otherwise a new key is typed the way the parser
+    /// would (see [`ClKey::from`](enum.ClKey.html)). Returns `None` if the value isn't a `Dict`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate clausewitz_parser;
+    ///
+    /// use clausewitz_parser::{parse, ClVal};
+    ///
+    /// fn main() {
+    ///     let mut values = parse(b"treasury=10").unwrap();
+    ///     *values.entry("treasury").unwrap().or_insert(ClVal::Integer(0)) = ClVal::Integer(20);
+    ///     values.entry("manpower").unwrap().or_insert(ClVal::Integer(5));
+    ///
+    ///     assert_eq!(values["treasury"], ClVal::Integer(20));
+    ///     assert_eq!(values["manpower"], ClVal::Integer(5));
+    /// }
+    /// ```
+    pub fn entry(&mut self, key: &str) -> Option<Entry<'_, ClKey, ClVal>> {
+        let key = self.find_key(key).unwrap_or_else(|| ClKey::from(key));
+        match self {
+            ClVal::Dict(dict) => Some(dict.entry(key)),
+            _ => None,
+        }
+    }
+
+    /// Set the value at a path of keys, creating missing `Dict`s on the way
+    ///
+    /// Returns the previous value, fails if the value or one of the values on the path isn't a `Dict`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate clausewitz_parser;
+    ///
+    /// use clausewitz_parser::{parse, ClVal};
+    ///
+    /// fn main() {
+    ///     let mut values = parse(b"countries={ PRU={ treasury=10 } }").unwrap();
+    ///     values.insert_path(&["countries", "PRU", "treasury"], ClVal::Integer(20)).unwrap();
+    ///     values.insert_path(&["countries", "FRA", "treasury"], ClVal::Integer(30)).unwrap();
+    ///
+    ///     assert_eq!(values.get_path(&["countries", "PRU", "treasury"]), Some(&ClVal::Integer(20)));
+    ///     assert_eq!(values.get_path(&["countries", "FRA", "treasury"]), Some(&ClVal::Integer(30)));
+    /// }
+    /// ```
+    pub fn insert_path(&mut self, path: &[&str], value: ClVal) -> Result<Option<ClVal>> {
+        let (last, parents) = match path.split_last() {
+            Some(split) => split,
+            None => bail!(ErrorKind::PathNotFound(String::new())),
+        };
+        let mut current = self;
+        for key in parents {
+            let entry = current
+                .entry(key)
+                .ok_or_else(|| ErrorKind::InvalidValue("dict".to_string()))?;
+            current = entry.or_insert_with(|| ClVal::Dict(HashMap::new()));
+        }
+        let key = current.find_key(last).unwrap_or_else(|| ClKey::from(*last));
+        Ok(current.as_dict_mut()?.insert(key, value))
+    }
+
+    /// Remove the value at a path of keys
+    ///
+    /// Returns the removed value, or `None` if there was nothing to remove.
+    pub fn remove_path(&mut self, path: &[&str]) -> Option<ClVal> {
+        let (last, parents) = path.split_last()?;
+        let parent = self.get_path_mut(parents)?;
+        let key = parent.find_key(last)?;
+        parent.as_dict_mut().ok()?.remove(&key)
+    }
+
+    /// Insert a value into a `Dict`, turning repeated keys into a list
+    ///
+    /// This works exactly like the parser handles repeated keys: the first value is stored as is,
+    /// the second one turns it into a `List` and every further value is pushed to that list.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate clausewitz_parser;
+    ///
+    /// use clausewitz_parser::{parse, ClKey, ClVal};
+    ///
+    /// fn main() {
+    ///     let mut values = parse(b"add_core=PRU").unwrap();
+    ///     values.insert_or_append(ClKey::from("add_core"), ClVal::Identifier("BRA".to_string())).unwrap();
+    ///
+    ///     assert_eq!(values, parse(b"PRU={ add_core=PRU add_core=BRA }").unwrap()["PRU"]);
+    /// }
+    /// ```
+    pub fn insert_or_append(&mut self, key: ClKey, value: ClVal) -> Result<()> {
+        insert_or_append(self.as_dict_mut()?, key, value);
+        Ok(())
+    }
+}
+
+/// Insert a value into a dict, turning repeated keys into a list
+pub(crate) fn insert_or_append(dict: &mut HashMap<ClKey, ClVal>, key: ClKey, value: ClVal) {
+    match dict.entry(key) {
+        Entry::Occupied(mut entry) => {
+            debug!("[insert_or_append] key already exists");
+            if let ClVal::List(ref mut list) = entry.get_mut() {
+                // append to the existing list
+                debug!("[insert_or_append] pushing to list");
+                list.push(value);
+                return;
+            }
+            // create a new list from the stored value
+            debug!("[insert_or_append] converting to a list");
+            let first = entry.insert(ClVal::List(Vec::new()));
+            *entry.get_mut() = ClVal::List(vec![first, value]);
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(value);
+        }
+    }
+}
+
+impl Index<&str> for ClVal {
+    type Output = ClVal;
+
+    /// Look up a key of a `Dict`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value isn't a `Dict` or the key doesn't exist.
+    fn index(&self, key: &str) -> &ClVal {
+        self.get(key).unwrap_or_else(|| panic!("no entry found for key: {}", key))
+    }
+}
+
+impl Index<usize> for ClVal {
+    type Output = ClVal;
+
+    /// Get an item of a `List`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value isn't a `List` or the index is out of bounds.
+    fn index(&self, index: usize) -> &ClVal {
+        self.get_index(index)
+            .unwrap_or_else(|| panic!("no item found at index: {}", index))
+    }
 }
 
 #[cfg(test)]
@@ -331,6 +677,202 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_i64() {
+        assert_eq!(ClVal::Integer(42).to_i64(), Some(42));
+        assert_eq!(ClVal::Float(5.0).to_i64(), Some(5));
+        assert_eq!(ClVal::Float(5.5).to_i64(), None);
+        assert_eq!(ClVal::String("-12".to_string()).to_i64(), Some(-12));
+        assert_eq!(ClVal::Identifier("foo".to_string()).to_i64(), None);
+        assert_eq!(ClVal::Bool(true).to_i64(), None);
+    }
+
+    #[test]
+    fn test_to_f64() {
+        assert_eq!(ClVal::Integer(42).to_f64(), Some(42.0));
+        assert_eq!(ClVal::Float(12.34).to_f64(), Some(12.34));
+        assert_eq!(ClVal::String("120.500".to_string()).to_f64(), Some(120.5));
+        assert_eq!(ClVal::Date(Date::new(1444, 11, 11)).to_f64(), None);
+    }
+
+    #[test]
+    fn test_to_str() {
+        assert_eq!(ClVal::String("foo".to_string()).to_str(), Some("foo"));
+        assert_eq!(ClVal::Identifier("foo".to_string()).to_str(), Some("foo"));
+        assert_eq!(ClVal::Integer(1).to_str(), None);
+    }
+
+    #[test]
+    fn test_to_bool() {
+        assert_eq!(ClVal::Bool(false).to_bool(), Some(false));
+        assert_eq!(ClVal::Integer(1).to_bool(), Some(true));
+        assert_eq!(ClVal::Integer(0).to_bool(), Some(false));
+        assert_eq!(ClVal::Integer(2).to_bool(), None);
+        assert_eq!(ClVal::String("yes".to_string()).to_bool(), Some(true));
+        assert_eq!(ClVal::Identifier("no".to_string()).to_bool(), Some(false));
+        assert_eq!(ClVal::String("0".to_string()).to_bool(), Some(false));
+        assert_eq!(ClVal::Identifier("yess".to_string()).to_bool(), None);
+    }
+
+    #[test]
+    fn test_as_list_or_single() {
+        let val = ClVal::Integer(1);
+        assert_eq!(val.as_list_or_single().collect::<Vec<_>>(), vec![&ClVal::Integer(1)]);
+        let val = ClVal::List(vec![ClVal::Integer(1), ClVal::Integer(2)]);
+        assert_eq!(
+            val.as_list_or_single().collect::<Vec<_>>(),
+            vec![&ClVal::Integer(1), &ClVal::Integer(2)]
+        );
+        let val = ClVal::List(Vec::new());
+        assert_eq!(val.as_list_or_single().count(), 0);
+    }
+
+    #[test]
+    fn test_key_display() {
+        assert_eq!(ClKey::Integer(-1).to_string(), "-1");
+        assert_eq!(ClKey::String("a b".to_string()).to_string(), "a b");
+        assert_eq!(ClKey::Date(Date::new(1444, 11, 11)).to_string(), "1444.11.11");
+        assert_eq!(ClKey::Identifier("PRU".to_string()).to_string(), "PRU");
+    }
+
+    #[test]
+    fn test_key_from_str() {
+        assert_eq!(ClKey::from("12"), ClKey::Integer(12));
+        assert_eq!(ClKey::from("1444.11.11"), ClKey::Date(Date::new(1444, 11, 11)));
+        assert_eq!(ClKey::from("foo"), ClKey::Identifier("foo".to_string()));
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut values = lookup_values();
+        *values.get_mut("bar").unwrap() = ClVal::Bool(true);
+        *values.get_path_mut(&["foo", "12"]).unwrap() = ClVal::Integer(42);
+        assert_eq!(values["bar"], ClVal::Bool(true));
+        assert_eq!(values["foo"]["12"], ClVal::Integer(42));
+        assert!(values.get_mut("baz").is_none());
+        assert!(values["foo"].clone().get_path_mut(&["12", "x"]).is_none());
+
+        // the same key as `get`, an identifier before a string of the same name
+        for _ in 0..16 {
+            let mut values = ::parse(b"foo = 1 \"foo\" = 2").unwrap();
+            assert_eq!(values.get("foo"), Some(&ClVal::Integer(1)));
+            assert_eq!(values.get_mut("foo"), Some(&mut ClVal::Integer(1)));
+            assert_eq!(values.entry("foo").unwrap().or_insert(ClVal::Integer(0)), &ClVal::Integer(1));
+            assert_eq!(values.remove_path(&["foo"]), Some(ClVal::Integer(1)));
+            assert_eq!(values.get("foo"), Some(&ClVal::Integer(2)));
+        }
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut values = lookup_values();
+        values.entry("bar").unwrap().or_insert(ClVal::Integer(0));
+        values.entry("13").unwrap().or_insert(ClVal::Integer(0));
+        assert_eq!(values["bar"], ClVal::List(vec![ClVal::Integer(3)]));
+        assert_eq!(values.as_dict().unwrap()[&ClKey::Integer(13)], ClVal::Integer(0));
+        assert!(ClVal::Integer(1).entry("foo").is_none());
+    }
+
+    #[test]
+    fn test_insert_path() {
+        let mut values = lookup_values();
+        assert_eq!(
+            values.insert_path(&["foo", "12"], ClVal::Integer(5)).unwrap(),
+            Some(ClVal::Integer(1))
+        );
+        assert_eq!(values.insert_path(&["a", "b", "c"], ClVal::Integer(6)).unwrap(), None);
+        assert_eq!(values["foo"]["12"], ClVal::Integer(5));
+        assert_eq!(values["a"]["b"]["c"], ClVal::Integer(6));
+        assert_eq!(
+            values.insert_path(&["bar", "c"], ClVal::Integer(6)).unwrap_err().to_string(),
+            "invalid value type: dict"
+        );
+        assert!(values.insert_path(&[], ClVal::Integer(6)).is_err());
+    }
+
+    #[test]
+    fn test_remove_path() {
+        let mut values = lookup_values();
+        assert_eq!(values.remove_path(&["foo", "12"]), Some(ClVal::Integer(1)));
+        assert_eq!(values.remove_path(&["foo", "12"]), None);
+        assert_eq!(values.remove_path(&["bar", "0"]), None);
+        assert_eq!(values.remove_path(&["bar"]), Some(ClVal::List(vec![ClVal::Integer(3)])));
+        assert_eq!(values.remove_path(&[]), None);
+    }
+
+    #[test]
+    fn test_insert_or_append() {
+        let mut values = ClVal::Dict(HashMap::new());
+        let key = ClKey::from("add_core");
+        values.insert_or_append(key.clone(), ClVal::Identifier("PRU".to_string())).unwrap();
+        assert_eq!(values["add_core"], ClVal::Identifier("PRU".to_string()));
+        values.insert_or_append(key.clone(), ClVal::Identifier("BRA".to_string())).unwrap();
+        values.insert_or_append(key.clone(), ClVal::Identifier("POM".to_string())).unwrap();
+        assert_eq!(
+            values["add_core"],
+            ClVal::List(vec![
+                ClVal::Identifier("PRU".to_string()),
+                ClVal::Identifier("BRA".to_string()),
+                ClVal::Identifier("POM".to_string()),
+            ])
+        );
+        assert!(ClVal::Integer(1).insert_or_append(key, ClVal::Integer(1)).is_err());
+    }
+
+    #[test]
+    fn test_key_matches() {
+        assert!(ClKey::Identifier("foo".to_string()).matches("foo"));
+        assert!(ClKey::String("foo".to_string()).matches("foo"));
+        assert!(ClKey::String("12".to_string()).matches("12"));
+        assert!(ClKey::Integer(12).matches("12"));
+        assert!(ClKey::Date(Date::new(1444, 11, 11)).matches("1444.11.11"));
+        assert!(!ClKey::Integer(12).matches("foo"));
+        assert!(!ClKey::Identifier("foo".to_string()).matches("bar"));
+    }
+
+    fn lookup_values() -> ClVal {
+        let mut inner = HashMap::new();
+        inner.insert(ClKey::Integer(12), ClVal::Integer(1));
+        inner.insert(ClKey::Date(Date::new(1444, 11, 11)), ClVal::Integer(2));
+        let mut dict = HashMap::new();
+        dict.insert(ClKey::Identifier("foo".to_string()), ClVal::Dict(inner));
+        dict.insert(ClKey::String("bar".to_string()), ClVal::List(vec![ClVal::Integer(3)]));
+        ClVal::Dict(dict)
+    }
+
+    #[test]
+    fn test_get() {
+        let values = lookup_values();
+        assert_eq!(values.get("bar"), Some(&ClVal::List(vec![ClVal::Integer(3)])));
+        assert_eq!(values.get_path(&["foo", "12"]), Some(&ClVal::Integer(1)));
+        assert_eq!(values.get_path(&["foo", "1444.11.11"]), Some(&ClVal::Integer(2)));
+        assert_eq!(values.get_path(&["foo", "13"]), None);
+        assert_eq!(values.get_path(&["bar", "foo"]), None);
+        assert_eq!(values.get_path(&[]), Some(&values));
+        assert_eq!(values["bar"].get_index(0), Some(&ClVal::Integer(3)));
+        assert_eq!(values["bar"].get_index(1), None);
+        assert_eq!(values["foo"].get_index(0), None);
+    }
+
+    #[test]
+    fn test_index() {
+        let values = lookup_values();
+        assert_eq!(values["foo"]["12"], ClVal::Integer(1));
+        assert_eq!(values["bar"][0], ClVal::Integer(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "no entry found for key: baz")]
+    fn test_index_missing() {
+        let _ = &lookup_values()["baz"];
+    }
+
+    #[test]
+    #[should_panic(expected = "no item found at index: 1")]
+    fn test_index_out_of_bounds() {
+        let _ = &lookup_values()["bar"][1];
+    }
+
     #[test]
     fn test_parse_date() {
         let s = "2018.5.16";
@@ -0,0 +1,349 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Localisation coverage and consistency checks
+//!
+//! A [**Checker**](struct.Checker.html) compares the localisation of every language with the
+//! one of a base language, usually `english`, and reports:
+//!
+//! | code | |
+//! |---|---|
+//! | `missing-key` | a key of the base language is missing in another language |
+//! | `extra-key` | a key is not in the base language |
+//! | `duplicate-key` | a key is defined in more than one file of a language |
+//! | `placeholder-mismatch` | the `$VARIABLES$` of a text differ from the base language |
+//! | `missing-localisation` | a key used in a script file, like `title = my_event.1.t`, has no localisation |
+//!
+//! Problems found while parsing the files are reported as well.
+
+use cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxTree};
+use diagnostic::{Diagnostic, Problem};
+use error::Error;
+use localisation::{Entry, Localisation};
+use markup::{self, Node};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use token::Span;
+use walk::files;
+
+/// The keys of script files whose values are localisation keys by default
+pub const REFERENCE_KEYS: &[&str] = &["title", "desc", "text", "tooltip", "custom_tooltip"];
+
+/// Checks the localisation of a mod
+pub struct Checker {
+    base: String,
+    reference_keys: Vec<String>,
+    sources: BTreeMap<PathBuf, Vec<u8>>,
+    localisations: Vec<(PathBuf, Localisation)>,
+    /// Localisation keys used in script files
+    references: Vec<(PathBuf, String, Span)>,
+    problems: Vec<Problem>,
+}
+
+impl Checker {
+    /// A checker comparing every language with `base`, like `english`
+    pub fn new(base: &str) -> Self {
+        Checker {
+            base: base.to_string(),
+            reference_keys: REFERENCE_KEYS.iter().map(|k| k.to_string()).collect(),
+            sources: BTreeMap::new(),
+            localisations: Vec::new(),
+            references: Vec::new(),
+            problems: Vec::new(),
+        }
+    }
+
+    /// Set the keys of script files whose values are localisation keys
+    pub fn reference_keys(mut self, keys: &[&str]) -> Self {
+        self.reference_keys = keys.iter().map(|k| k.to_string()).collect();
+        self
+    }
+
+    /// Add the `.yml` files in `localisation` (or `localization`) and the `.txt` script files of a mod
+    pub fn load(&mut self, dir: &Path) -> Result<(), Error> {
+        let localisation_dirs = [dir.join("localisation"), dir.join("localization")];
+        for localisation_dir in &localisation_dirs {
+            for path in files(localisation_dir, &["yml"], &[])? {
+                let buf = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                self.add_localisation(&path, buf);
+            }
+        }
+        for path in files(dir, &["txt"], &localisation_dirs)? {
+            let buf = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            self.add_script(&path, buf);
+        }
+        Ok(())
+    }
+
+    /// Add a localisation file
+    pub fn add_localisation(&mut self, path: &Path, buf: Vec<u8>) {
+        let (localisation, diagnostics) = Localisation::parse(&buf);
+        self.report(path, diagnostics);
+        self.localisations.push((path.to_path_buf(), localisation));
+        self.sources.insert(path.to_path_buf(), buf);
+    }
+
+    /// Add a script file, everything which isn't valid UTF-8 is replaced
+    pub fn add_script(&mut self, path: &Path, buf: Vec<u8>) {
+        let buf = String::from_utf8_lossy(&buf).into_owned().into_bytes();
+        let tree = SyntaxTree::parse(&buf);
+        let mut references = Vec::new();
+        self.find_references(&tree, tree.root(), &mut references);
+        self.references
+            .extend(references.into_iter().map(|(key, span)| (path.to_path_buf(), key, span)));
+        self.sources.insert(path.to_path_buf(), buf);
+    }
+
+    fn find_references(&self, tree: &SyntaxTree, node: &SyntaxNode, references: &mut Vec<(String, Span)>) {
+        for child in node.children().iter().filter_map(SyntaxElement::as_node) {
+            if let (Some(key), Some(SyntaxElement::Token(value))) = (child.key(), child.value()) {
+                let text = tree.scalar(value);
+                if self.reference_keys.iter().any(|k| *k == tree.scalar(key)) && is_localisation_key(value.kind, &text) {
+                    references.push((text.to_string(), value.span));
+                }
+            }
+            self.find_references(tree, child, references);
+        }
+    }
+
+    /// The source of a file, for [**LineIndexes**](../diagnostic/struct.LineIndex.html)
+    pub fn source(&self, path: &Path) -> Option<&[u8]> {
+        self.sources.get(path).map(Vec::as_slice)
+    }
+
+    fn report(&mut self, path: &Path, diagnostics: Vec<Diagnostic>) {
+        self.problems.extend(diagnostics.into_iter().map(|diagnostic| Problem {
+            file: path.to_path_buf(),
+            diagnostic,
+        }));
+    }
+
+    /// All problems, sorted by file and position
+    pub fn check(&self) -> Vec<Problem> {
+        let mut checked = Checked {
+            problems: self.problems.clone(),
+        };
+
+        // the first definition of every key by language
+        let mut languages: BTreeMap<&str, HashMap<&str, (&Path, &Entry)>> = BTreeMap::new();
+        let mut order: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (path, localisation) in &self.localisations {
+            for language in &localisation.languages {
+                let keys = languages.entry(&language.name).or_default();
+                let keys_order = order.entry(&language.name).or_default();
+                for entry in language.entries() {
+                    match keys.get(entry.key.as_str()) {
+                        // duplicates in the same file are reported by the parser
+                        Some((first, _)) if first != path => {
+                            let message = format!("`{}` is already defined in {}", entry.key, first.display());
+                            checked.push(path, Diagnostic::warning("duplicate-key", entry.span, message));
+                        }
+                        Some(_) => {}
+                        None => {
+                            keys.insert(&entry.key, (path, entry));
+                            keys_order.push(&entry.key);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(base) = languages.get(self.base.as_str()) {
+            for (name, keys) in languages.iter().filter(|(name, _)| **name != self.base) {
+                for key in &order[self.base.as_str()] {
+                    let (path, entry) = base[key];
+                    match keys.get(key) {
+                        None => {
+                            let message = format!("`{}` is missing in {}", key, name);
+                            checked.push(path, Diagnostic::error("missing-key", entry.span, message));
+                        }
+                        Some((other_path, other)) => {
+                            let expected = placeholders(&entry.text);
+                            let found = placeholders(&other.text);
+                            if expected != found {
+                                let message = format!(
+                                    "`{}` has {}, but {} has {}",
+                                    key,
+                                    list(&found),
+                                    self.base,
+                                    list(&expected)
+                                );
+                                checked.push(other_path, Diagnostic::error("placeholder-mismatch", other.span, message));
+                            }
+                        }
+                    }
+                }
+                for key in order[name].iter().filter(|key| !base.contains_key(*key)) {
+                    let (path, entry) = keys[key];
+                    let message = format!("`{}` is not in {}", key, self.base);
+                    checked.push(path, Diagnostic::warning("extra-key", entry.span, message));
+                }
+            }
+        }
+
+        let empty = HashMap::new();
+        let base = languages.get(self.base.as_str()).unwrap_or(&empty);
+        for (path, key, span) in &self.references {
+            if !base.contains_key(key.as_str()) {
+                let message = format!("`{}` has no {} localisation", key, self.base);
+                checked.push(path, Diagnostic::error("missing-localisation", *span, message));
+            }
+        }
+
+        let mut problems = checked.problems;
+        problems.sort_by(|a, b| (&a.file, a.diagnostic.span.start).cmp(&(&b.file, b.diagnostic.span.start)));
+        problems
+    }
+}
+
+struct Checked {
+    problems: Vec<Problem>,
+}
+
+impl Checked {
+    fn push(&mut self, path: &Path, diagnostic: Diagnostic) {
+        self.problems.push(Problem {
+            file: path.to_path_buf(),
+            diagnostic,
+        });
+    }
+}
+
+/// Whether the value of a reference key looks like a localisation key
+fn is_localisation_key(kind: SyntaxKind, text: &str) -> bool {
+    (kind == SyntaxKind::Untyped || kind == SyntaxKind::Quoted)
+        && !text.is_empty()
+        && text != "yes"
+        && text != "no"
+        && text.parse::<f64>().is_err()
+        && !text.contains(|c: char| c.is_whitespace() || c == '$' || c == '[')
+}
+
+/// The names of the variables of a text
+fn placeholders(text: &str) -> BTreeSet<String> {
+    fn collect(nodes: &[Node], names: &mut BTreeSet<String>) {
+        for node in nodes {
+            match node {
+                Node::Variable { name, .. } => {
+                    names.insert(name.clone());
+                }
+                Node::Color { children, .. } => collect(children, names),
+                _ => {}
+            }
+        }
+    }
+    let mut names = BTreeSet::new();
+    collect(&markup::parse(text), &mut names);
+    names
+}
+
+fn list(names: &BTreeSet<String>) -> String {
+    if names.is_empty() {
+        return "no placeholders".to_string();
+    }
+    names.iter().map(|name| format!("${}$", name)).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(checker: &Checker) -> Vec<String> {
+        checker
+            .check()
+            .iter()
+            .map(|p| {
+                let source = checker.source(&p.file).unwrap();
+                let text = String::from_utf8_lossy(&source[p.diagnostic.span.start..p.diagnostic.span.end]);
+                format!("{} {} {}", p.file.display(), text, p.diagnostic)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let mut checker = Checker::new("english");
+        checker.add_localisation(
+            Path::new("events_l_english.yml"),
+            b"\xEF\xBB\xBFl_english:\n a:0 \"A $X$ $Y$\"\n b:0 \"B\"\n c:0 \"\xC2\xA7Y$Z$\xC2\xA7!\"\n".to_vec(),
+        );
+        checker.add_localisation(
+            Path::new("events_l_german.yml"),
+            b"\xEF\xBB\xBFl_german:\n a:0 \"A $Y$\"\n c:0 \"$Z$\"\n d:0 \"D\"\n".to_vec(),
+        );
+        checker.add_localisation(
+            Path::new("more_l_german.yml"),
+            b"\xEF\xBB\xBFl_german:\n c:0 \"C\"\n".to_vec(),
+        );
+        checker.add_script(
+            Path::new("events/my_events.txt"),
+            b"country_event = { id = e.1 title = a desc = \"missing\" option = { name = missing custom_tooltip = 5 } }"
+                .to_vec(),
+        );
+        assert_eq!(
+            check(&checker),
+            vec![
+                "events/my_events.txt \"missing\" error[missing-localisation]: `missing` has no english localisation",
+                "events_l_english.yml b:0 \"B\" error[missing-key]: `b` is missing in german",
+                "events_l_german.yml a:0 \"A $Y$\" error[placeholder-mismatch]: `a` has $Y$, but english has $X$, $Y$",
+                "events_l_german.yml d:0 \"D\" warning[extra-key]: `d` is not in english",
+                "more_l_german.yml c:0 \"C\" warning[duplicate-key]: `c` is already defined in events_l_german.yml",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_language() {
+        let mut checker = Checker::new("english");
+        checker.add_localisation(Path::new("a_l_english.yml"), b"\xEF\xBB\xBFl_english:\n".to_vec());
+        checker.add_localisation(Path::new("a_l_german.yml"), b"\xEF\xBB\xBFl_german:\n a:0 \"A\"\n".to_vec());
+        assert_eq!(
+            check(&checker),
+            vec!["a_l_german.yml a:0 \"A\" warning[extra-key]: `a` is not in english"]
+        );
+
+        let mut checker = Checker::new("english");
+        checker.add_localisation(Path::new("a_l_english.yml"), b"\xEF\xBB\xBFl_english:\n a:0 \"A\"\n".to_vec());
+        checker.add_localisation(Path::new("a_l_german.yml"), b"\xEF\xBB\xBFl_german:\n".to_vec());
+        assert_eq!(
+            check(&checker),
+            vec!["a_l_english.yml a:0 \"A\" error[missing-key]: `a` is missing in german"]
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/coverage");
+
+        let mut checker = Checker::new("english").reference_keys(&["desc"]);
+        checker.load(&dir).unwrap();
+        let problems: Vec<_> = checker
+            .check()
+            .into_iter()
+            .map(|p| (p.file.strip_prefix(&dir).unwrap().to_path_buf(), p.diagnostic.code))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (PathBuf::from("events/a.txt"), "missing-localisation".to_string()),
+                (PathBuf::from("localisation/english/a_l_english.yml"), "missing-bom".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,657 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The concrete syntax tree
+//!
+//! A lossless representation of a Clausewitz file. Besides the tokens the parser cares about,
+//! the tree keeps all trivia (whitespace, newlines and comments), so the input can be reproduced
+//! byte for byte. The [**ClVal**](../clval/enum.ClVal.html) tree can be derived from it with
+//! [`SyntaxTree::to_clval`](struct.SyntaxTree.html#method.to_clval).
+//!
+//! The tree consists of three kinds of nodes:
+//!
+//! * `Document`: the root node
+//! * `Entry`: a `key = value` pair, the equals sign and the value may be missing in broken input
+//! * `Block`: everything between `{` and `}`, including the curly brackets
+//!
+//! Scalar values are plain `Untyped` or `Quoted` tokens. Tokens that don't fit anywhere
+//! (like a stray `=`) are kept as children of the surrounding node.
+
+use clval::ClVal;
+use diagnostic::Diagnostic;
+use error::Error;
+use parser::Parser;
+use std::borrow::Cow;
+use token::{LexerToken, Span, Tokenizer};
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+/// The kinds of tokens and nodes
+pub enum SyntaxKind {
+    // Tokens
+    Bom,
+    Whitespace,
+    Newline,
+    Comment,
+    Equals,
+    LeftCurly,
+    RightCurly,
+    LeftParanthesis,
+    RightParanthesis,
+    Comma,
+    Quoted,
+    Untyped,
+    // Nodes
+    Document,
+    Entry,
+    Block,
+}
+
+impl SyntaxKind {
+    /// Returns `true` for tokens without meaning to the parser
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::Bom | SyntaxKind::Whitespace | SyntaxKind::Newline | SyntaxKind::Comment
+        )
+    }
+
+    /// Returns `true` for tokens which hold a key or a scalar value
+    pub fn is_scalar(self) -> bool {
+        matches!(self, SyntaxKind::Quoted | SyntaxKind::Untyped)
+    }
+}
+
+impl<'a, 'buf> From<&'a LexerToken<'buf>> for SyntaxKind {
+    fn from(token: &LexerToken) -> Self {
+        match token {
+            LexerToken::Equals => SyntaxKind::Equals,
+            LexerToken::Quote => SyntaxKind::Quoted,
+            LexerToken::LeftCurly => SyntaxKind::LeftCurly,
+            LexerToken::RightCurly => SyntaxKind::RightCurly,
+            LexerToken::LeftParanthesis => SyntaxKind::LeftParanthesis,
+            LexerToken::RightParanthesis => SyntaxKind::RightParanthesis,
+            LexerToken::Comment => SyntaxKind::Comment,
+            LexerToken::Comma => SyntaxKind::Comma,
+            LexerToken::Untyped(_) => SyntaxKind::Untyped,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+/// A token of the syntax tree
+pub struct SyntaxToken {
+    pub kind: SyntaxKind,
+    pub span: Span,
+}
+
+impl SyntaxToken {
+    /// Construct a new `SyntaxToken`
+    pub fn new(kind: SyntaxKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+/// A child of a [`SyntaxNode`](struct.SyntaxNode.html)
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxElement {
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            SyntaxElement::Node(node) => node.kind,
+            SyntaxElement::Token(token) => token.kind,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            SyntaxElement::Node(node) => node.span,
+            SyntaxElement::Token(token) => token.span,
+        }
+    }
+
+    pub fn as_node(&self) -> Option<&SyntaxNode> {
+        if let SyntaxElement::Node(node) = self {
+            Some(node)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_token(&self) -> Option<&SyntaxToken> {
+        if let SyntaxElement::Token(token) = self {
+            Some(token)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+/// A node of the syntax tree
+pub struct SyntaxNode {
+    kind: SyntaxKind,
+    span: Span,
+    children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind, start: usize, children: Vec<SyntaxElement>) -> Self {
+        let end = children.last().map(|c| c.span().end).unwrap_or(start);
+        let start = children.first().map(|c| c.span().start).unwrap_or(start);
+        Self {
+            kind,
+            span: Span::new(start, end),
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn children(&self) -> &[SyntaxElement] {
+        &self.children
+    }
+
+    /// The `Entry` children of this node
+    pub fn entries(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children
+            .iter()
+            .filter_map(|c| c.as_node())
+            .filter(|n| n.kind == SyntaxKind::Entry)
+    }
+
+    /// The children which are neither trivia nor punctuation, i.e. entries, blocks and scalars
+    pub fn items(&self) -> impl Iterator<Item = &SyntaxElement> {
+        self.children.iter().filter(|c| match c.kind() {
+            SyntaxKind::Entry | SyntaxKind::Block => true,
+            kind => kind.is_scalar(),
+        })
+    }
+
+    /// The key token of an `Entry`
+    pub fn key(&self) -> Option<&SyntaxToken> {
+        if self.kind != SyntaxKind::Entry {
+            return None;
+        }
+        self.children.first().and_then(|c| c.as_token())
+    }
+
+    /// The equals token of an `Entry`
+    pub fn equals(&self) -> Option<&SyntaxToken> {
+        if self.kind != SyntaxKind::Entry {
+            return None;
+        }
+        self.children
+            .iter()
+            .filter_map(|c| c.as_token())
+            .find(|t| t.kind == SyntaxKind::Equals)
+    }
+
+    /// The value of an `Entry`, either a scalar token or a `Block` node
+    pub fn value(&self) -> Option<&SyntaxElement> {
+        if self.kind != SyntaxKind::Entry {
+            return None;
+        }
+        self.children.iter().skip(1).find(|c| match c.kind() {
+            SyntaxKind::Block => true,
+            kind => kind.is_scalar(),
+        })
+    }
+
+    /// All tokens of this node in source order
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+}
+
+/// A lossless syntax tree of a buffer
+///
+/// # Example
+///
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::cst::SyntaxTree;
+///
+/// fn main() {
+///     let buf = b"# the player\nplayer = \"PRU\"\n";
+///     let tree = SyntaxTree::parse(buf);
+///
+///     assert_eq!(tree.to_bytes(), buf.to_vec());
+///     let values = tree.to_clval().unwrap();
+/// }
+/// ```
+pub struct SyntaxTree<'buf> {
+    source: &'buf [u8],
+    root: SyntaxNode,
+}
+
+impl<'buf> SyntaxTree<'buf> {
+    /// Build the syntax tree of the provided buffer
+    ///
+    /// Building the tree never fails, broken input just leads to incomplete nodes and stray tokens.
+    pub fn parse(source: &'buf [u8]) -> Self {
+        let tokens = Tokenizer::new(source).tokenize_lossless();
+        let root = Builder::new(&tokens).document();
+        Self { source, root }
+    }
+
+    /// The buffer this tree was built from
+    pub fn source(&self) -> &'buf [u8] {
+        self.source
+    }
+
+    /// The `Document` node
+    pub fn root(&self) -> &SyntaxNode {
+        &self.root
+    }
+
+    /// The bytes covered by a span
+    pub fn text(&self, span: Span) -> &'buf [u8] {
+        &self.source[span.start..span.end]
+    }
+
+    /// The text of a scalar token, without the quotes of a quoted string
+    ///
+    /// Bytes which are not valid UTF-8, like the Windows-1252 text of many game files, are
+    /// replaced with `U+FFFD`.
+    pub fn scalar(&self, token: &SyntaxToken) -> Cow<'buf, str> {
+        String::from_utf8_lossy(self.scalar_bytes(token))
+    }
+
+    fn scalar_bytes(&self, token: &SyntaxToken) -> &'buf [u8] {
+        let text = self.text(token.span);
+        if token.kind == SyntaxKind::Quoted {
+            let end = if text.len() > 1 && text.ends_with(b"\"") {
+                text.len() - 1
+            } else {
+                text.len()
+            };
+            &text[1..end]
+        } else {
+            text
+        }
+    }
+
+    /// Returns `true` if the buffer starts with a UTF-8 BOM
+    pub fn has_bom(&self) -> bool {
+        self.root
+            .children
+            .first()
+            .map(|c| c.kind() == SyntaxKind::Bom)
+            .unwrap_or(false)
+    }
+
+    /// Reproduce the buffer from the tokens of the tree
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.source.len());
+        for token in self.root.tokens() {
+            buf.extend_from_slice(self.text(token.span));
+        }
+        buf
+    }
+
+    /// The tokens of the tree as the [**Parser**](../parser/struct.Parser.html) expects them
+    ///
+    /// Trivia, including comments, is dropped.
+    pub fn lexer_tokens(&self) -> Vec<LexerToken<'buf>> {
+        self.node_lexer_tokens(&self.root)
+    }
+
+    fn node_lexer_tokens(&self, node: &SyntaxNode) -> Vec<LexerToken<'buf>> {
+        let mut tokens = Vec::new();
+        for token in node.tokens() {
+            match token.kind {
+                SyntaxKind::Equals => tokens.push(LexerToken::Equals),
+                SyntaxKind::LeftCurly => tokens.push(LexerToken::LeftCurly),
+                SyntaxKind::RightCurly => tokens.push(LexerToken::RightCurly),
+                SyntaxKind::LeftParanthesis => tokens.push(LexerToken::LeftParanthesis),
+                SyntaxKind::RightParanthesis => tokens.push(LexerToken::RightParanthesis),
+                SyntaxKind::Comma => tokens.push(LexerToken::Comma),
+                SyntaxKind::Untyped => tokens.push(LexerToken::Untyped(self.text(token.span))),
+                SyntaxKind::Quoted => {
+                    let text = self.scalar_bytes(token);
+                    tokens.push(LexerToken::Quote);
+                    tokens.push(LexerToken::Untyped(text));
+                    if self.text(token.span).len() > 1 && self.text(token.span).ends_with(b"\"") {
+                        tokens.push(LexerToken::Quote);
+                    }
+                }
+                _ => {}
+            }
+        }
+        tokens
+    }
+
+    /// The syntax errors of the tree: unclosed blocks, entries without a value and stray tokens
+    ///
+    /// Commas are tolerated, stray parentheses are warnings.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        self.collect_diagnostics(&self.root, &mut diagnostics);
+        diagnostics
+    }
+
+    fn collect_diagnostics(&self, node: &SyntaxNode, diagnostics: &mut Vec<Diagnostic>) {
+        let is_document = node.kind == SyntaxKind::Document;
+        if node.kind == SyntaxKind::Block && node.children.last().map(|c| c.kind()) != Some(SyntaxKind::RightCurly) {
+            diagnostics.push(Diagnostic::error(
+                "unclosed-block",
+                node.children[0].span(),
+                "unclosed block".to_string(),
+            ));
+        }
+        if node.kind == SyntaxKind::Entry && node.value().is_none() {
+            let key = node.key().map(|k| self.scalar(k)).unwrap_or_default();
+            diagnostics.push(Diagnostic::error(
+                "missing-value",
+                node.span,
+                format!("`{}` has no value", key),
+            ));
+        }
+
+        for (index, child) in node.children.iter().enumerate() {
+            let unexpected = |span: Span, text: &str| {
+                Diagnostic::error("unexpected-token", span, format!("unexpected `{}`", text))
+            };
+            match child {
+                SyntaxElement::Node(child) => {
+                    if is_document && child.kind == SyntaxKind::Block {
+                        diagnostics.push(unexpected(child.children[0].span(), "{"));
+                    }
+                    self.collect_diagnostics(child, diagnostics);
+                }
+                SyntaxElement::Token(token) => match token.kind {
+                    // the closing curly of a block
+                    SyntaxKind::RightCurly if !is_document && index == node.children.len() - 1 => {}
+                    // the key and the equals sign of an entry
+                    SyntaxKind::Equals if node.kind == SyntaxKind::Entry => {}
+                    kind if kind.is_scalar() && !is_document => {}
+                    SyntaxKind::Equals | SyntaxKind::RightCurly | SyntaxKind::Quoted | SyntaxKind::Untyped => {
+                        let text = String::from_utf8_lossy(self.text(token.span));
+                        diagnostics.push(unexpected(token.span, &text));
+                    }
+                    SyntaxKind::LeftParanthesis | SyntaxKind::RightParanthesis => {
+                        let text = String::from_utf8_lossy(self.text(token.span));
+                        diagnostics.push(Diagnostic::warning(
+                            "unexpected-token",
+                            token.span,
+                            format!("unexpected `{}`", text),
+                        ));
+                    }
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    /// Derive the [**ClVals**](../clval/enum.ClVal.html) of the tree
+    ///
+    /// The returned `ClVal` is always a `Dict`
+    pub fn to_clval(&self) -> Result<ClVal, Error> {
+        Parser::new(self.lexer_tokens()).parse()
+    }
+
+    /// Derive the [**ClVals**](../clval/enum.ClVal.html) of a single `Entry` of the tree
+    ///
+    /// The returned `ClVal` is a `Dict` holding the entry, so every definition of a key which
+    /// is repeated at the top level can be read, not just the last one.
+    pub fn entry_to_clval(&self, entry: &SyntaxNode) -> Result<ClVal, Error> {
+        Parser::new(self.node_lexer_tokens(entry)).parse()
+    }
+}
+
+struct Builder<'t> {
+    tokens: &'t [SyntaxToken],
+    position: usize,
+}
+
+impl<'t> Builder<'t> {
+    fn new(tokens: &'t [SyntaxToken]) -> Self {
+        Self { tokens, position: 0 }
+    }
+
+    /// The index and kind of the next non-trivia token at or after `from`
+    fn peek_significant(&self, from: usize) -> Option<(usize, SyntaxKind)> {
+        self.tokens[from.min(self.tokens.len())..]
+            .iter()
+            .position(|t| !t.kind.is_trivia())
+            .map(|i| (from + i, self.tokens[from + i].kind))
+    }
+
+    /// Move all tokens up to (excluding) `until` into `children`
+    fn bump_until(&mut self, until: usize, children: &mut Vec<SyntaxElement>) {
+        while self.position < until {
+            children.push(SyntaxElement::Token(self.tokens[self.position]));
+            self.position += 1;
+        }
+    }
+
+    fn bump(&mut self, children: &mut Vec<SyntaxElement>) {
+        let until = self.position + 1;
+        self.bump_until(until, children);
+    }
+
+    fn document(&mut self) -> SyntaxNode {
+        let mut children = Vec::new();
+        while self.position < self.tokens.len() {
+            self.item(true, &mut children);
+        }
+        SyntaxNode::new(SyntaxKind::Document, 0, children)
+    }
+
+    /// Parse the next item of a document or block into `children`
+    ///
+    /// `in_dict` mirrors the parser: inside dicts the equals sign in front of a block is optional.
+    fn item(&mut self, in_dict: bool, children: &mut Vec<SyntaxElement>) {
+        let kind = self.tokens[self.position].kind;
+        if kind.is_scalar() {
+            match self.peek_significant(self.position + 1) {
+                Some((_, SyntaxKind::Equals)) => children.push(SyntaxElement::Node(self.entry())),
+                Some((_, SyntaxKind::LeftCurly)) if in_dict => children.push(SyntaxElement::Node(self.entry())),
+                _ => self.bump(children),
+            }
+        } else if kind == SyntaxKind::LeftCurly {
+            children.push(SyntaxElement::Node(self.block()));
+        } else {
+            self.bump(children);
+        }
+    }
+
+    fn entry(&mut self) -> SyntaxNode {
+        let start = self.tokens[self.position].span.start;
+        let mut children = Vec::new();
+        // the key
+        self.bump(&mut children);
+
+        if let Some((idx, SyntaxKind::Equals)) = self.peek_significant(self.position) {
+            self.bump_until(idx + 1, &mut children);
+        }
+        match self.peek_significant(self.position) {
+            Some((idx, kind)) if kind.is_scalar() => self.bump_until(idx + 1, &mut children),
+            Some((idx, SyntaxKind::LeftCurly)) => {
+                self.bump_until(idx, &mut children);
+                children.push(SyntaxElement::Node(self.block()));
+            }
+            _ => {}
+        }
+
+        SyntaxNode::new(SyntaxKind::Entry, start, children)
+    }
+
+    fn block(&mut self) -> SyntaxNode {
+        let start = self.tokens[self.position].span.start;
+        let mut children = Vec::new();
+        // the left curly
+        self.bump(&mut children);
+
+        // like the parser, the block is a dict if the first value is followed by an equals sign
+        let in_dict = match self.peek_significant(self.position) {
+            Some((idx, kind)) if kind.is_scalar() => {
+                matches!(self.peek_significant(idx + 1), Some((_, SyntaxKind::Equals)))
+            }
+            _ => false,
+        };
+
+        while self.position < self.tokens.len() {
+            if self.tokens[self.position].kind == SyntaxKind::RightCurly {
+                self.bump(&mut children);
+                break;
+            }
+            self.item(in_dict, &mut children);
+        }
+
+        SyntaxNode::new(SyntaxKind::Block, start, children)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(elements: &[SyntaxElement]) -> Vec<SyntaxKind> {
+        elements.iter().map(|e| e.kind()).collect()
+    }
+
+    #[test]
+    fn test_lossless() {
+        let buf = include_bytes!("../examples/test");
+        let tree = SyntaxTree::parse(buf);
+        assert_eq!(tree.to_bytes(), buf.to_vec());
+
+        let buf = b"\xEF\xBB\xBF# comment\r\nfoo = { bar baz } # trailing\n\n\"quoted\" = \"unclosed";
+        let tree = SyntaxTree::parse(buf);
+        assert!(tree.has_bom());
+        assert_eq!(tree.to_bytes(), buf.to_vec());
+    }
+
+    #[test]
+    fn test_to_clval() {
+        let buf = include_bytes!("../examples/test");
+        let tree = SyntaxTree::parse(buf);
+        assert_eq!(tree.to_clval().unwrap(), ::parse(buf).unwrap());
+
+        let tree = SyntaxTree::parse(b"# comment\nfoo = bar # trailing\n");
+        assert_eq!(tree.to_clval().unwrap(), ::parse(b"foo = bar").unwrap());
+
+        let tree = SyntaxTree::parse(b"foo = { a = 1 } # first\nfoo { a = 2 }\n");
+        let values: Vec<_> = tree.root().entries().map(|e| tree.entry_to_clval(e).unwrap()).collect();
+        assert_eq!(values, vec![::parse(b"foo = { a = 1 }").unwrap(), ::parse(b"foo = { a = 2 }").unwrap()]);
+    }
+
+    #[test]
+    fn test_structure() {
+        let buf = b"key = value # comment\nblock { a = 1 b = { 1 2 } }\n";
+        let tree = SyntaxTree::parse(buf);
+        let root = tree.root();
+        assert_eq!(
+            kinds(root.children()),
+            vec![
+                SyntaxKind::Entry,
+                SyntaxKind::Whitespace,
+                SyntaxKind::Comment,
+                SyntaxKind::Newline,
+                SyntaxKind::Entry,
+                SyntaxKind::Newline,
+            ]
+        );
+
+        let entries: Vec<_> = root.entries().collect();
+        assert_eq!(tree.scalar(entries[0].key().unwrap()), "key");
+        assert!(entries[0].equals().is_some());
+        assert_eq!(tree.scalar(entries[0].value().unwrap().as_token().unwrap()), "value");
+
+        // the equals sign in front of a block is optional
+        assert_eq!(tree.scalar(entries[1].key().unwrap()), "block");
+        assert!(entries[1].equals().is_none());
+        let block = entries[1].value().unwrap().as_node().unwrap();
+        assert_eq!(block.kind(), SyntaxKind::Block);
+        assert_eq!(tree.text(block.span()), b"{ a = 1 b = { 1 2 } }");
+        assert_eq!(block.entries().count(), 2);
+
+        let list = block.entries().nth(1).unwrap().value().unwrap().as_node().unwrap();
+        assert_eq!(
+            kinds(list.children()),
+            vec![
+                SyntaxKind::LeftCurly,
+                SyntaxKind::Whitespace,
+                SyntaxKind::Untyped,
+                SyntaxKind::Whitespace,
+                SyntaxKind::Untyped,
+                SyntaxKind::Whitespace,
+                SyntaxKind::RightCurly,
+            ]
+        );
+        assert_eq!(list.items().count(), 2);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let buf = b"a = 1\nc = { d = { 1 ) }\n} }\n{ }\nfoo\nx = { b = }\ne = {";
+        let tree = SyntaxTree::parse(buf);
+        let diagnostics: Vec<_> = tree
+            .diagnostics()
+            .iter()
+            .map(|d| format!("{} {}", String::from_utf8_lossy(tree.text(d.span)), d))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                ") warning[unexpected-token]: unexpected `)`",
+                "} error[unexpected-token]: unexpected `}`",
+                "{ error[unexpected-token]: unexpected `{`",
+                "foo error[unexpected-token]: unexpected `foo`",
+                "b = error[missing-value]: `b` has no value",
+                "{ error[unclosed-block]: unclosed block",
+            ]
+        );
+        assert!(SyntaxTree::parse(b"a = { 1, 2 } b = { c = d }").diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_scalar() {
+        let tree = SyntaxTree::parse(b"\"key\" = \"\"");
+        let entry = tree.root().entries().next().unwrap();
+        assert_eq!(tree.scalar(entry.key().unwrap()), "key");
+        assert_eq!(tree.scalar(entry.value().unwrap().as_token().unwrap()), "");
+
+        let tree = SyntaxTree::parse(b"name = \"Arm\xe9nie\"");
+        let entry = tree.root().entries().next().unwrap();
+        assert_eq!(tree.scalar(entry.value().unwrap().as_token().unwrap()), "Arm\u{FFFD}nie");
+    }
+}
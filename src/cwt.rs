@@ -0,0 +1,428 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! CWTools rule files
+//!
+//! [CWTools](https://github.com/cwtools/cwtools) rule sets (`.cwt` files) describe the script
+//! files of the Paradox games in Clausewitz syntax, with options in `##` comments before an
+//! entry. They are converted into a [**Schema**](../schema/struct.Schema.html):
+//!
+//! * top level rules become named rules. A type in the `types` section applies its rule to the
+//!   top level of the files under its `path`, to the keys of its `type_key_filter` or to every
+//!   key. Rules which no type refers to apply to their own name in every file
+//! * `## cardinality = min..max` sets how often a key may occur, a soft minimum (`~1..2`) is
+//!   treated as 0
+//! * `enums` are used for `enum[name]`, references to other enums (like complex enums) accept
+//!   any scalar
+//! * keys like `scalar`, `<type>`, `enum[name]` or `alias_name[name]` match every key
+//! * `<type>` references, localisation, file paths and value fields accept any scalar, aliases
+//!   accept any value
+//!
+//! Subtypes, aliases, `skip_root_key`, `path_strict` and all other options are ignored, so the
+//! conversion is more lenient than CWTools itself.
+
+use cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxTree};
+use error::{Error, ErrorKind};
+use schema::{Field, Rule, Schema, ValueType, ANY_KEY};
+use std::collections::HashMap;
+
+/// Convert a `.cwt` file into a schema
+///
+/// Like [`Schema::from_slice`](../schema/struct.Schema.html#method.from_slice), references
+/// to enums defined in other files aren't resolved, use [`resolve`](fn.resolve.html) after
+/// all files are loaded.
+pub fn from_cwt(buf: &[u8]) -> Result<Schema, Error> {
+    let tree = SyntaxTree::parse(buf);
+    let mut schema = Schema::default();
+    // the paths and root keys of the rules, from `path` and `type_key_filter`
+    let mut types: HashMap<String, (Vec<String>, Option<Vec<String>>)> = HashMap::new();
+    let mut rules = Vec::new();
+
+    for (_, entry) in entries(&tree, tree.root()) {
+        let key = match entry.key() {
+            Some(key) => tree.scalar(key),
+            None => continue,
+        };
+        let block = match entry.value().and_then(SyntaxElement::as_node) {
+            Some(block) => block,
+            None => continue,
+        };
+        match key.as_ref() {
+            "types" => {
+                for (_, entry) in entries(&tree, block) {
+                    if let Some(name) = entry_key(&tree, entry, "type") {
+                        types.insert(name, (type_paths(&tree, entry), type_key_filter(&tree, entry)));
+                    }
+                }
+            }
+            "enums" => {
+                for (_, entry) in entries(&tree, block) {
+                    let value = entry.value().and_then(SyntaxElement::as_node);
+                    if let (Some(name), Some(values)) = (entry_key(&tree, entry, "enum"), value) {
+                        let values = values
+                            .items()
+                            .filter_map(SyntaxElement::as_token)
+                            .map(|t| tree.scalar(t).to_string())
+                            .collect();
+                        schema.enums.insert(name.to_string(), values);
+                    }
+                }
+            }
+            // aliases, value sets and the like
+            key if key.contains('[') => {}
+            key => rules.push((key.to_string(), rule(&tree, block)?)),
+        }
+    }
+
+    for (name, rule) in rules {
+        let field = |key: &str| Field::new(key, ValueType::RuleRef(name.clone()));
+        match types.remove(&name) {
+            Some((paths, keys)) if !paths.is_empty() => {
+                let keys = keys.unwrap_or_else(|| vec![ANY_KEY.to_string()]);
+                for path in paths {
+                    let root = schema.paths.entry(path).or_default();
+                    root.fields.extend(keys.iter().map(|key| field(key)));
+                }
+            }
+            keys => {
+                let keys = keys.and_then(|(_, keys)| keys).unwrap_or_else(|| vec![name.clone()]);
+                schema.root.fields.extend(keys.iter().map(|key| field(key)));
+            }
+        }
+        schema.rules.insert(name, rule);
+    }
+    Ok(schema)
+}
+
+/// Replace references to unknown enums by `scalar`
+pub fn resolve(schema: &mut Schema) {
+    let enums: Vec<String> = schema.enums.keys().cloned().collect();
+    resolve_rule(&mut schema.root, &enums);
+    for rule in schema.paths.values_mut() {
+        resolve_rule(rule, &enums);
+    }
+    for rule in schema.rules.values_mut() {
+        resolve_rule(rule, &enums);
+    }
+}
+
+fn resolve_rule(rule: &mut Rule, enums: &[String]) {
+    for field in &mut rule.fields {
+        resolve_type(&mut field.value, enums);
+    }
+}
+
+fn resolve_type(value: &mut ValueType, enums: &[String]) {
+    match value {
+        ValueType::EnumRef(name) if !enums.contains(name) => *value = ValueType::Scalar,
+        ValueType::Block(rule) => resolve_rule(rule, enums),
+        ValueType::List(items) => resolve_type(items, enums),
+        _ => {}
+    }
+}
+
+/// The entries of a node with the `##` options before them
+fn entries<'t>(tree: &SyntaxTree, node: &'t SyntaxNode) -> Vec<(HashMap<String, String>, &'t SyntaxNode)> {
+    let mut entries = Vec::new();
+    let mut options = HashMap::new();
+    for child in node.children() {
+        match child {
+            SyntaxElement::Token(token) if token.kind == SyntaxKind::Comment => {
+                let text = String::from_utf8_lossy(tree.text(token.span));
+                if text.starts_with("##") && !text.starts_with("###") {
+                    let mut option = text[2..].splitn(2, '=');
+                    let key = option.next().unwrap_or("").trim();
+                    let value = option.next().unwrap_or("").trim();
+                    options.insert(key.to_string(), value.to_string());
+                }
+            }
+            SyntaxElement::Node(entry) if entry.kind() == SyntaxKind::Entry => {
+                entries.push((options, entry));
+                options = HashMap::new();
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// The name of a `prefix[name]` key
+fn entry_key(tree: &SyntaxTree, entry: &SyntaxNode, prefix: &str) -> Option<String> {
+    let key = tree.scalar(entry.key()?);
+    if key.starts_with(prefix) && key[prefix.len()..].starts_with('[') && key.ends_with(']') {
+        Some(key[prefix.len() + 1..key.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+/// The directories of a type, without the leading `game/`
+fn type_paths(tree: &SyntaxTree, entry: &SyntaxNode) -> Vec<String> {
+    let block = match entry.value().and_then(SyntaxElement::as_node) {
+        Some(block) => block,
+        None => return Vec::new(),
+    };
+    block
+        .entries()
+        .filter(|e| e.key().map(|k| tree.scalar(k) == "path").unwrap_or(false))
+        .filter_map(|e| e.value()?.as_token().map(|t| tree.scalar(t)))
+        .map(|path| {
+            let path = path.trim_matches('/');
+            match path {
+                "game" => String::new(),
+                path => path.strip_prefix("game/").unwrap_or(path).to_string(),
+            }
+        })
+        .collect()
+}
+
+fn type_key_filter(tree: &SyntaxTree, entry: &SyntaxNode) -> Option<Vec<String>> {
+    let block = entry.value()?.as_node()?;
+    let filter = block
+        .entries()
+        .find(|e| e.key().map(|k| tree.scalar(k) == "type_key_filter").unwrap_or(false))?;
+    match filter.value()? {
+        SyntaxElement::Token(token) => Some(vec![tree.scalar(token).to_string()]),
+        SyntaxElement::Node(node) => Some(
+            node.items()
+                .filter_map(SyntaxElement::as_token)
+                .map(|t| tree.scalar(t).to_string())
+                .collect(),
+        ),
+    }
+}
+
+fn rule(tree: &SyntaxTree, block: &SyntaxNode) -> Result<Rule, Error> {
+    let mut fields = Vec::new();
+    for (options, entry) in entries(tree, block) {
+        let key = match entry.key() {
+            Some(key) => key_name(&tree.scalar(key)).to_string(),
+            None => continue,
+        };
+        let value = match entry.value() {
+            Some(SyntaxElement::Token(token)) => value_type(&tree.scalar(token)),
+            Some(SyntaxElement::Node(node)) => block_type(tree, node)?,
+            None => continue,
+        };
+        let (min, max) = match options.get("cardinality") {
+            Some(cardinality) => parse_cardinality(cardinality)?,
+            None => (0, None),
+        };
+        fields.push(Field {
+            key,
+            value,
+            min,
+            max,
+        });
+    }
+    Ok(Rule { fields })
+}
+
+fn block_type(tree: &SyntaxTree, node: &SyntaxNode) -> Result<ValueType, Error> {
+    let first = node.items().next();
+    Ok(match first {
+        Some(SyntaxElement::Token(token)) => ValueType::List(Box::new(value_type(&tree.scalar(token)))),
+        _ => ValueType::Block(rule(tree, node)?),
+    })
+}
+
+/// The key of a field, keys matching a kind of value match every key
+fn key_name(key: &str) -> &str {
+    match key {
+        "scalar" | "int" | "float" | "bool" | "localisation" | "date_field" | "scope_field" => ANY_KEY,
+        key if key.contains('[') || key.starts_with('<') => ANY_KEY,
+        key => key,
+    }
+}
+
+fn value_type(value: &str) -> ValueType {
+    let (name, argument) = match value.find('[') {
+        Some(start) if value.ends_with(']') => (&value[..start], Some(&value[start + 1..value.len() - 1])),
+        _ => (value, None),
+    };
+    match name {
+        "bool" => ValueType::Bool,
+        "int" => {
+            let (min, max) = argument.and_then(parse_range).unwrap_or((None, None));
+            ValueType::Int { min, max }
+        }
+        "float" | "percentage_field" => ValueType::Float,
+        "date_field" => ValueType::Date,
+        "scope" | "scope_field" | "event_target" => ValueType::Scope,
+        "enum" => match argument {
+            Some(argument) => ValueType::EnumRef(argument.to_string()),
+            None => ValueType::Scalar,
+        },
+        "alias_match_left" | "single_alias_right" | "alias_keys_field" => ValueType::Any,
+        "scalar" | "localisation" | "localisation_synced" | "localisation_inline" | "filepath" | "icon"
+        | "variable_field" | "value_field" | "int_variable_field" | "int_value_field" | "value" | "value_set" => {
+            ValueType::Scalar
+        }
+        _ if value.starts_with('<') || argument.is_some() => ValueType::Scalar,
+        // a literal value like `yes`
+        literal => ValueType::Enum(vec![literal.to_string()]),
+    }
+}
+
+/// A `min..max` range of integers, `inf` and `-inf` are unbounded
+fn parse_range(range: &str) -> Option<(Option<i64>, Option<i64>)> {
+    let mut bounds = range.splitn(2, "..");
+    let bound = |s: &str| -> Option<Option<i64>> {
+        match s.trim() {
+            "inf" | "-inf" => Some(None),
+            s => s.parse().ok().map(Some),
+        }
+    };
+    Some((bound(bounds.next()?)?, bound(bounds.next()?)?))
+}
+
+fn parse_cardinality(cardinality: &str) -> Result<(usize, Option<usize>), Error> {
+    let invalid = || Error::from(ErrorKind::InvalidSchema(format!("invalid cardinality `{}`", cardinality)));
+    let soft = cardinality.starts_with('~');
+    let (min, max) = parse_range(cardinality.trim_start_matches('~')).ok_or_else(invalid)?;
+    let min = if soft { 0 } else { min.unwrap_or(0).max(0) as usize };
+    Ok((min, max.map(|max| max.max(0) as usize)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CWT: &[u8] = b"types = {
+\ttype[event] = {
+\t\tpath = \"game/events\"
+\t\ttype_key_filter = { country_event province_event }
+\t}
+\ttype[scripted_trigger] = {
+\t\tpath = \"game/common/scripted_triggers\"
+\t}
+}
+
+scripted_trigger = {
+\talias_name[trigger] = alias_match_left[trigger]
+}
+
+on_actions = {
+\tevents = { <event> }
+}
+
+### An event
+event = {
+\t## cardinality = 1..1
+\tid = <event>
+\t## cardinality = 0..1
+\tis_triggered_only = bool # a trailing comment
+\tdifficulty = enum[difficulty]
+\tgoods = enum[trade_goods]
+\tweight = int[0..inf]
+\tscalar = scalar
+\ttags = { scope[country] }
+\t## cardinality = ~1..inf
+\toption = {
+\t\tname = localisation
+\t\talias_name[effect] = alias_match_left[effect]
+\t}
+\thidden = yes
+}
+
+enums = {
+\tenum[difficulty] = { easy normal hard }
+}
+";
+
+    #[test]
+    fn test_from_cwt() {
+        let mut schema = from_cwt(CWT).unwrap();
+        assert!(schema.check().is_err());
+        resolve(&mut schema);
+        schema.check().unwrap();
+
+        assert_eq!(
+            schema.paths["events"].fields,
+            vec![
+                Field::new("country_event", ValueType::RuleRef("event".to_string())),
+                Field::new("province_event", ValueType::RuleRef("event".to_string())),
+            ]
+        );
+        assert_eq!(
+            schema.paths["common/scripted_triggers"].fields,
+            vec![Field::new(ANY_KEY, ValueType::RuleRef("scripted_trigger".to_string()))]
+        );
+        assert_eq!(
+            schema.root.fields,
+            vec![Field::new("on_actions", ValueType::RuleRef("on_actions".to_string()))]
+        );
+        assert_eq!(schema.enums["difficulty"], vec!["easy", "normal", "hard"]);
+        let fields = &schema.rules["event"].fields;
+        let field = |key: &str| fields.iter().find(|f| f.key == key).unwrap();
+        assert_eq!((field("id").min, field("id").max), (1, Some(1)));
+        assert_eq!(field("id").value, ValueType::Scalar);
+        assert_eq!(field("is_triggered_only").max, Some(1));
+        assert_eq!(field("is_triggered_only").value, ValueType::Bool);
+        assert_eq!(field("difficulty").value, ValueType::EnumRef("difficulty".to_string()));
+        assert_eq!(field("goods").value, ValueType::Scalar);
+        assert_eq!(field("weight").value, ValueType::Int { min: Some(0), max: None });
+        assert_eq!(field(ANY_KEY).value, ValueType::Scalar);
+        assert_eq!(field("tags").value, ValueType::List(Box::new(ValueType::Scope)));
+        assert_eq!(field("hidden").value, ValueType::Enum(vec!["yes".to_string()]));
+        assert_eq!(field("option").min, 0);
+        match &field("option").value {
+            ValueType::Block(rule) => {
+                assert_eq!(rule.fields[0], Field::new("name", ValueType::Scalar));
+                assert_eq!(rule.fields[1], Field::new(ANY_KEY, ValueType::Any));
+            }
+            value => panic!("not a block: {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_validate_with_cwt() {
+        let mut schema = from_cwt(CWT).unwrap();
+        resolve(&mut schema);
+        let validate = |path: &str, buf: &[u8]| -> Vec<String> {
+            let tree = SyntaxTree::parse(buf);
+            schema.validate_file(path, &tree).iter().map(|d| d.to_string()).collect()
+        };
+        assert_eq!(
+            validate("mod/events/test.txt", b"country_event = { difficulty = hard weight = -1 }"),
+            vec![
+                "error[missing-key]: missing `id`",
+                "error[type-mismatch]: expected int[0..], found `-1`",
+            ]
+        );
+        assert_eq!(
+            validate("common/scripted_triggers/a/b.txt", b"is_rich = { treasury = 1000 } is_poor = 1"),
+            vec!["error[type-mismatch]: expected a block, found `1`"]
+        );
+        assert_eq!(
+            validate("common/on_actions/test.txt", b"country_event = { id = a.1 }"),
+            vec!["warning[unknown-key]: unknown key `country_event`"]
+        );
+    }
+
+    #[test]
+    fn test_parse_cardinality() {
+        assert_eq!(parse_cardinality("0..1").unwrap(), (0, Some(1)));
+        assert_eq!(parse_cardinality("1..inf").unwrap(), (1, None));
+        assert_eq!(parse_cardinality("~2..5").unwrap(), (0, Some(5)));
+        assert_eq!(
+            parse_cardinality("many").unwrap_err().to_string(),
+            "invalid schema: invalid cardinality `many`"
+        );
+    }
+}
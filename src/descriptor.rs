@@ -0,0 +1,444 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Mod descriptors
+//!
+//! A mod is described by a `descriptor.mod` (or a `.mod` file in the `mod` directory) in
+//! Clausewitz syntax:
+//!
+//! ```text
+//! version="1.2"
+//! tags={
+//!     "Gameplay"
+//! }
+//! name="My Mod"
+//! supported_version="1.35.*"
+//! replace_path="common/ideas"
+//! ```
+//!
+//! Newer games use a `.metadata/metadata.json` instead, both load into a
+//! [**ModDescriptor**](struct.ModDescriptor.html). Keys which are not fields of the descriptor
+//! are ignored.
+
+use cst::{SyntaxElement, SyntaxTree};
+use diagnostic::Severity;
+use error::{Error, ErrorKind};
+use serde_json::{self, Map, Value};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// The description of a mod
+pub struct ModDescriptor {
+    pub name: String,
+    pub version: Option<String>,
+    /// The game versions the mod supports, like `1.35.*`
+    pub supported_version: Option<String>,
+    /// The directory of the mod, relative to the user directory of the game
+    pub path: Option<String>,
+    /// A zip archive instead of a directory
+    pub archive: Option<String>,
+    pub tags: Vec<String>,
+    /// Directories of the game which the mod replaces entirely
+    pub replace_path: Vec<String>,
+    /// The names of mods this mod is loaded after
+    pub dependencies: Vec<String>,
+    pub picture: Option<String>,
+    /// The Steam Workshop id
+    pub remote_file_id: Option<String>,
+}
+
+impl ModDescriptor {
+    /// Load a descriptor file, a `.json` file is a `metadata.json`
+    ///
+    /// A directory is the directory of a mod with a `descriptor.mod` or a `.metadata/metadata.json`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ModDescriptor, Error> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let descriptor = path.join("descriptor.mod");
+            if descriptor.exists() {
+                return ModDescriptor::load(descriptor);
+            }
+            return ModDescriptor::load(path.join(".metadata").join("metadata.json"));
+        }
+
+        let buf = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let descriptor = if path.extension().map(|e| e == "json").unwrap_or(false) {
+            ModDescriptor::from_metadata_json(&buf)
+        } else {
+            ModDescriptor::from_slice(&buf)
+        };
+        descriptor.map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Parse a descriptor in Clausewitz syntax
+    pub fn from_slice(buf: &[u8]) -> Result<ModDescriptor, Error> {
+        let tree = SyntaxTree::parse(buf);
+        if let Some(diagnostic) = tree.diagnostics().into_iter().find(|d| d.severity == Severity::Error) {
+            bail!(ErrorKind::Syntax(diagnostic));
+        }
+
+        let mut descriptor = ModDescriptor::default();
+        // keys like `replace_path` may be repeated, so the entries are read in order
+        for entry in tree.root().entries() {
+            let key = entry.key().map(|k| tree.scalar(k)).unwrap_or_default();
+            let values: Vec<String> = match entry.value() {
+                Some(SyntaxElement::Token(token)) => vec![tree.scalar(token).to_string()],
+                Some(SyntaxElement::Node(block)) => block
+                    .items()
+                    .filter_map(SyntaxElement::as_token)
+                    .map(|token| tree.scalar(token).to_string())
+                    .collect(),
+                None => continue,
+            };
+            let single = || match values.as_slice() {
+                [value] => Ok(Some(value.clone())),
+                _ => Err(invalid(&format!("`{}` must be a single value", key))),
+            };
+            match key.as_ref() {
+                "name" => descriptor.name = single()?.unwrap(),
+                "version" => descriptor.version = single()?,
+                "supported_version" => descriptor.supported_version = single()?,
+                "path" => descriptor.path = single()?,
+                "archive" => descriptor.archive = single()?,
+                "picture" => descriptor.picture = single()?,
+                "remote_file_id" => descriptor.remote_file_id = single()?,
+                "tags" => descriptor.tags.extend(values),
+                "replace_path" => descriptor.replace_path.extend(values),
+                "dependencies" => descriptor.dependencies.extend(values),
+                _ => {}
+            }
+        }
+        Ok(descriptor)
+    }
+
+    /// Parse a `metadata.json`
+    pub fn from_metadata_json(buf: &[u8]) -> Result<ModDescriptor, Error> {
+        let value: Value = serde_json::from_slice(buf)?;
+        let string = |value: &Value, key: &str| -> Result<Option<String>, Error> {
+            match value.get(key) {
+                None | Some(Value::Null) => Ok(None),
+                Some(Value::String(s)) => Ok(Some(s.clone())),
+                Some(_) => Err(invalid(&format!("`{}` must be a string", key))),
+            }
+        };
+        let strings = |value: &Value, key: &str| -> Result<Vec<String>, Error> {
+            match value.get(key) {
+                None | Some(Value::Null) => Ok(Vec::new()),
+                Some(Value::Array(values)) => values
+                    .iter()
+                    .map(|v| v.as_str().map(str::to_string))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid(&format!("`{}` must be a list of strings", key))),
+                Some(_) => Err(invalid(&format!("`{}` must be a list of strings", key))),
+            }
+        };
+
+        let empty = Value::Null;
+        let custom = value.get("game_custom_data").unwrap_or(&empty);
+        Ok(ModDescriptor {
+            name: string(&value, "name")?.unwrap_or_default(),
+            version: string(&value, "version")?,
+            supported_version: string(&value, "supported_game_version")?,
+            path: None,
+            archive: None,
+            tags: strings(&value, "tags")?,
+            replace_path: strings(custom, "replace_paths")?,
+            dependencies: Vec::new(),
+            picture: string(&value, "picture")?,
+            remote_file_id: string(&value, "id")?,
+        })
+    }
+
+    /// Check the descriptor for mistakes the launcher silently ignores
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.name.trim().is_empty() {
+            bail!(invalid("`name` is missing"));
+        }
+        if let Some(supported) = &self.supported_version {
+            let components: Vec<&str> = strip_v(supported).split('.').collect();
+            let valid = |i: usize, c: &&str| {
+                (*c == "*" && i == components.len() - 1) || (!c.is_empty() && c.chars().all(|c| c.is_ascii_digit()))
+            };
+            if !components.iter().enumerate().all(|(i, c)| valid(i, c)) {
+                bail!(invalid(&format!(
+                    "invalid `supported_version` `{}`, expected a version like `1.35.*`",
+                    supported
+                )));
+            }
+        }
+        if self.path.is_some() && self.archive.is_some() {
+            bail!(invalid("only one of `path` and `archive` may be set"));
+        }
+        for path in &self.replace_path {
+            if path.is_empty() || path.starts_with('/') || path.split(['/', '\\']).any(|c| c == "..") {
+                bail!(invalid(&format!("`replace_path` `{}` must be relative to the game directory", path)));
+            }
+        }
+        let fields = [
+            ("name", Some(&self.name)),
+            ("version", self.version.as_ref()),
+            ("supported_version", self.supported_version.as_ref()),
+            ("path", self.path.as_ref()),
+            ("archive", self.archive.as_ref()),
+            ("picture", self.picture.as_ref()),
+            ("remote_file_id", self.remote_file_id.as_ref()),
+        ];
+        let lists = [("tags", &self.tags), ("replace_path", &self.replace_path), ("dependencies", &self.dependencies)];
+        let values = fields
+            .iter()
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .chain(lists.iter().flat_map(|(key, values)| values.iter().map(move |value| (key, value))));
+        for (key, value) in values {
+            without_quotes(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the supported version matches a game version
+    ///
+    /// A trailing `*` matches the remaining components, a leading `v` is ignored. Without a
+    /// supported version every game version matches.
+    pub fn supports(&self, game_version: &str) -> bool {
+        let supported = match &self.supported_version {
+            Some(supported) => strip_v(supported),
+            None => return true,
+        };
+        let mut game = strip_v(game_version).split('.');
+        for component in supported.split('.') {
+            if component == "*" {
+                return true;
+            }
+            if game.next() != Some(component) {
+                return false;
+            }
+        }
+        game.next().is_none()
+    }
+
+    /// Write the descriptor in Clausewitz syntax
+    ///
+    /// Quoted strings can't contain quotes, so values containing one are rejected like in
+    /// [`validate`](#method.validate).
+    pub fn write(&self) -> Result<Vec<u8>, Error> {
+        let mut out = String::new();
+        let fields = [
+            ("version", self.version.as_ref()),
+            ("name", Some(&self.name)),
+            ("supported_version", self.supported_version.as_ref()),
+            ("path", self.path.as_ref()),
+            ("archive", self.archive.as_ref()),
+            ("picture", self.picture.as_ref()),
+            ("remote_file_id", self.remote_file_id.as_ref()),
+        ];
+        for (key, value) in fields.iter() {
+            if let Some(value) = value {
+                out.push_str(&format!("{}={}\n", key, quote(key, value)?));
+            }
+        }
+        for (key, values) in [("tags", &self.tags), ("dependencies", &self.dependencies)].iter() {
+            if !values.is_empty() {
+                out.push_str(&format!("{}={{\n", key));
+                for value in values.iter() {
+                    out.push_str(&format!("\t{}\n", quote(key, value)?));
+                }
+                out.push_str("}\n");
+            }
+        }
+        for path in &self.replace_path {
+            out.push_str(&format!("replace_path={}\n", quote("replace_path", path)?));
+        }
+        Ok(out.into_bytes())
+    }
+
+    /// Write the descriptor as a `metadata.json`
+    pub fn to_metadata_json(&self) -> String {
+        let mut map = Map::new();
+        map.insert("name".to_string(), Value::from(self.name.clone()));
+        let mut optional = |key: &str, value: &Option<String>| {
+            if let Some(value) = value {
+                map.insert(key.to_string(), Value::from(value.clone()));
+            }
+        };
+        optional("id", &self.remote_file_id);
+        optional("version", &self.version);
+        optional("supported_game_version", &self.supported_version);
+        optional("picture", &self.picture);
+        map.insert("tags".to_string(), Value::from(self.tags.clone()));
+        if !self.replace_path.is_empty() {
+            let mut custom = Map::new();
+            custom.insert("replace_paths".to_string(), Value::from(self.replace_path.clone()));
+            map.insert("game_custom_data".to_string(), Value::Object(custom));
+        }
+        serde_json::to_string_pretty(&Value::Object(map)).unwrap()
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    ErrorKind::InvalidValue(format!("mod descriptor: {}", message)).into()
+}
+
+fn strip_v(version: &str) -> &str {
+    version.trim_start_matches('v')
+}
+
+/// The tokenizer ends a quoted string at the next quote, so values can't contain one
+fn without_quotes<'a>(key: &str, value: &'a str) -> Result<&'a str, Error> {
+    if value.contains('"') {
+        bail!(invalid(&format!("`{}` `{}` can't contain quotes", key, value)));
+    }
+    Ok(value)
+}
+
+fn quote(key: &str, value: &str) -> Result<String, Error> {
+    Ok(format!("\"{}\"", without_quotes(key, value)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTOR: &[u8] = b"version=\"1.2\"\ntags={\n\t\"Gameplay\"\n\t\"Historical\"\n}\nname=\"My Mod\"\n\
+        supported_version=\"v1.35.*\"\npath=\"mod/my_mod\"\nreplace_path=\"common/ideas\"\n\
+        replace_path=\"history/countries\"\ndependencies={ \"Base Mod\" }\nunknown = { a = b }\n";
+
+    fn descriptor() -> ModDescriptor {
+        ModDescriptor {
+            name: "My Mod".to_string(),
+            version: Some("1.2".to_string()),
+            supported_version: Some("v1.35.*".to_string()),
+            path: Some("mod/my_mod".to_string()),
+            archive: None,
+            tags: vec!["Gameplay".to_string(), "Historical".to_string()],
+            replace_path: vec!["common/ideas".to_string(), "history/countries".to_string()],
+            dependencies: vec!["Base Mod".to_string()],
+            picture: None,
+            remote_file_id: None,
+        }
+    }
+
+    #[test]
+    fn test_from_slice() {
+        assert_eq!(ModDescriptor::from_slice(DESCRIPTOR).unwrap(), descriptor());
+        assert_eq!(
+            ModDescriptor::from_slice(b"name = { a b }").unwrap_err().to_string(),
+            "invalid value type: mod descriptor: `name` must be a single value"
+        );
+        assert_eq!(
+            ModDescriptor::from_slice(b"name = \"x\" tags = {").unwrap_err().to_string(),
+            "error[unclosed-block]: unclosed block"
+        );
+    }
+
+    #[test]
+    fn test_metadata_json() {
+        let json = br#"{
+            "name": "My Mod",
+            "id": "123456",
+            "version": "1.2",
+            "supported_game_version": "1.9.*",
+            "short_description": "",
+            "tags": ["Gameplay"],
+            "relationships": [],
+            "game_custom_data": { "multiplayer_synchronized": true, "replace_paths": ["common/laws"] }
+        }"#;
+        let descriptor = ModDescriptor::from_metadata_json(json).unwrap();
+        assert_eq!(descriptor.name, "My Mod");
+        assert_eq!(descriptor.remote_file_id.as_deref(), Some("123456"));
+        assert_eq!(descriptor.supported_version.as_deref(), Some("1.9.*"));
+        assert_eq!(descriptor.tags, vec!["Gameplay"]);
+        assert_eq!(descriptor.replace_path, vec!["common/laws"]);
+        assert_eq!(
+            ModDescriptor::from_metadata_json(descriptor.to_metadata_json().as_bytes()).unwrap(),
+            descriptor
+        );
+        assert_eq!(
+            ModDescriptor::from_metadata_json(br#"{ "name": "x", "tags": "a" }"#)
+                .unwrap_err()
+                .to_string(),
+            "invalid value type: mod descriptor: `tags` must be a list of strings"
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let out = descriptor().write().unwrap();
+        assert_eq!(
+            String::from_utf8(out.clone()).unwrap(),
+            "version=\"1.2\"\nname=\"My Mod\"\nsupported_version=\"v1.35.*\"\npath=\"mod/my_mod\"\n\
+             tags={\n\t\"Gameplay\"\n\t\"Historical\"\n}\ndependencies={\n\t\"Base Mod\"\n}\n\
+             replace_path=\"common/ideas\"\nreplace_path=\"history/countries\"\n"
+        );
+        assert_eq!(ModDescriptor::from_slice(&out).unwrap(), descriptor());
+
+        let mut quoted = descriptor();
+        quoted.tags.push("\"".to_string());
+        assert_eq!(
+            quoted.write().unwrap_err().to_string(),
+            "invalid value type: mod descriptor: `tags` `\"` can't contain quotes"
+        );
+    }
+
+    #[test]
+    fn test_supports() {
+        let mut descriptor = descriptor();
+        assert!(descriptor.supports("1.35.3"));
+        assert!(descriptor.supports("v1.35.0.1"));
+        assert!(!descriptor.supports("1.34.5"));
+        assert!(!descriptor.supports("1.3"));
+        descriptor.supported_version = Some("1.35.3".to_string());
+        assert!(descriptor.supports("1.35.3"));
+        assert!(!descriptor.supports("1.35.3.1"));
+        descriptor.supported_version = None;
+        assert!(descriptor.supports("1.0"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(descriptor().validate().is_ok());
+        let error = |f: &dyn Fn(&mut ModDescriptor)| {
+            let mut descriptor = descriptor();
+            f(&mut descriptor);
+            descriptor.validate().unwrap_err().to_string()
+        };
+        assert_eq!(
+            error(&|d| d.name = " ".to_string()),
+            "invalid value type: mod descriptor: `name` is missing"
+        );
+        assert_eq!(
+            error(&|d| d.supported_version = Some("1.*.3".to_string())),
+            "invalid value type: mod descriptor: invalid `supported_version` `1.*.3`, expected a version like `1.35.*`"
+        );
+        assert_eq!(
+            error(&|d| d.archive = Some("mod.zip".to_string())),
+            "invalid value type: mod descriptor: only one of `path` and `archive` may be set"
+        );
+        assert_eq!(
+            error(&|d| d.replace_path.push("../common".to_string())),
+            "invalid value type: mod descriptor: `replace_path` `../common` must be relative to the game directory"
+        );
+        assert_eq!(
+            error(&|d| d.name = "My \"Best\" Mod".to_string()),
+            "invalid value type: mod descriptor: `name` `My \"Best\" Mod` can't contain quotes"
+        );
+        assert_eq!(
+            error(&|d| d.tags.push("\"".to_string())),
+            "invalid value type: mod descriptor: `tags` `\"` can't contain quotes"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Located messages about a source file
+//!
+//! Diagnostics point to a byte [**Span**](../token/struct.Span.html) of the source, a
+//! [**LineIndex**](struct.LineIndex.html) turns it into lines and columns for display.
+
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A message about a span of a source file
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A short identifier of the kind of problem, like `unknown-key`
+    pub code: String,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &str, span: Span, message: String) -> Self {
+        Self {
+            severity,
+            code: code.to_string(),
+            span,
+            message,
+        }
+    }
+
+    pub fn error(code: &str, span: Span, message: String) -> Self {
+        Self::new(Severity::Error, code, span, message)
+    }
+
+    pub fn warning(code: &str, span: Span, message: String) -> Self {
+        Self::new(Severity::Warning, code, span, message)
+    }
+
+    /// Format as `file:line:column: severity[code]: message`, with lines and columns starting at 1
+    pub fn to_string_in(&self, file: &str, index: &LineIndex) -> String {
+        let position = index.position(self.span.start);
+        format!("{}:{}:{}: {}", file, position.line + 1, position.column + 1, self)
+    }
+}
+
+impl Display for Diagnostic {
+    /// Like `severity[code]: message`
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A diagnostic in one of several files
+pub struct Problem {
+    pub file: PathBuf,
+    pub diagnostic: Diagnostic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+/// A position in a source file, both starting at 0
+///
+/// The column counts bytes, a tab is one column.
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+/// The line starts of a source file for mapping byte offsets to positions
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(buf: &[u8]) -> Self {
+        let line_starts = Some(0)
+            .into_iter()
+            .chain(buf.iter().enumerate().filter(|(_, b)| **b == b'\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { line_starts }
+    }
+
+    /// The position of a byte offset
+    pub fn position(&self, offset: usize) -> Position {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        Position {
+            line,
+            column: offset - self.line_starts[line],
+        }
+    }
+
+    /// The byte offset of a position, clamped to the start of the following line
+    pub fn offset(&self, position: Position, len: usize) -> usize {
+        match self.line_starts.get(position.line) {
+            Some(start) => {
+                let end = self.line_starts.get(position.line + 1).cloned().unwrap_or(len);
+                (start + position.column).min(end)
+            }
+            None => len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_index() {
+        let buf = b"a=1\r\nb={\n\tc=2\n}";
+        let index = LineIndex::new(buf);
+        assert_eq!(index.position(0), Position { line: 0, column: 0 });
+        assert_eq!(index.position(4), Position { line: 0, column: 4 });
+        assert_eq!(index.position(5), Position { line: 1, column: 0 });
+        assert_eq!(index.position(10), Position { line: 2, column: 1 });
+        assert_eq!(index.position(buf.len()), Position { line: 3, column: 1 });
+        assert_eq!(index.offset(Position { line: 2, column: 1 }, buf.len()), 10);
+        assert_eq!(index.offset(Position { line: 0, column: 10 }, buf.len()), 5);
+        assert_eq!(index.offset(Position { line: 9, column: 0 }, buf.len()), buf.len());
+    }
+
+    #[test]
+    fn test_display() {
+        let diagnostic = Diagnostic::error("unknown-key", Span::new(6, 7), "unknown key `b`".to_string());
+        assert_eq!(diagnostic.to_string(), "error[unknown-key]: unknown key `b`");
+        let index = LineIndex::new(b"a = 1\nb = 2");
+        assert_eq!(
+            diagnostic.to_string_in("test.txt", &index),
+            "test.txt:2:1: error[unknown-key]: unknown key `b`"
+        );
+    }
+}
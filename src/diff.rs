@@ -0,0 +1,542 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Structural diff between two `ClVal` trees
+//!
+//! Keys which occur once in one tree and several times in the other are
+//! compared as lists, so gaining a second `add_core` shows up as an added item
+//! instead of a changed value. Losing all but one shows up as removed items and
+//! a [**Collapsed**](enum.ChangeKind.html#variant.Collapsed) change, which tells
+//! it apart from an explicit list left with one item. Lists of dicts can
+//! optionally be matched by an id key, which keeps reordered or inserted entries
+//! from showing up as changes to every following item.
+//!
+//! ```
+//! extern crate clausewitz_parser;
+//!
+//! use clausewitz_parser::diff::diff;
+//! use clausewitz_parser::parse;
+//!
+//! fn main() {
+//!     let old = parse(b"a = { treasury = 10 add_core = PRU }").unwrap();
+//!     let new = parse(b"a = { treasury = 12 add_core = PRU add_core = BRA }").unwrap();
+//!     let changes: Vec<_> = diff(&old, &new).iter().map(|c| c.to_string()).collect();
+//!     assert_eq!(changes, vec!["+ a.add_core[1] = BRA", "~ a.treasury: 10 -> 12"]);
+//! }
+//! ```
+//!
+//! The changes can be applied to another tree with [**apply**](fn.apply.html).
+
+use clval::{ClKey, ClVal};
+use error::{Error, ErrorKind};
+use json::to_json;
+use query::{Path, PathSegment};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::mem;
+use std::slice;
+use writer::write_value;
+
+#[derive(Debug, Clone, PartialEq)]
+/// What happened to the value at the path of a [**Change**](struct.Change.html)
+pub enum ChangeKind {
+    /// A value only present in the new tree
+    Added(ClVal),
+    /// A value only present in the old tree
+    Removed(ClVal),
+    /// A value present in both trees, with the old and the new value
+    Changed(ClVal, ClVal),
+    /// A key which occurs several times in the old tree and once in the new one, with the value
+    /// left
+    ///
+    /// The other values show up as removed list items, this turns the list left into a single value.
+    Collapsed(ClVal),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single difference between two trees
+pub struct Change {
+    pub path: Path,
+    pub kind: ChangeKind,
+}
+
+impl Change {
+    /// The change as a JSON object with `op` (`add`, `remove`, `change` or `collapse`), `path` and
+    /// `value` or `old` and `new`
+    pub fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        let op = match self.kind {
+            ChangeKind::Added(_) => "add",
+            ChangeKind::Removed(_) => "remove",
+            ChangeKind::Changed(..) => "change",
+            ChangeKind::Collapsed(_) => "collapse",
+        };
+        object.insert("op".to_string(), Value::String(op.to_string()));
+        object.insert("path".to_string(), Value::String(self.path.to_string()));
+        match &self.kind {
+            ChangeKind::Added(value) | ChangeKind::Removed(value) | ChangeKind::Collapsed(value) => {
+                object.insert("value".to_string(), to_json(value));
+            }
+            ChangeKind::Changed(old, new) => {
+                object.insert("old".to_string(), to_json(old));
+                object.insert("new".to_string(), to_json(new));
+            }
+        }
+        Value::Object(object)
+    }
+}
+
+impl Display for Change {
+    /// One line per change like `+ path = value`, `- path = value`, `~ path: old -> new` or
+    /// `~ path: collapsed to value`
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match &self.kind {
+            ChangeKind::Added(value) => write!(f, "+ {} = {}", self.path, text(value)),
+            ChangeKind::Removed(value) => write!(f, "- {} = {}", self.path, text(value)),
+            ChangeKind::Changed(old, new) => write!(f, "~ {}: {} -> {}", self.path, text(old), text(new)),
+            ChangeKind::Collapsed(value) => write!(f, "~ {}: collapsed to {}", self.path, text(value)),
+        }
+    }
+}
+
+fn text(value: &ClVal) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value, 0);
+    out
+}
+
+#[derive(Debug, Clone, Default)]
+/// Options for [**diff_with**](fn.diff_with.html)
+pub struct DiffOptions {
+    pub(crate) id_key: Option<String>,
+}
+
+impl DiffOptions {
+    /// Construct the default options, lists are compared by position
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Match the dicts in lists by the value of `key` instead of their position
+    ///
+    /// Only used for lists where every item is a dict with a scalar value for `key`.
+    pub fn match_by(mut self, key: &str) -> Self {
+        self.id_key = Some(key.to_string());
+        self
+    }
+}
+
+/// The changes turning `old` into `new`, with the default options
+pub fn diff(old: &ClVal, new: &ClVal) -> Vec<Change> {
+    diff_with(old, new, &DiffOptions::default())
+}
+
+/// The changes turning `old` into `new`
+///
+/// Dict entries are compared in key order, so the result is deterministic.
+pub fn diff_with(old: &ClVal, new: &ClVal, options: &DiffOptions) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_value(&Path::new(), old, new, options, &mut changes);
+    changes
+}
+
+fn diff_value(path: &Path, old: &ClVal, new: &ClVal, options: &DiffOptions, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (ClVal::Dict(old), ClVal::Dict(new)) => diff_dict(path, old, new, options, changes),
+        (ClVal::List(old), ClVal::List(new)) => diff_list(path, old, new, options, changes),
+        // a key which occurs once in one tree and several times in the other
+        (ClVal::List(old), new) if old.len() > 1 => {
+            diff_list(path, old, slice::from_ref(new), options, changes);
+            changes.push(Change {
+                path: path.clone(),
+                kind: ChangeKind::Collapsed(new.clone()),
+            });
+        }
+        (old, ClVal::List(new)) if new.len() > 1 => {
+            diff_list(path, slice::from_ref(old), new, options, changes)
+        }
+        (old, new) => {
+            if old != new {
+                changes.push(Change {
+                    path: path.clone(),
+                    kind: ChangeKind::Changed(old.clone(), new.clone()),
+                });
+            }
+        }
+    }
+}
+
+fn diff_dict(
+    path: &Path,
+    old: &HashMap<ClKey, ClVal>,
+    new: &HashMap<ClKey, ClVal>,
+    options: &DiffOptions,
+    changes: &mut Vec<Change>,
+) {
+    let mut keys: Vec<_> = old.keys().chain(new.keys()).collect::<HashSet<_>>().into_iter().collect();
+    keys.sort();
+    for key in keys {
+        let path = path.join(PathSegment::Key(key.clone()));
+        match (old.get(key), new.get(key)) {
+            (Some(old), Some(new)) => diff_value(&path, old, new, options, changes),
+            (Some(old), None) => changes.push(Change {
+                path,
+                kind: ChangeKind::Removed(old.clone()),
+            }),
+            (None, Some(new)) => changes.push(Change {
+                path,
+                kind: ChangeKind::Added(new.clone()),
+            }),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+fn diff_list(path: &Path, old: &[ClVal], new: &[ClVal], options: &DiffOptions, changes: &mut Vec<Change>) {
+    if let Some(key) = &options.id_key {
+        if let (Some(old_ids), Some(new_ids)) = (ids(old, key), ids(new, key)) {
+            return diff_list_by_id(path, key, old, &old_ids, new, &new_ids, options, changes);
+        }
+    }
+
+    for (index, (old, new)) in old.iter().zip(new).enumerate() {
+        diff_value(&path.join(PathSegment::Index(index)), old, new, options, changes);
+    }
+    for (index, old) in old.iter().enumerate().skip(new.len()) {
+        changes.push(Change {
+            path: path.join(PathSegment::Index(index)),
+            kind: ChangeKind::Removed(old.clone()),
+        });
+    }
+    for (index, new) in new.iter().enumerate().skip(old.len()) {
+        changes.push(Change {
+            path: path.join(PathSegment::Index(index)),
+            kind: ChangeKind::Added(new.clone()),
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn diff_list_by_id(
+    path: &Path,
+    key: &str,
+    old: &[ClVal],
+    old_ids: &[String],
+    new: &[ClVal],
+    new_ids: &[String],
+    options: &DiffOptions,
+    changes: &mut Vec<Change>,
+) {
+    let segment = |id: &String| {
+        path.join(PathSegment::Match {
+            key: key.to_string(),
+            value: id.clone(),
+        })
+    };
+    for (old, id) in old.iter().zip(old_ids) {
+        match new_ids.iter().position(|new_id| new_id == id) {
+            Some(position) => diff_value(&segment(id), old, &new[position], options, changes),
+            None => changes.push(Change {
+                path: segment(id),
+                kind: ChangeKind::Removed(old.clone()),
+            }),
+        }
+    }
+    for (new, id) in new.iter().zip(new_ids) {
+        if !old_ids.contains(id) {
+            changes.push(Change {
+                path: segment(id),
+                kind: ChangeKind::Added(new.clone()),
+            });
+        }
+    }
+}
+
+/// Apply changes as returned by [**diff**](fn.diff.html) to a tree
+///
+/// The old values of removed and changed values aren't checked, so a diff can be applied to a
+/// tree which differs from the one it was made from. Items added to a single value turn it into
+/// a list, like a repeated key does. A [**Collapsed**](enum.ChangeKind.html#variant.Collapsed)
+/// change turns a list left with one item back into a single value and leaves longer lists alone.
+/// Fails if a path doesn't exist.
+pub fn apply(value: &mut ClVal, changes: &[Change]) -> Result<(), Error> {
+    // removing list items shifts the following ones, so remove from the back after everything else
+    for change in changes.iter().filter(|c| !is_removal(c) && !is_collapse(c)) {
+        apply_change(value, change)?;
+    }
+    for change in changes.iter().rev().filter(|c| is_removal(c)) {
+        apply_change(value, change)?;
+    }
+    // and lists are collapsed once their items are removed
+    for change in changes.iter().filter(|c| is_collapse(c)) {
+        apply_change(value, change)?;
+    }
+    Ok(())
+}
+
+fn is_removal(change: &Change) -> bool {
+    matches!(change.kind, ChangeKind::Removed(_))
+}
+
+fn is_collapse(change: &Change) -> bool {
+    matches!(change.kind, ChangeKind::Collapsed(_))
+}
+
+fn apply_change(root: &mut ClVal, change: &Change) -> Result<(), Error> {
+    let not_found = || Error::from(ErrorKind::PathNotFound(change.path.to_string()));
+    let (last, parents) = match change.path.segments().split_last() {
+        Some(split) => split,
+        None => {
+            return match &change.kind {
+                ChangeKind::Changed(_, new) => {
+                    *root = new.clone();
+                    Ok(())
+                }
+                _ => Err(not_found()),
+            }
+        }
+    };
+    let parent = parents
+        .iter()
+        .try_fold(root, |value, segment| resolve_mut(value, segment))
+        .ok_or_else(not_found)?;
+
+    match (&change.kind, last) {
+        (ChangeKind::Added(new), PathSegment::Key(key)) => {
+            parent.as_dict_mut()?.insert(key.clone(), new.clone());
+        }
+        (ChangeKind::Added(new), PathSegment::Index(index)) => push_item(parent, *index, new.clone()),
+        (ChangeKind::Added(new), PathSegment::Match { .. }) => push_item(parent, usize::MAX, new.clone()),
+        (ChangeKind::Removed(_), PathSegment::Key(key)) => {
+            parent.as_dict_mut()?.remove(key).ok_or_else(not_found)?;
+        }
+        (ChangeKind::Removed(_), segment) => match parent {
+            ClVal::List(list) => {
+                let position = match segment {
+                    PathSegment::Index(index) if *index < list.len() => *index,
+                    PathSegment::Match { key, value } => list
+                        .iter()
+                        .position(|item| has_id(item, key, value))
+                        .ok_or_else(not_found)?,
+                    _ => return Err(not_found()),
+                };
+                list.remove(position);
+            }
+            // a single value counts as a list with one item
+            value => {
+                resolve_mut(value, segment).ok_or_else(not_found)?;
+                *value = ClVal::List(Vec::new());
+            }
+        },
+        (ChangeKind::Changed(_, new), segment) => {
+            *resolve_mut(parent, segment).ok_or_else(not_found)? = new.clone();
+        }
+        (ChangeKind::Collapsed(_), segment) => {
+            let value = resolve_mut(parent, segment).ok_or_else(not_found)?;
+            let item = match value {
+                ClVal::List(list) if list.len() == 1 => list.remove(0),
+                _ => return Ok(()),
+            };
+            *value = item;
+        }
+    }
+    Ok(())
+}
+
+/// The value at a single step of a path, a single value counts as a list with one item
+pub(crate) fn resolve_mut<'a>(value: &'a mut ClVal, segment: &PathSegment) -> Option<&'a mut ClVal> {
+    match (value, segment) {
+        (ClVal::Dict(dict), PathSegment::Key(key)) => dict.get_mut(key),
+        (ClVal::List(list), PathSegment::Index(index)) => list.get_mut(*index),
+        (ClVal::List(list), PathSegment::Match { key, value }) => {
+            list.iter_mut().find(|item| has_id(item, key, value))
+        }
+        (value, PathSegment::Index(0)) => Some(value),
+        (value, PathSegment::Match { key, value: id }) if has_id(value, key, id) => Some(value),
+        _ => None,
+    }
+}
+
+fn has_id(item: &ClVal, key: &str, id: &str) -> bool {
+    item.get(key).and_then(scalar_text).as_deref() == Some(id)
+}
+
+/// Insert an item at `index` or the end, turning a single value into a list
+fn push_item(parent: &mut ClVal, index: usize, item: ClVal) {
+    match parent {
+        ClVal::List(list) => list.insert(index.min(list.len()), item),
+        value => {
+            let first = mem::replace(value, ClVal::List(Vec::new()));
+            *value = ClVal::List(if index == 0 { vec![item, first] } else { vec![first, item] });
+        }
+    }
+}
+
+/// The ids of all items, if every item is a dict with a unique scalar id
+pub(crate) fn ids(list: &[ClVal], key: &str) -> Option<Vec<String>> {
+    let mut ids = Vec::with_capacity(list.len());
+    for item in list {
+        let id = item.get(key).and_then(scalar_text)?;
+        if ids.contains(&id) {
+            return None;
+        }
+        ids.push(id);
+    }
+    Some(ids)
+}
+
+pub(crate) fn scalar_text(value: &ClVal) -> Option<String> {
+    match value {
+        ClVal::Identifier(s) | ClVal::String(s) => Some(s.clone()),
+        ClVal::Integer(i) => Some(i.to_string()),
+        ClVal::Float(f) => Some(f.to_string()),
+        ClVal::Date(d) => Some(d.to_string()),
+        ClVal::Bool(b) => Some(if *b { "yes" } else { "no" }.to_string()),
+        ClVal::List(_) | ClVal::Dict(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(old: &str, new: &str, options: &DiffOptions) -> Vec<String> {
+        let old = ::parse(old.as_bytes()).unwrap();
+        let new = ::parse(new.as_bytes()).unwrap();
+        diff_with(&old, &new, options).iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn test_diff_dict() {
+        let changes = changes(
+            "a = { b = 1 c = 2 d = { e = yes } }",
+            "a = { b = 1 c = 3 d = { f = \"x\" } }",
+            &DiffOptions::new(),
+        );
+        assert_eq!(
+            changes,
+            vec!["~ a.c: 2 -> 3", "- a.d.e = yes", "+ a.d.f = \"x\""]
+        );
+    }
+
+    #[test]
+    fn test_diff_equal() {
+        assert!(changes("a = { b = { 1 2 } }", "a = { b = { 1 2 } }", &DiffOptions::new()).is_empty());
+    }
+
+    #[test]
+    fn test_diff_list() {
+        let changes = changes("a = { l = { 1 2 3 } }", "a = { l = { 1 5 } }", &DiffOptions::new());
+        assert_eq!(changes, vec!["~ a.l[1]: 2 -> 5", "- a.l[2] = 3"]);
+    }
+
+    #[test]
+    fn test_diff_implicit_list() {
+        let changes = changes(
+            "a = { core = PRU core = BRA }",
+            "a = { core = PRU }",
+            &DiffOptions::new(),
+        );
+        assert_eq!(changes, vec!["- a.core[1] = BRA", "~ a.core: collapsed to PRU"]);
+    }
+
+    #[test]
+    fn test_diff_type_change() {
+        let changes = changes("a = { b = 1 }", "a = { b = { c = 1 } }", &DiffOptions::new());
+        assert_eq!(changes, vec!["~ a.b: 1 -> {\n\tc=1\n}"]);
+    }
+
+    #[test]
+    fn test_diff_by_id() {
+        let old = "a = { u = { id = 1 s = 10 } u = { id = 2 s = 20 } }";
+        let new = "a = { u = { id = 3 s = 30 } u = { id = 2 s = 25 } }";
+        assert_eq!(
+            changes(old, new, &DiffOptions::new().match_by("id")),
+            vec!["- a.u[id=1] = {\n\tid=1\n\ts=10\n}", "~ a.u[id=2].s: 20 -> 25", "+ a.u[id=3] = {\n\tid=3\n\ts=30\n}"]
+        );
+        // without the option the items are compared by position
+        assert_eq!(
+            changes(old, new, &DiffOptions::new()),
+            vec!["~ a.u[0].id: 1 -> 3", "~ a.u[0].s: 10 -> 30", "~ a.u[1].s: 20 -> 25"]
+        );
+    }
+
+    fn apply_diff(old: &str, new: &str, options: &DiffOptions) {
+        let mut old = ::parse(old.as_bytes()).unwrap();
+        let new = ::parse(new.as_bytes()).unwrap();
+        let changes = diff_with(&old, &new, options);
+        apply(&mut old, &changes).unwrap();
+        assert_eq!(old, new);
+    }
+
+    #[test]
+    fn test_apply() {
+        apply_diff(
+            "a = { b = 1 c = 2 d = { e = yes } }",
+            "a = { b = 1 c = 3 d = { f = \"x\" } g = 1.5 }",
+            &DiffOptions::new(),
+        );
+        apply_diff("a = { l = { 1 2 3 4 } }", "a = { l = { 5 2 } }", &DiffOptions::new());
+        apply_diff("a = { l = { 1 } }", "a = { l = { 1 2 3 } }", &DiffOptions::new());
+        apply_diff("a = { c = PRU }", "a = { c = PRU c = BRA c = HAB }", &DiffOptions::new());
+        apply_diff("a = { c = PRU c = BRA }", "a = { c = PRU }", &DiffOptions::new());
+        apply_diff("a = { c = PRU c = BRA c = HAB }", "a = { c = HAB }", &DiffOptions::new());
+        apply_diff("a = { l = { 1 2 } }", "a = { l = { } }", &DiffOptions::new());
+        // explicit lists keep a single item
+        apply_diff("a = { l = { 1 2 } }", "a = { l = { 1 } }", &DiffOptions::new());
+        apply_diff("a = { l = { 1 } }", "a = { l = 1 }", &DiffOptions::new());
+        apply_diff("a = { l = 1 }", "a = { l = { 1 } }", &DiffOptions::new());
+        let old = "a = { u = { id = 1 } u = { id = 2 } }";
+        apply_diff(old, "a = { u = { id = 2 } }", &DiffOptions::new().match_by("id"));
+        let old = "a = { u = { id = 1 s = 10 } u = { id = 2 s = 20 } u = { id = 4 } }";
+        let new = "a = { u = { id = 2 s = 25 } u = { id = 3 s = 30 } }";
+        apply_diff(old, new, &DiffOptions::new().match_by("id"));
+        apply_diff(old, new, &DiffOptions::new());
+    }
+
+    #[test]
+    fn test_apply_missing_path() {
+        let mut value = ::parse(b"a = { b = 1 }").unwrap();
+        let change = Change {
+            path: Path::new()
+                .join(PathSegment::Key(ClKey::from("x")))
+                .join(PathSegment::Key(ClKey::from("y"))),
+            kind: ChangeKind::Removed(ClVal::Integer(1)),
+        };
+        let err = apply(&mut value, &[change]).unwrap_err();
+        assert_eq!(err.to_string(), "key path not found: x.y");
+    }
+
+    #[test]
+    fn test_change_to_json() {
+        let change = Change {
+            path: Path::new().join(PathSegment::Key(ClKey::Identifier("a".to_string()))),
+            kind: ChangeKind::Changed(ClVal::Integer(1), ClVal::Integer(2)),
+        };
+        let expected: Value = ::serde_json::from_str(r#"{"op": "change", "path": "a", "old": 1, "new": 2}"#).unwrap();
+        assert_eq!(change.to_json(), expected);
+
+        let change = Change {
+            path: Path::new().join(PathSegment::Key(ClKey::Identifier("core".to_string()))),
+            kind: ChangeKind::Collapsed(ClVal::Identifier("PRU".to_string())),
+        };
+        let expected: Value = ::serde_json::from_str(r#"{"op": "collapse", "path": "core", "value": "PRU"}"#).unwrap();
+        assert_eq!(change.to_json(), expected);
+    }
+}
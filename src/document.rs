@@ -0,0 +1,44 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Parsed documents
+
+use clval::ClVal;
+use error::Error;
+use writer::Writer;
+
+#[derive(Clone, Debug, PartialEq)]
+/// A parsed document
+///
+/// Holds the parsed values together with the information needed to write the file back
+/// the way it was read.
+pub struct Document {
+    /// `true` if the input started with a UTF-8 BOM
+    pub bom: bool,
+    /// The parsed values, always a `Dict`
+    pub values: ClVal,
+}
+
+impl Document {
+    /// Write the document, re-emitting the BOM if the input had one
+    ///
+    /// Fails if a string or key contains a quote, see [**Writer::write**](../writer/struct.Writer.html#method.write).
+    pub fn write(&self) -> Result<Vec<u8>, Error> {
+        Writer::new().bom(self.bom).write(&self.values)
+    }
+}
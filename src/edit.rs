@@ -0,0 +1,242 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Source preserving edits
+//!
+//! The [**Editor**](struct.Editor.html) changes values in a buffer without touching anything else:
+//! comments, ordering and whitespace stay as they are, only the byte ranges of the edited values
+//! are replaced.
+
+use clval::ClVal;
+use cst::{SyntaxNode, SyntaxTree};
+use error::{Error, ErrorKind};
+use token::Span;
+use writer::{check_quotes, write_value};
+
+/// A source preserving editor
+///
+/// Key paths are resolved against the [**SyntaxTree**](../cst/struct.SyntaxTree.html).
+/// A segment matches the text of a key, quoted keys are matched without their quotes.
+/// If a key occurs more than once in a block, the last occurrence is used, like the parser keeps
+/// the last value of a key repeated at the top level.
+///
+/// # Example
+///
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::edit::Editor;
+///
+/// fn main() {
+///     let buf = b"PRU={\n\ttreasury = 120.500 # gold\n}\n";
+///     let mut editor = Editor::new(buf);
+///     editor.set_raw(&["PRU", "treasury"], "99.000").unwrap();
+///
+///     assert_eq!(editor.apply(), b"PRU={\n\ttreasury = 99.000 # gold\n}\n".to_vec());
+/// }
+/// ```
+pub struct Editor<'buf> {
+    tree: SyntaxTree<'buf>,
+    edits: Vec<(Span, Vec<u8>)>,
+}
+
+impl<'buf> Editor<'buf> {
+    /// Construct a new `Editor` for the provided buffer
+    pub fn new(buf: &'buf [u8]) -> Self {
+        Self {
+            tree: SyntaxTree::parse(buf),
+            edits: Vec::new(),
+        }
+    }
+
+    /// The syntax tree of the unedited buffer
+    pub fn tree(&self) -> &SyntaxTree<'buf> {
+        &self.tree
+    }
+
+    /// Find the `Entry` node at the end of a key path
+    pub fn find(&self, path: &[&str]) -> Option<&SyntaxNode> {
+        let (first, rest) = path.split_first()?;
+        let mut entry = self.find_entry(self.tree.root(), first)?;
+        for segment in rest {
+            let block = entry.value()?.as_node()?;
+            entry = self.find_entry(block, segment)?;
+        }
+        Some(entry)
+    }
+
+    fn find_entry<'a>(&self, node: &'a SyntaxNode, key: &str) -> Option<&'a SyntaxNode> {
+        node.entries()
+            .filter(|e| e.key().map(|k| self.tree.scalar(k) == key).unwrap_or(false))
+            .last()
+    }
+
+    /// Replace the value at `path` with `value`
+    ///
+    /// Fails if a string or key in `value` contains a quote.
+    pub fn set(&mut self, path: &[&str], value: &ClVal) -> Result<(), Error> {
+        check_quotes(value)?;
+        let depth = path.len().saturating_sub(1);
+        let mut text = String::new();
+        write_value(&mut text, value, depth);
+        self.set_raw(path, &text)
+    }
+
+    /// Replace the value at `path` with the raw text `text`
+    ///
+    /// Use this to keep a specific notation, like the three decimals of floats in save games.
+    pub fn set_raw(&mut self, path: &[&str], text: &str) -> Result<(), Error> {
+        let span = {
+            let entry = self
+                .find(path)
+                .ok_or_else(|| ErrorKind::PathNotFound(path.join(".")))?;
+            match entry.value() {
+                Some(value) => value.span(),
+                None => bail!(ErrorKind::PathNotFound(path.join("."))),
+            }
+        };
+        self.replace(span, text.as_bytes())
+    }
+
+    /// Replace the bytes of `span` with `text`
+    ///
+    /// Replacing the same span again overrides the previous edit, overlapping edits are an error.
+    pub fn replace(&mut self, span: Span, text: &[u8]) -> Result<(), Error> {
+        if span.end > self.tree.source().len() || span.start > span.end {
+            bail!("span out of bounds");
+        }
+        if let Some(edit) = self.edits.iter_mut().find(|e| e.0 == span) {
+            edit.1 = text.to_vec();
+            return Ok(());
+        }
+        if self
+            .edits
+            .iter()
+            .any(|e| e.0.start < span.end && span.start < e.0.end)
+        {
+            bail!("overlapping edit");
+        }
+        let idx = self
+            .edits
+            .iter()
+            .position(|e| e.0.start > span.start)
+            .unwrap_or(self.edits.len());
+        self.edits.insert(idx, (span, text.to_vec()));
+        Ok(())
+    }
+
+    /// The spans of all edits in source order
+    pub fn edited_spans(&self) -> Vec<Span> {
+        self.edits.iter().map(|e| e.0).collect()
+    }
+
+    /// Produce the edited buffer
+    pub fn apply(&self) -> Vec<u8> {
+        let source = self.tree.source();
+        let mut buf = Vec::with_capacity(source.len());
+        let mut pos = 0;
+        for (span, text) in &self.edits {
+            buf.extend_from_slice(&source[pos..span.start]);
+            buf.extend_from_slice(text);
+            pos = span.end;
+        }
+        buf.extend_from_slice(&source[pos..]);
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clval::ClKey;
+    use cst::SyntaxKind;
+    use std::collections::HashMap;
+
+    const SAVE: &[u8] = b"date=1597.1.1\n# the countries\ncountries={\n\tPRU={\n\t\ttreasury=120.500\n\t\t\"name\" = \"Prussia\" # quoted\n\t}\n}\n";
+
+    #[test]
+    fn test_set_raw() {
+        let mut editor = Editor::new(SAVE);
+        editor.set_raw(&["countries", "PRU", "treasury"], "99.000").unwrap();
+        editor.set_raw(&["date"], "1600.1.1").unwrap();
+        assert_eq!(
+            editor.apply(),
+            b"date=1600.1.1\n# the countries\ncountries={\n\tPRU={\n\t\ttreasury=99.000\n\t\t\"name\" = \"Prussia\" # quoted\n\t}\n}\n".to_vec()
+        );
+        assert_eq!(editor.edited_spans(), vec![Span::new(5, 13), Span::new(60, 67)]);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut editor = Editor::new(SAVE);
+        editor
+            .set(&["countries", "PRU", "name"], &ClVal::String("Brandenburg".to_string()))
+            .unwrap();
+        let mut dict = HashMap::new();
+        dict.insert(ClKey::Identifier("a".to_string()), ClVal::Integer(1));
+        editor.set(&["countries", "PRU", "treasury"], &ClVal::Dict(dict)).unwrap();
+        assert_eq!(
+            editor.apply(),
+            b"date=1597.1.1\n# the countries\ncountries={\n\tPRU={\n\t\ttreasury={\n\t\t\ta=1\n\t\t}\n\t\t\"name\" = \"Brandenburg\" # quoted\n\t}\n}\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_set_repeated_key() {
+        let buf = b"a = 1\nb = { c = 2 }\na = 3\nb = { c = 4 }\n";
+        let mut editor = Editor::new(buf);
+        editor.set(&["a"], &ClVal::Integer(5)).unwrap();
+        editor.set(&["b", "c"], &ClVal::Integer(6)).unwrap();
+        let edited = editor.apply();
+        assert_eq!(edited, b"a = 1\nb = { c = 2 }\na = 5\nb = { c = 6 }\n".to_vec());
+        let values = ::parse(&edited).unwrap();
+        assert_eq!(values.get_path(&["a"]), Some(&ClVal::Integer(5)));
+        assert_eq!(values.get_path(&["b", "c"]), Some(&ClVal::Integer(6)));
+    }
+
+    #[test]
+    fn test_set_twice() {
+        let mut editor = Editor::new(SAVE);
+        editor.set_raw(&["date"], "1600.1.1").unwrap();
+        editor.set_raw(&["date"], "1700.1.1").unwrap();
+        assert!(editor.apply().starts_with(b"date=1700.1.1\n"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut editor = Editor::new(SAVE);
+        assert_eq!(
+            editor.set_raw(&["countries", "FRA"], "{}").unwrap_err().to_string(),
+            "key path not found: countries.FRA"
+        );
+        let entry = editor.find(&["countries", "PRU"]).unwrap();
+        assert_eq!(entry.value().unwrap().kind(), SyntaxKind::Block);
+        editor.set_raw(&["countries", "PRU", "treasury"], "1.000").unwrap();
+        assert_eq!(
+            editor.set_raw(&["countries", "PRU"], "{}").unwrap_err().to_string(),
+            "overlapping edit"
+        );
+        assert_eq!(
+            editor
+                .set(&["countries", "PRU", "name"], &ClVal::String("\"Old\" Fritz".to_string()))
+                .unwrap_err()
+                .to_string(),
+            "invalid value type: string containing a quote: \"Old\" Fritz"
+        );
+    }
+}
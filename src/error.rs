@@ -23,6 +23,7 @@ error_chain! {
         Fmt(::std::fmt::Error);
         ParseInt(::std::num::ParseIntError);
         ParseFloat(::std::num::ParseFloatError);
+        Json(::serde_json::Error);
     }
 
     errors {
@@ -35,5 +36,25 @@ error_chain! {
             display("invalid value type: {}", t)
         }
         InvalidToken
+        UnexpectedEof {
+            description("unexpected end of input")
+            display("unexpected end of input")
+        }
+        InvalidQuery(q: String) {
+            description("invalid query")
+            display("invalid query: {}", q)
+        }
+        PathNotFound(p: String) {
+            description("key path not found")
+            display("key path not found: {}", p)
+        }
+        Syntax(d: ::diagnostic::Diagnostic) {
+            description("syntax error")
+            display("{}", d)
+        }
+        InvalidSchema(s: String) {
+            description("invalid schema")
+            display("invalid schema: {}", s)
+        }
     }
 }
\ No newline at end of file
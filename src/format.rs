@@ -0,0 +1,286 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The Formatter
+//!
+//! Normalizes the layout of Clausewitz script while keeping comments:
+//!
+//! * one tab of indentation per block
+//! * `key = value` with single spaces around the equals sign
+//! * an opening curly bracket on the line of its key, the closing one on a line of its own
+//! * at most one blank line in a row, none at the start or end of a block
+//! * commas are dropped
+//!
+//! Blocks written on a single line which only contain plain values or `key = value` pairs
+//! (`color = { 12 34 56 }`, `limit = { tag = FRA }`) stay on a single line.
+//! Plain values of a list keep their line breaks, so long lists don't become one value per line.
+
+use cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxTree};
+use token::BOM;
+
+/// Format a syntax tree
+///
+/// Syntax errors aren't reported, an unclosed block gets its closing curly bracket and a stray one
+/// is kept, so check [**SyntaxTree::diagnostics**](../cst/struct.SyntaxTree.html#method.diagnostics)
+/// for errors first.
+///
+/// # Example
+///
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::cst::SyntaxTree;
+/// use clausewitz_parser::format::format;
+///
+/// fn main() {
+///     let tree = SyntaxTree::parse(b"a={b=c # comment\n\n\n d ={1 2}}");
+///     assert_eq!(format(&tree), b"a = {\n\tb = c # comment\n\n\td = { 1 2 }\n}\n".to_vec());
+/// }
+/// ```
+pub fn format(tree: &SyntaxTree) -> Vec<u8> {
+    let mut formatter = Formatter {
+        tree,
+        out: Vec::with_capacity(tree.source().len()),
+    };
+    if tree.has_bom() {
+        formatter.out.extend_from_slice(BOM);
+    }
+    let root = tree.root();
+    let written = formatter.items(root.children(), 0);
+    if written {
+        formatter.out.push(b'\n');
+    }
+    formatter.out
+}
+
+/// Returns `true` if the tree is formatted already
+pub fn is_formatted(tree: &SyntaxTree) -> bool {
+    format(tree) == tree.source()
+}
+
+struct Formatter<'a, 'buf: 'a> {
+    tree: &'a SyntaxTree<'buf>,
+    out: Vec<u8>,
+}
+
+impl<'a, 'buf> Formatter<'a, 'buf> {
+    fn text(&mut self, element: &SyntaxElement) {
+        let text = self.tree.text(element.span());
+        self.out.extend_from_slice(text);
+    }
+
+    fn comment(&mut self, element: &SyntaxElement) {
+        let text = self.tree.text(element.span());
+        let end = text
+            .iter()
+            .rposition(|c| !(*c == b' ' || *c == b'\t' || *c == b'\r'))
+            .map(|p| p + 1)
+            .unwrap_or(0);
+        self.out.extend_from_slice(&text[..end]);
+    }
+
+    fn line_break(&mut self, indent: usize, blank: bool) {
+        self.out.push(b'\n');
+        if blank {
+            self.out.push(b'\n');
+        }
+        for _ in 0..indent {
+            self.out.push(b'\t');
+        }
+    }
+
+    /// Write the items of a document or block, every item starts on a new line
+    ///
+    /// The first item is written without a line break in front of it.
+    /// Returns `true` if anything has been written.
+    fn items(&mut self, children: &[SyntaxElement], indent: usize) -> bool {
+        let mut newlines = 0;
+        let mut written = false;
+        let mut previous_scalar = false;
+
+        for child in children {
+            let kind = child.kind();
+            match kind {
+                SyntaxKind::Bom | SyntaxKind::Whitespace | SyntaxKind::Comma => continue,
+                SyntaxKind::Newline => {
+                    newlines += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let same_line = newlines == 0
+                && written
+                && (kind == SyntaxKind::Comment || (previous_scalar && kind.is_scalar()));
+            if same_line {
+                self.out.push(b' ');
+            } else if written {
+                self.line_break(indent, newlines > 1);
+            }
+
+            match child {
+                SyntaxElement::Node(node) if node.kind() == SyntaxKind::Entry => self.entry(node, indent),
+                SyntaxElement::Node(node) => self.block(node, indent),
+                _ if kind == SyntaxKind::Comment => self.comment(child),
+                _ => self.text(child),
+            }
+
+            previous_scalar = kind.is_scalar();
+            written = true;
+            newlines = 0;
+        }
+
+        written
+    }
+
+    fn entry(&mut self, node: &SyntaxNode, indent: usize) {
+        // comments between the key and the value are moved in front of the entry
+        for child in node.children() {
+            if child.kind() == SyntaxKind::Comment {
+                self.comment(child);
+                self.line_break(indent, false);
+            }
+        }
+
+        if let Some(key) = node.children().first() {
+            self.text(key);
+        }
+        match node.value() {
+            Some(value) => {
+                self.out.extend_from_slice(b" = ");
+                match value {
+                    SyntaxElement::Node(block) => self.block(block, indent),
+                    _ => self.text(value),
+                }
+            }
+            None => {
+                if node.equals().is_some() {
+                    self.out.extend_from_slice(b" =");
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, node: &SyntaxNode, indent: usize) {
+        let children = node.children();
+        let inner = match children.last() {
+            Some(last) if last.kind() == SyntaxKind::RightCurly && children.len() > 1 => &children[1..children.len() - 1],
+            _ => &children[1..],
+        };
+
+        if inner.iter().all(|c| c.kind().is_trivia() || c.kind() == SyntaxKind::Comma) {
+            self.out.extend_from_slice(b"{ }");
+        } else if is_inline(inner) {
+            self.out.push(b'{');
+            for child in inner {
+                match child {
+                    SyntaxElement::Node(entry) => {
+                        self.out.push(b' ');
+                        self.entry(entry, indent);
+                    }
+                    _ if child.kind().is_scalar() => {
+                        self.out.push(b' ');
+                        self.text(child);
+                    }
+                    _ => {}
+                }
+            }
+            self.out.extend_from_slice(b" }");
+        } else {
+            self.out.push(b'{');
+            self.line_break(indent + 1, false);
+            self.items(inner, indent + 1);
+            self.line_break(indent, false);
+            self.out.push(b'}');
+        }
+    }
+}
+
+/// Returns `true` if a block can stay on a single line
+fn is_inline(children: &[SyntaxElement]) -> bool {
+    children.iter().all(|child| match child {
+        SyntaxElement::Node(node) => {
+            node.kind() == SyntaxKind::Entry
+                && node.children().iter().all(|c| match c.kind() {
+                    SyntaxKind::Whitespace | SyntaxKind::Equals => true,
+                    kind => kind.is_scalar(),
+                })
+        }
+        SyntaxElement::Token(token) => match token.kind {
+            SyntaxKind::Whitespace | SyntaxKind::Comma => true,
+            kind => kind.is_scalar(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::from_utf8;
+
+    fn fmt(buf: &[u8]) -> String {
+        from_utf8(&format(&SyntaxTree::parse(buf))).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(fmt(b"a=b"), "a = b\n");
+        assert_eq!(fmt(b"a=b c = d"), "a = b\nc = d\n");
+        assert_eq!(fmt(b"\n\n  a  =   b\n\n\n\nc=d\n\n"), "a = b\n\nc = d\n");
+        assert_eq!(fmt(b"a = {}"), "a = { }\n");
+        assert_eq!(fmt(b"a = {\n}"), "a = { }\n");
+        assert_eq!(fmt(b"color = {1, 2, 3}"), "color = { 1 2 3 }\n");
+        assert_eq!(fmt(b"limit = {  tag = FRA }"), "limit = { tag = FRA }\n");
+        assert_eq!(fmt(b"a { b = c\n}"), "a = {\n\tb = c\n}\n");
+        assert_eq!(
+            fmt(b"a = {\n\n  b = { c = { d = e } }\n\n}"),
+            "a = {\n\tb = {\n\t\tc = { d = e }\n\t}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_lists() {
+        assert_eq!(fmt(b"l = {\n1 2 3\n4 5\n}"), "l = {\n\t1 2 3\n\t4 5\n}\n");
+        assert_eq!(fmt(b"l = { { a } { b } }"), "l = {\n\t{ a }\n\t{ b }\n}\n");
+    }
+
+    #[test]
+    fn test_format_comments() {
+        assert_eq!(fmt(b"# head  \na=b # trailing\n"), "# head\na = b # trailing\n");
+        assert_eq!(
+            fmt(b"a = { # open\n# inside\nb = c }"),
+            "a = {\n\t# open\n\t# inside\n\tb = c\n}\n"
+        );
+        assert_eq!(fmt(b"a = # odd\n{ b = c }"), "# odd\na = { b = c }\n");
+    }
+
+    #[test]
+    fn test_format_bom() {
+        assert_eq!(fmt(b"\xEF\xBB\xBFa=b"), "\u{feff}a = b\n");
+    }
+
+    #[test]
+    fn test_format_stable() {
+        let buf = include_bytes!("../examples/test");
+        let tree = SyntaxTree::parse(buf);
+        let formatted = format(&tree);
+        let formatted_tree = SyntaxTree::parse(&formatted);
+        assert_eq!(formatted_tree.to_clval().unwrap(), tree.to_clval().unwrap());
+        assert!(is_formatted(&formatted_tree));
+    }
+}
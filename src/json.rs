@@ -0,0 +1,318 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! JSON conversion
+//!
+//! [**ClVals**](../clval/enum.ClVal.html) are mapped to JSON like this:
+//!
+//! | ClVal        | JSON                                          |
+//! |--------------|-----------------------------------------------|
+//! | `Integer`    | number                                        |
+//! | `Float`      | number, always with a fraction (`5.0`)        |
+//! | `Bool`       | `true` / `false`                              |
+//! | `String`     | string                                        |
+//! | `Identifier` | string                                        |
+//! | `Date`       | string in the form `year.month.day`           |
+//! | `List`       | array                                         |
+//! | `Dict`       | object                                        |
+//!
+//! Dict keys become strings the same way: integers and dates are written as text, quoted strings
+//! lose their quotes. If a `String` and an `Identifier` key of the same name end up in the same
+//! object, the latter one in key order wins.
+//!
+//! Repeated keys (`add_core=PRU add_core=BRA`) are parsed into a `List`, so they become an array.
+//! A key which occurs only once stays a single value, consumers should be prepared for both.
+//!
+//! # Reversible mapping
+//!
+//! [`to_json_reversible`](fn.to_json_reversible.html) keeps everything needed to get the same
+//! `ClVal` back with [`from_json`](fn.from_json.html): quoted strings keep their quotes
+//! (`"\"PRU\""`), while identifiers and dates are written without them (`"PRU"`, `"1597.1.1"`).
+//! Keys follow the same rule.
+//!
+//! `from_json` types unquoted strings the same way the parser types unquoted values and keys,
+//! so `"1597.1.1"` becomes a `Date`, `"yes"` a `Bool` and `"PRU"` an `Identifier`.
+//! Plain JSON, which never has quotes inside its strings, can be imported as well: strings which
+//! can't be written without quotes, like `"Rights of Man"` or `""`, become `String`s.
+//! Clausewitz strings have no escapes, so strings and keys containing a quote are rejected.
+
+use clval::{ClKey, ClVal};
+use error::{Error, ErrorKind};
+use parser::{untyped_key, untyped_value};
+use serde_json::{self, Map, Number, Value};
+use token::is_bare;
+use writer::without_quotes;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Convert a `ClVal` into a JSON value
+///
+/// # Example
+///
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::json::to_json;
+/// use clausewitz_parser::parse;
+///
+/// fn main() {
+///     let values = parse(b"date=1597.1.1 player=\"PRU\" PRU={ treasury=120.5 core=PRU core=BRA }").unwrap();
+///     let json = to_json(&values);
+///
+///     assert_eq!(
+///         json.to_string(),
+///         r#"{"PRU":{"core":["PRU","BRA"],"treasury":120.5},"date":"1597.1.1","player":"PRU"}"#
+///     );
+/// }
+/// ```
+pub fn to_json(value: &ClVal) -> Value {
+    convert(value, false)
+}
+
+/// Convert a `ClVal` into a JSON value which keeps quoted strings apart from identifiers
+///
+/// # Example
+///
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::json::{from_json, to_json_reversible};
+/// use clausewitz_parser::parse;
+///
+/// fn main() {
+///     let values = parse(b"player=\"PRU\" tag=PRU").unwrap();
+///     let json = to_json_reversible(&values);
+///
+///     assert_eq!(json.to_string(), r#"{"player":"\"PRU\"","tag":"PRU"}"#);
+///     assert_eq!(from_json(&json).unwrap(), values);
+/// }
+/// ```
+pub fn to_json_reversible(value: &ClVal) -> Value {
+    convert(value, true)
+}
+
+fn convert(value: &ClVal, reversible: bool) -> Value {
+    match value {
+        ClVal::Integer(i) => Value::from(*i),
+        ClVal::Float(f) => float_to_json(*f),
+        ClVal::String(s) if reversible => Value::String(quote(s)),
+        ClVal::String(s) => Value::String(s.clone()),
+        ClVal::Date(d) => Value::String(d.to_string()),
+        ClVal::Bool(b) => Value::Bool(*b),
+        ClVal::Identifier(i) => Value::String(i.clone()),
+        ClVal::List(list) => Value::Array(list.iter().map(|v| convert(v, reversible)).collect()),
+        ClVal::Dict(dict) => {
+            let mut entries: Vec<_> = dict.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            let mut map = Map::new();
+            for (key, value) in entries {
+                map.insert(key_to_json(key, reversible), convert(value, reversible));
+            }
+            Value::Object(map)
+        }
+    }
+}
+
+fn key_to_json(key: &ClKey, reversible: bool) -> String {
+    match key {
+        ClKey::Integer(i) => i.to_string(),
+        ClKey::String(s) if reversible => quote(s),
+        ClKey::String(s) => s.clone(),
+        ClKey::Date(d) => d.to_string(),
+        ClKey::Identifier(i) => i.clone(),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s)
+}
+
+/// Returns the inner string if `s` is quoted
+fn unquote(s: &str) -> Option<&str> {
+    if s.len() > 1 && s.starts_with('"') && s.ends_with('"') {
+        Some(&s[1..s.len() - 1])
+    } else {
+        None
+    }
+}
+
+fn float_to_json(f: f32) -> Value {
+    // go through the shortest decimal representation, otherwise 12.34 becomes 12.34000015258789
+    let f = f.to_string().parse::<f64>().unwrap_or_else(|_| f64::from(f));
+    Number::from_f64(f).map(Value::Number).unwrap_or(Value::Null)
+}
+
+/// Convert a JSON value into a `ClVal`
+///
+/// See the [module documentation](index.html#reversible-mapping) for how strings are typed.
+/// `null` and strings containing a quote can't be represented and integers have to fit into an
+/// `i32`.
+pub fn from_json(value: &Value) -> Result<ClVal, Error> {
+    let value = match value {
+        Value::Null => bail!(ErrorKind::InvalidValue("null".to_string())),
+        Value::Bool(b) => ClVal::Bool(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) if i >= i64::from(i32::MIN) && i <= i64::from(i32::MAX) => ClVal::Integer(i as i32),
+            Some(i) => bail!(ErrorKind::InvalidValue(format!("integer out of range: {}", i))),
+            None => ClVal::Float(n.as_f64().unwrap_or(f64::NAN) as f32),
+        },
+        Value::String(s) => match unquote(s) {
+            Some(s) => ClVal::String(without_quotes(s)?.to_string()),
+            None if is_bare(s.as_bytes()) => untyped_value(s.as_bytes()),
+            None => ClVal::String(without_quotes(s)?.to_string()),
+        },
+        Value::Array(list) => ClVal::List(list.iter().map(from_json).collect::<Result<_, _>>()?),
+        Value::Object(map) => {
+            let mut dict = HashMap::with_capacity(map.len());
+            for (key, value) in map {
+                dict.insert(key_from_json(key)?, from_json(value)?);
+            }
+            ClVal::Dict(dict)
+        }
+    };
+    Ok(value)
+}
+
+fn key_from_json(key: &str) -> Result<ClKey, Error> {
+    let key = match unquote(key) {
+        Some(s) => ClKey::String(without_quotes(s)?.to_string()),
+        None if is_bare(key.as_bytes()) => untyped_key(key.as_bytes()),
+        None => ClKey::String(without_quotes(key)?.to_string()),
+    };
+    Ok(key)
+}
+
+/// Read a JSON document and convert it into a `ClVal`
+pub fn from_json_reader<R: Read>(reader: R) -> Result<ClVal, Error> {
+    let value: Value = serde_json::from_reader(reader)?;
+    from_json(&value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clval::Date;
+
+    #[test]
+    fn test_to_json_scalars() {
+        assert_eq!(to_json(&ClVal::Integer(-12)), json_value("-12"));
+        assert_eq!(to_json(&ClVal::Float(12.34)), json_value("12.34"));
+        assert_eq!(to_json(&ClVal::Float(5.0)).to_string(), "5.0");
+        assert_eq!(to_json(&ClVal::Bool(true)), Value::Bool(true));
+        assert_eq!(to_json(&ClVal::String("Rights of Man".to_string())), json_value("\"Rights of Man\""));
+        assert_eq!(to_json(&ClVal::Identifier("PRU".to_string())), json_value("\"PRU\""));
+        assert_eq!(to_json(&ClVal::Date(Date::new(1597, 1, 1))), json_value("\"1597.1.1\""));
+    }
+
+    #[test]
+    fn test_to_json_collections() {
+        let mut inner = HashMap::new();
+        inner.insert(ClKey::Integer(12), ClVal::List(vec![ClVal::Integer(1), ClVal::Integer(2)]));
+        inner.insert(ClKey::Date(Date::new(1444, 11, 11)), ClVal::List(Vec::new()));
+        let mut dict = HashMap::new();
+        dict.insert(ClKey::String("quoted".to_string()), ClVal::Dict(inner));
+
+        assert_eq!(
+            to_json(&ClVal::Dict(dict)),
+            json_value(r#"{"quoted": {"12": [1, 2], "1444.11.11": []}}"#)
+        );
+    }
+
+    #[test]
+    fn test_to_json_document() {
+        let values = ::parse(include_bytes!("../examples/test")).unwrap();
+        let json = to_json(&values);
+        assert_eq!(json["player"], json_value("\"PRU\""));
+        assert_eq!(json["savegame_version"]["second"], json_value("24"));
+    }
+
+    #[test]
+    fn test_to_json_reversible() {
+        let values = ::parse(b"\"key\"=\"value\" 12=\"12\" 1444.11.11=\"1.5\" id=PRU").unwrap();
+        assert_eq!(
+            to_json_reversible(&values),
+            json_value(r#"{"12": "\"12\"", "1444.11.11": "\"1.5\"", "\"key\"": "\"value\"", "id": "PRU"}"#)
+        );
+    }
+
+    #[test]
+    fn test_from_json() {
+        let json = json_value(r#"{"a": 1, "b": 1.5, "c": 5.0, "d": "1597.1.1", "e": "yes", "f": "\"yes\"", "12": ["x", "\"\""]}"#);
+        let mut dict = HashMap::new();
+        dict.insert(ClKey::Identifier("a".to_string()), ClVal::Integer(1));
+        dict.insert(ClKey::Identifier("b".to_string()), ClVal::Float(1.5));
+        dict.insert(ClKey::Identifier("c".to_string()), ClVal::Float(5.0));
+        dict.insert(ClKey::Identifier("d".to_string()), ClVal::Date(Date::new(1597, 1, 1)));
+        dict.insert(ClKey::Identifier("e".to_string()), ClVal::Bool(true));
+        dict.insert(ClKey::Identifier("f".to_string()), ClVal::String("yes".to_string()));
+        dict.insert(
+            ClKey::Integer(12),
+            ClVal::List(vec![ClVal::Identifier("x".to_string()), ClVal::String("".to_string())]),
+        );
+        assert_eq!(from_json(&json).unwrap(), ClVal::Dict(dict));
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        assert_eq!(
+            from_json(&json_value("[null]")).unwrap_err().to_string(),
+            "invalid value type: null"
+        );
+        assert_eq!(
+            from_json(&json_value("4294967296")).unwrap_err().to_string(),
+            "invalid value type: integer out of range: 4294967296"
+        );
+        assert_eq!(
+            from_json(&json_value(r#"{"name": "\"Der \"Alte\" Fritz\""}"#)).unwrap_err().to_string(),
+            "invalid value type: string containing a quote: Der \"Alte\" Fritz"
+        );
+        assert_eq!(
+            from_json(&json_value(r#"{"a\"b": 1}"#)).unwrap_err().to_string(),
+            "invalid value type: string containing a quote: a\"b"
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        // Clausewitz -> JSON -> Clausewitz
+        let values = ::parse(include_bytes!("../examples/test")).unwrap();
+        let json = to_json_reversible(&values).to_string();
+        let imported = from_json_reader(json.as_bytes()).unwrap();
+        assert_eq!(imported, values);
+
+        let buf = ::Writer::new().write(&imported).unwrap();
+        assert_eq!(::parse(&buf).unwrap(), values);
+    }
+
+    #[test]
+    fn test_roundtrip_plain_json() {
+        // plain JSON -> Clausewitz -> ClVal
+        let json = json_value(r#"{"name": "Rights of Man", "empty": "", "tag": "PRU", "a b": {"c": "d e"}}"#);
+        let imported = from_json(&json).unwrap();
+        assert_eq!(imported.get("name"), Some(&ClVal::String("Rights of Man".to_string())));
+        assert_eq!(imported.get("empty"), Some(&ClVal::String("".to_string())));
+        assert_eq!(imported.get("tag"), Some(&ClVal::Identifier("PRU".to_string())));
+
+        let buf = ::Writer::new().write(&imported).unwrap();
+        assert_eq!(::parse(&buf).unwrap(), imported);
+    }
+
+    fn json_value(s: &str) -> Value {
+        ::serde_json::from_str(s).unwrap()
+    }
+}
@@ -20,6 +20,7 @@
 #[macro_use]
 extern crate error_chain;
 extern crate regex;
+extern crate serde_json;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
@@ -30,14 +31,38 @@ extern crate log;
 extern crate pretty_assertions;
 
 pub use clval::{ClKey, ClVal};
+pub use diagnostic::Diagnostic;
+pub use document::Document;
 pub use error::{Error, ErrorKind};
-pub use parser::Parser;
-pub use token::{LexerToken, Tokenizer};
+pub use parser::{Parser, ParserOptions};
+pub use token::{LexerToken, Span, Tokenizer};
+pub use writer::Writer;
 
 pub mod clval;
+pub mod coverage;
+pub mod cst;
+pub mod cwt;
+pub mod descriptor;
+pub mod diagnostic;
+pub mod diff;
+pub mod document;
+pub mod edit;
+pub mod format;
+pub mod json;
+pub mod lint;
+pub mod localisation;
+pub mod markup;
+pub mod merge;
+pub mod mod_stack;
+pub mod overrides;
 mod error;
 pub mod parser;
+pub mod query;
+pub mod schema;
 pub mod token;
+pub mod visit;
+mod walk;
+pub mod writer;
 
 /// Parse a buffer of bytes into [**ClVals**](clval/enum.ClVal.html)
 ///
@@ -58,3 +83,71 @@ pub fn parse(buf: &[u8]) -> Result<ClVal, Error> {
     let mut parser = Parser::new(tokenizer.tokenize());
     parser.parse()
 }
+
+/// Parse a buffer of bytes with [**ParserOptions**](parser/struct.ParserOptions.html)
+///
+/// # Example
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::{parse_with_options, ParserOptions};
+///
+/// fn main() {
+///     let result = parse_with_options(b"foo { bar = baz }", ParserOptions { strict: true });
+///     assert_eq!(result.unwrap_err().to_string(), "error[missing-equals]: missing `=` after `foo`");
+/// }
+/// ```
+pub fn parse_with_options(buf: &[u8], options: ParserOptions) -> Result<ClVal, Error> {
+    let tokenizer = Tokenizer::new(buf);
+    let mut parser = Parser::with_spans(tokenizer.tokenize_with_spans()).options(options);
+    parser.parse()
+}
+
+/// Parse a buffer of bytes, recovering from every syntax error
+///
+/// Returns the values which could be parsed and a [**Diagnostic**](diagnostic/struct.Diagnostic.html)
+/// for every problem the parser skipped over, see
+/// [`Parser::parse_with_diagnostics`](parser/struct.Parser.html#method.parse_with_diagnostics).
+///
+/// # Example
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::parse_with_diagnostics;
+///
+/// fn main() {
+///     let (values, diagnostics) = parse_with_diagnostics(b"foo = { bar = baz");
+///     assert_eq!(values["foo"]["bar"].to_str(), Some("baz"));
+///     assert_eq!(diagnostics[0].to_string(), "error[unclosed-block]: unclosed block");
+/// }
+/// ```
+pub fn parse_with_diagnostics(buf: &[u8]) -> (ClVal, Vec<Diagnostic>) {
+    let tokenizer = Tokenizer::new(buf);
+    let mut parser = Parser::with_spans(tokenizer.tokenize_with_spans());
+    parser.parse_with_diagnostics()
+}
+
+/// Parse a buffer of bytes into a [**Document**](document/struct.Document.html)
+///
+/// Like [`parse`](fn.parse.html), but also records whether the buffer started with a UTF-8 BOM.
+///
+/// # Example
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::parse_document;
+///
+/// fn main() {
+///     let document = parse_document(b"\xEF\xBB\xBFfoo=bar").unwrap();
+///     assert!(document.bom);
+///     assert_eq!(document.write().unwrap(), b"\xEF\xBB\xBFfoo=bar\n");
+/// }
+/// ```
+pub fn parse_document(buf: &[u8]) -> Result<Document, Error> {
+    let tokenizer = Tokenizer::new(buf);
+    let mut parser = Parser::new(tokenizer.tokenize());
+    Ok(Document {
+        bom: tokenizer.has_bom(),
+        values: parser.parse()?,
+    })
+}
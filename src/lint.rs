@@ -0,0 +1,522 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! A pluggable lint engine for script files
+//!
+//! A [**Linter**](struct.Linter.html) runs [**LintRules**](trait.LintRule.html) over a
+//! [**SyntaxTree**](../cst/struct.SyntaxTree.html). Every rule has an id and a default severity,
+//! both can be changed with a [**LintConfig**](struct.LintConfig.html), which is written in
+//! Clausewitz syntax:
+//!
+//! ```text
+//! rules = {
+//!     empty-block = off
+//!     bool-typo = error
+//!     duplicate-key = { severity = warning unique = { id name title desc } }
+//! }
+//! ```
+//!
+//! The built-in rules are:
+//!
+//! | id | default | |
+//! |---|---|---|
+//! | `duplicate-key` | error | a key which may only occur once is repeated in a block, the keys are set with `unique` |
+//! | `empty-block` | warning | a `key = { }` entry |
+//! | `bool-typo` | warning | a value like `yess` or `No` where `yes` or `no` was meant |
+//! | `missing-equals` | warning | a block without `=` between the key and the block, fixable |
+
+use clval::ClVal;
+use cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxTree};
+use diagnostic::{Diagnostic, Severity};
+use diff::scalar_text;
+use edit::Editor;
+use error::{Error, ErrorKind};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use token::Span;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A replacement of a span of the source
+pub struct Fix {
+    pub span: Span,
+    pub text: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A problem found by a rule, the diagnostic code is the id of the rule
+pub struct Lint {
+    pub diagnostic: Diagnostic,
+    pub fix: Option<Fix>,
+}
+
+impl Lint {
+    /// A lint with the default severity of the rule, which the linter sets
+    pub fn new(rule: &str, span: Span, message: String) -> Self {
+        Self {
+            diagnostic: Diagnostic::warning(rule, span, message),
+            fix: None,
+        }
+    }
+
+    pub fn with_fix(mut self, span: Span, text: &[u8]) -> Self {
+        self.fix = Some(Fix {
+            span,
+            text: text.to_vec(),
+        });
+        self
+    }
+}
+
+/// A lint rule
+pub trait LintRule {
+    /// The id used in diagnostics and in the configuration, like `empty-block`
+    fn id(&self) -> &'static str;
+
+    fn default_severity(&self) -> Severity;
+
+    /// Apply the rule specific settings of the configuration
+    fn configure(&mut self, _options: &ClVal) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Append the problems in a tree to `lints`
+    fn check(&self, tree: &SyntaxTree, lints: &mut Vec<Lint>);
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// The configuration of a rule, `severity: None` disables it
+pub struct RuleConfig {
+    pub severity: Option<Severity>,
+    /// The block of rule specific settings
+    pub options: Option<ClVal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// The configuration of the rules by id
+pub struct LintConfig {
+    pub rules: HashMap<String, RuleConfig>,
+}
+
+impl LintConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<LintConfig, Error> {
+        let path = path.as_ref();
+        let buf = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        LintConfig::from_slice(&buf).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    pub fn from_slice(buf: &[u8]) -> Result<LintConfig, Error> {
+        let values = SyntaxTree::parse(buf).to_clval()?;
+        let mut config = LintConfig::default();
+        let rules = match values.get("rules") {
+            Some(ClVal::Dict(rules)) => rules,
+            Some(_) => bail!(invalid("`rules` must be a block")),
+            None => return Ok(config),
+        };
+        for (id, value) in rules {
+            let (severity, options) = match value {
+                ClVal::Dict(_) => (value.get("severity"), Some(value.clone())),
+                value => (Some(value), None),
+            };
+            let severity = match severity.and_then(scalar_text).as_deref() {
+                Some("error") => Some(Severity::Error),
+                Some("warning") => Some(Severity::Warning),
+                Some("info") => Some(Severity::Info),
+                Some("off") | Some("no") => None,
+                Some(severity) => bail!(invalid(&format!("unknown severity `{}` of `{}`", severity, id))),
+                None => bail!(invalid(&format!("`{}` has no severity", id))),
+            };
+            config.rules.insert(id.to_string(), RuleConfig { severity, options });
+        }
+        Ok(config)
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    ErrorKind::InvalidValue(format!("lint configuration: {}", message)).into()
+}
+
+/// Runs lint rules with their configured severities
+pub struct Linter {
+    rules: Vec<(Box<dyn LintRule>, Option<Severity>)>,
+}
+
+impl Default for Linter {
+    /// A linter with the built-in rules
+    fn default() -> Self {
+        let mut linter = Linter::empty();
+        linter.add_rule(Box::new(DuplicateKey::default()));
+        linter.add_rule(Box::new(EmptyBlock));
+        linter.add_rule(Box::new(BoolTypo));
+        linter.add_rule(Box::new(MissingEquals));
+        linter
+    }
+}
+
+impl Linter {
+    /// A linter with the built-in rules
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// A linter without any rules
+    pub fn empty() -> Self {
+        Linter { rules: Vec::new() }
+    }
+
+    /// Add a rule with its default severity
+    pub fn add_rule(&mut self, rule: Box<dyn LintRule>) {
+        let severity = rule.default_severity();
+        self.rules.push((rule, Some(severity)));
+    }
+
+    /// Apply a configuration, fails for unknown rules
+    pub fn configure(&mut self, config: &LintConfig) -> Result<(), Error> {
+        let mut ids: Vec<_> = config.rules.keys().collect();
+        ids.sort();
+        for id in ids {
+            let rule_config = &config.rules[id];
+            let (rule, severity) = self
+                .rules
+                .iter_mut()
+                .find(|(rule, _)| rule.id() == id)
+                .ok_or_else(|| invalid(&format!("unknown rule `{}`", id)))?;
+            *severity = rule_config.severity;
+            if let Some(options) = &rule_config.options {
+                rule.configure(options)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The lints of all enabled rules, in source order
+    pub fn lint(&self, tree: &SyntaxTree) -> Vec<Lint> {
+        let mut all = Vec::new();
+        for (rule, severity) in &self.rules {
+            let severity = match severity {
+                Some(severity) => *severity,
+                None => continue,
+            };
+            let mut lints = Vec::new();
+            rule.check(tree, &mut lints);
+            for mut lint in lints {
+                lint.diagnostic.severity = severity;
+                all.push(lint);
+            }
+        }
+        all.sort_by_key(|l| l.diagnostic.span.start);
+        all
+    }
+}
+
+/// Apply the fixes of lints to the source of a tree
+///
+/// Fixes overlapping an earlier one are skipped, linting the result again finds them.
+pub fn apply_fixes(tree: &SyntaxTree, lints: &[Lint]) -> Vec<u8> {
+    let mut editor = Editor::new(tree.source());
+    for fix in lints.iter().filter_map(|l| l.fix.as_ref()) {
+        let _ = editor.replace(fix.span, &fix.text);
+    }
+    editor.apply()
+}
+
+/// Call `f` for every node of a tree
+fn visit_nodes<F: FnMut(&SyntaxNode)>(node: &SyntaxNode, f: &mut F) {
+    f(node);
+    for child in node.children().iter().filter_map(SyntaxElement::as_node) {
+        visit_nodes(child, f);
+    }
+}
+
+/// Keys which may only occur once in a block
+pub struct DuplicateKey {
+    unique: Vec<String>,
+}
+
+impl Default for DuplicateKey {
+    fn default() -> Self {
+        let unique = ["id", "name", "title", "desc", "picture", "is_triggered_only", "trigger", "mean_time_to_happen"];
+        DuplicateKey {
+            unique: unique.iter().map(|k| k.to_string()).collect(),
+        }
+    }
+}
+
+impl LintRule for DuplicateKey {
+    fn id(&self) -> &'static str {
+        "duplicate-key"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn configure(&mut self, options: &ClVal) -> Result<(), Error> {
+        if let Some(unique) = options.get("unique") {
+            self.unique = unique.as_list_or_single().filter_map(scalar_text).collect();
+        }
+        Ok(())
+    }
+
+    fn check(&self, tree: &SyntaxTree, lints: &mut Vec<Lint>) {
+        visit_nodes(tree.root(), &mut |node| {
+            let mut seen = Vec::new();
+            for key in node.entries().filter_map(SyntaxNode::key) {
+                let text = tree.scalar(key);
+                if !self.unique.iter().any(|k| *k == text) {
+                    continue;
+                }
+                if seen.contains(&text) {
+                    lints.push(Lint::new(self.id(), key.span, format!("duplicate key `{}`", text)));
+                } else {
+                    seen.push(text);
+                }
+            }
+        });
+    }
+}
+
+/// `key = { }` entries
+pub struct EmptyBlock;
+
+impl LintRule for EmptyBlock {
+    fn id(&self) -> &'static str {
+        "empty-block"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, tree: &SyntaxTree, lints: &mut Vec<Lint>) {
+        visit_nodes(tree.root(), &mut |node| {
+            if node.kind() != SyntaxKind::Entry {
+                return;
+            }
+            if let Some(SyntaxElement::Node(block)) = node.value() {
+                let closed = block.children().last().map(|c| c.kind()) == Some(SyntaxKind::RightCurly);
+                if closed && block.items().next().is_none() {
+                    let key = node.key().map(|k| tree.scalar(k)).unwrap_or_default();
+                    lints.push(Lint::new(self.id(), node.span(), format!("`{}` is an empty block", key)));
+                }
+            }
+        });
+    }
+}
+
+/// Values which are almost `yes` or `no`
+///
+/// Without a schema there is no telling whether a key takes a bool, so the rule offers no fix.
+/// All-uppercase values are skipped as they are usually tags, like `NOR` or `YEM`.
+pub struct BoolTypo;
+
+impl LintRule for BoolTypo {
+    fn id(&self) -> &'static str {
+        "bool-typo"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, tree: &SyntaxTree, lints: &mut Vec<Lint>) {
+        visit_nodes(tree.root(), &mut |node| {
+            let value = match node.value() {
+                Some(SyntaxElement::Token(token)) if token.kind == SyntaxKind::Untyped => token,
+                _ => return,
+            };
+            let text = tree.scalar(value);
+            if let Some(fixed) = bool_typo(&text) {
+                let message = format!("`{}` should probably be `{}`", text, fixed);
+                lints.push(Lint::new(self.id(), value.span, message));
+            }
+        });
+    }
+}
+
+/// The bool a misspelled `yes` or `no` was meant to be
+fn bool_typo(text: &str) -> Option<&'static str> {
+    if text == "yes" || text == "no" || text.len() < 2 || !text.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    if text.chars().all(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+    let lower = text.to_ascii_lowercase();
+    ["yes", "no"]
+        .iter()
+        .find(|b| lower.starts_with(&b[..1]) && distance(&lower, b) <= 1)
+        .cloned()
+}
+
+/// The optimal string alignment distance, counting transpositions as one edit
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Blocks without `=` after the key, which the parser tolerates
+pub struct MissingEquals;
+
+impl LintRule for MissingEquals {
+    fn id(&self) -> &'static str {
+        "missing-equals"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, tree: &SyntaxTree, lints: &mut Vec<Lint>) {
+        visit_nodes(tree.root(), &mut |node| {
+            let key = match node.key() {
+                Some(key) if node.equals().is_none() && node.value().is_some() => key,
+                _ => return,
+            };
+            let mut fixed = tree.text(key.span).to_vec();
+            fixed.extend_from_slice(b" =");
+            let message = format!("missing `=` after `{}`", tree.scalar(key));
+            lints.push(Lint::new(self.id(), key.span, message).with_fix(key.span, &fixed));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(linter: &Linter, buf: &[u8]) -> Vec<String> {
+        let tree = SyntaxTree::parse(buf);
+        linter
+            .lint(&tree)
+            .iter()
+            .map(|l| format!("{} {}", String::from_utf8_lossy(tree.text(l.diagnostic.span)), l.diagnostic))
+            .collect()
+    }
+
+    #[test]
+    fn test_rules() {
+        let buf = b"e = {\n\tid = a.1\n\tid = a.2\n\tb = yess\n\tc = No\n\td = { }\n\te = nothing\n\toption { name = x }\n}\n";
+        assert_eq!(
+            lint(&Linter::new(), buf),
+            vec![
+                "id error[duplicate-key]: duplicate key `id`",
+                "yess warning[bool-typo]: `yess` should probably be `yes`",
+                "No warning[bool-typo]: `No` should probably be `no`",
+                "d = { } warning[empty-block]: `d` is an empty block",
+                "option warning[missing-equals]: missing `=` after `option`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bool_typo() {
+        assert_eq!(bool_typo("yse"), Some("yes"));
+        assert_eq!(bool_typo("Yse"), Some("yes"));
+        assert_eq!(bool_typo("YES"), None);
+        assert_eq!(bool_typo("NOR"), None);
+        assert_eq!(bool_typo("YEM"), None);
+        assert_eq!(bool_typo("noo"), Some("no"));
+        assert_eq!(bool_typo("yes"), None);
+        assert_eq!(bool_typo("n"), None);
+        assert_eq!(bool_typo("on"), None);
+        assert_eq!(bool_typo("nos1"), None);
+    }
+
+    #[test]
+    fn test_config() {
+        let config = LintConfig::from_slice(
+            b"# project settings\nrules = {\n\tempty-block = off\n\tbool-typo = error\n\tduplicate-key = { severity = info unique = { b } }\n}\n",
+        )
+        .unwrap();
+        let mut linter = Linter::new();
+        linter.configure(&config).unwrap();
+        assert_eq!(
+            lint(&linter, b"a = { b = 1 b = 2 id = 1 id = 2 c = noo d = { } }"),
+            vec![
+                "b info[duplicate-key]: duplicate key `b`",
+                "noo error[bool-typo]: `noo` should probably be `no`",
+            ]
+        );
+
+        let err = |buf: &[u8]| {
+            let config = LintConfig::from_slice(buf)?;
+            Linter::new().configure(&config)
+        };
+        assert_eq!(
+            err(b"rules = { foo = error }").unwrap_err().to_string(),
+            "invalid value type: lint configuration: unknown rule `foo`"
+        );
+        assert_eq!(
+            err(b"rules = { empty-block = loud }").unwrap_err().to_string(),
+            "invalid value type: lint configuration: unknown severity `loud` of `empty-block`"
+        );
+    }
+
+    #[test]
+    fn test_apply_fixes() {
+        let buf = b"a = { b = yess c = NOR }\nd {\n\te = ys\n}\n";
+        let tree = SyntaxTree::parse(buf);
+        let fixed = apply_fixes(&tree, &Linter::new().lint(&tree));
+        assert_eq!(String::from_utf8(fixed).unwrap(), "a = { b = yess c = NOR }\nd = {\n\te = ys\n}\n");
+    }
+
+    struct NoFoo;
+
+    impl LintRule for NoFoo {
+        fn id(&self) -> &'static str {
+            "no-foo"
+        }
+
+        fn default_severity(&self) -> Severity {
+            Severity::Info
+        }
+
+        fn check(&self, tree: &SyntaxTree, lints: &mut Vec<Lint>) {
+            for key in tree.root().entries().filter_map(SyntaxNode::key) {
+                if tree.scalar(key) == "foo" {
+                    lints.push(Lint::new(self.id(), key.span, "foo".to_string()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_rule() {
+        let mut linter = Linter::empty();
+        linter.add_rule(Box::new(NoFoo));
+        assert_eq!(lint(&linter, b"foo = yess"), vec!["foo info[no-foo]: foo"]);
+    }
+}
@@ -0,0 +1,499 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Paradox localisation files
+//!
+//! Localisation `.yml` files look like YAML, but are not:
+//!
+//! ```text
+//! l_english:
+//!  # a comment
+//!  my_event.1.t:0 "The "Great" Event"
+//!  my_event.1.d: "No version"
+//! ```
+//!
+//! A [**Localisation**](struct.Localisation.html) keeps the entries of every language in the
+//! order of the file together with the comments and the spacing, so writing it reproduces the
+//! file except for the changed entries. The texts are kept as written, with inner quotes and
+//! escapes like `\n`.
+
+use diagnostic::{Diagnostic, Severity};
+use error::{Error, ErrorKind};
+use std::collections::HashSet;
+use std::str;
+use token::{Span, BOM};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A localisation entry like `key:0 "text"`
+pub struct Entry {
+    pub key: String,
+    pub version: Option<u32>,
+    pub text: String,
+    /// From the key to the closing quote, empty for new entries
+    pub span: Span,
+    indent: String,
+    gap: String,
+    trailing: String,
+}
+
+impl Entry {
+    pub fn new(key: &str, version: Option<u32>, text: &str) -> Self {
+        Entry {
+            key: key.to_string(),
+            version,
+            text: text.to_string(),
+            span: Span::default(),
+            indent: " ".to_string(),
+            gap: " ".to_string(),
+            trailing: String::new(),
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        out.push_str(&self.indent);
+        out.push_str(&self.key);
+        out.push(':');
+        if let Some(version) = self.version {
+            out.push_str(&version.to_string());
+        }
+        out.push_str(&self.gap);
+        out.push('"');
+        out.push_str(&self.text);
+        out.push('"');
+        out.push_str(&self.trailing);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    Entry(Entry),
+    /// Blank lines, comments and lines which could not be parsed, as they were read
+    Raw(Vec<u8>),
+}
+
+impl Line {
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Line::Entry(entry) => {
+                let mut out = String::new();
+                entry.write(&mut out);
+                out.into_bytes()
+            }
+            Line::Raw(line) => line.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The entries below a header like `l_english:`
+pub struct Language {
+    /// The name without `l_`, like `english`
+    pub name: String,
+    /// The span of the header, empty for new languages
+    pub span: Span,
+    indent: String,
+    trailing: String,
+    lines: Vec<Line>,
+}
+
+impl Language {
+    pub fn new(name: &str) -> Self {
+        Language {
+            name: name.to_string(),
+            span: Span::default(),
+            indent: String::new(),
+            trailing: String::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// The entries in the order of the file
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry(entry) => Some(entry),
+            Line::Raw(_) => None,
+        })
+    }
+
+    /// The first entry with a key
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries().find(|entry| entry.key == key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        self.lines.iter_mut().find_map(|line| match line {
+            Line::Entry(entry) if entry.key == key => Some(entry),
+            _ => None,
+        })
+    }
+
+    /// Set the version and text of an entry, new entries are added after the last one
+    pub fn insert(&mut self, key: &str, version: Option<u32>, text: &str) {
+        if let Some(entry) = self.get_mut(key) {
+            entry.version = version;
+            entry.text = text.to_string();
+            return;
+        }
+        let position = self
+            .lines
+            .iter()
+            .rposition(|line| matches!(line, Line::Entry(_)))
+            .map_or(0, |i| i + 1);
+        self.lines.insert(position, Line::Entry(Entry::new(key, version, text)));
+    }
+
+    /// Remove the first entry with a key
+    pub fn remove(&mut self, key: &str) -> Option<Entry> {
+        let position = self
+            .lines
+            .iter()
+            .position(|line| matches!(line, Line::Entry(entry) if entry.key == key))?;
+        match self.lines.remove(position) {
+            Line::Entry(entry) => Some(entry),
+            Line::Raw(_) => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A localisation file
+pub struct Localisation {
+    /// `true` if the file starts with a UTF-8 BOM, which the games require
+    pub bom: bool,
+    pub languages: Vec<Language>,
+    /// The lines before the first language
+    preamble: Vec<Vec<u8>>,
+    /// `true` if the last line ends with a line break
+    newline: bool,
+}
+
+impl Default for Localisation {
+    fn default() -> Self {
+        Localisation {
+            bom: true,
+            languages: Vec::new(),
+            preamble: Vec::new(),
+            newline: true,
+        }
+    }
+}
+
+impl Localisation {
+    /// An empty file with a BOM
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Parse a file, failing on the first error
+    pub fn from_slice(buf: &[u8]) -> Result<Localisation, Error> {
+        let (localisation, diagnostics) = Localisation::parse(buf);
+        match diagnostics.into_iter().find(|d| d.severity == Severity::Error) {
+            Some(diagnostic) => bail!(ErrorKind::Syntax(diagnostic)),
+            None => Ok(localisation),
+        }
+    }
+
+    /// Parse a file, reporting every problem
+    ///
+    /// Lines which could not be parsed, including lines which aren't valid UTF-8, are kept as
+    /// they are.
+    pub fn parse(buf: &[u8]) -> (Localisation, Vec<Diagnostic>) {
+        let mut diagnostics = Vec::new();
+        let bom = buf.starts_with(BOM);
+        let start = if bom {
+            BOM.len()
+        } else {
+            let message = "localisation files must start with a UTF-8 BOM".to_string();
+            diagnostics.push(Diagnostic::warning("missing-bom", Span::new(0, 0), message));
+            0
+        };
+        let mut localisation = Localisation {
+            bom,
+            newline: buf[start..].ends_with(b"\n"),
+            ..Default::default()
+        };
+        let mut keys = HashSet::new();
+        let mut lines = buf[start..].split(|&c| c == b'\n').collect::<Vec<_>>();
+        if localisation.newline {
+            lines.pop();
+        }
+        let mut offset = start;
+        for line in lines {
+            let parsed = str::from_utf8(line)
+                .map_err(|e| {
+                    let offset = offset + e.valid_up_to();
+                    let message = "invalid UTF-8, localisation files must be UTF-8".to_string();
+                    Diagnostic::error("invalid-utf8", Span::new(offset, offset + 1), message)
+                })
+                .and_then(|line| parse_line(line, offset));
+            match parsed {
+                Ok(Parsed::Header(language)) => {
+                    keys.clear();
+                    localisation.languages.push(language);
+                }
+                Ok(Parsed::Line(line)) => match localisation.languages.last_mut() {
+                    Some(language) => {
+                        if let Line::Entry(entry) = &line {
+                            if !keys.insert(entry.key.clone()) {
+                                let message = format!("duplicate key `{}`", entry.key);
+                                diagnostics.push(Diagnostic::warning("duplicate-key", entry.span, message));
+                            }
+                        }
+                        language.lines.push(line);
+                    }
+                    None => {
+                        if let Line::Entry(entry) = &line {
+                            let message = format!("`{}` is outside of a language like `l_english:`", entry.key);
+                            diagnostics.push(Diagnostic::error("missing-language", entry.span, message));
+                        }
+                        localisation.preamble.push(line.to_bytes());
+                    }
+                },
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic);
+                    match localisation.languages.last_mut() {
+                        Some(language) => language.lines.push(Line::Raw(line.to_vec())),
+                        None => localisation.preamble.push(line.to_vec()),
+                    }
+                }
+            }
+            offset += line.len() + 1;
+        }
+
+        (localisation, diagnostics)
+    }
+
+    /// The first language with a name, like `english`
+    pub fn language(&self, name: &str) -> Option<&Language> {
+        self.languages.iter().find(|language| language.name == name)
+    }
+
+    pub fn language_mut(&mut self, name: &str) -> Option<&mut Language> {
+        self.languages.iter_mut().find(|language| language.name == name)
+    }
+
+    /// Write the file, unchanged entries, comments and spacing are written as they were read
+    pub fn write(&self) -> Vec<u8> {
+        let mut lines = self.preamble.clone();
+        for language in &self.languages {
+            lines.push(format!("{}l_{}:{}", language.indent, language.name, language.trailing).into_bytes());
+            lines.extend(language.lines.iter().map(Line::to_bytes));
+        }
+
+        let mut out = Vec::new();
+        if self.bom {
+            out.extend_from_slice(BOM);
+        }
+        out.extend_from_slice(&lines.join(&b'\n'));
+        if self.newline && !lines.is_empty() {
+            out.push(b'\n');
+        }
+        out
+    }
+}
+
+enum Parsed {
+    Header(Language),
+    Line(Line),
+}
+
+/// Parse a line starting at `offset` of the file
+fn parse_line(line: &str, offset: usize) -> Result<Parsed, Diagnostic> {
+    let content = line.trim_start();
+    if content.trim_end().is_empty() || content.starts_with('#') {
+        return Ok(Parsed::Line(Line::Raw(line.as_bytes().to_vec())));
+    }
+    let indent = &line[..line.len() - content.len()];
+    let start = offset + indent.len();
+    let line_span = Span::new(start, start + content.trim_end().len());
+
+    let key_len = content
+        .find(|c: char| c == ':' || c == '"' || c.is_whitespace())
+        .unwrap_or(content.len());
+    let key = &content[..key_len];
+    let rest = &content[key_len..];
+    if key.is_empty() || !rest.starts_with(':') {
+        let message = "expected an entry like `key:0 \"text\"`".to_string();
+        return Err(Diagnostic::error("invalid-entry", line_span, message));
+    }
+    let rest = &rest[1..];
+
+    let trimmed = rest.trim();
+    if key.starts_with("l_") && (trimmed.is_empty() || trimmed.starts_with('#')) {
+        return Ok(Parsed::Header(Language {
+            name: key[2..].to_string(),
+            span: Span::new(start, start + key_len + 1),
+            indent: indent.to_string(),
+            trailing: rest.to_string(),
+            lines: Vec::new(),
+        }));
+    }
+
+    let version_len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let version = match &rest[..version_len] {
+        "" => None,
+        version => Some(version.parse::<u32>().map_err(|e| {
+            let version_start = start + key_len + 1;
+            let span = Span::new(version_start, version_start + version_len);
+            Diagnostic::error("invalid-version", span, format!("invalid version: {}", e))
+        })?),
+    };
+    let rest = &rest[version_len..];
+    let text = rest.trim_start();
+    let gap = &rest[..rest.len() - text.len()];
+    if !text.starts_with('"') {
+        let message = format!("expected `\"` after `{}:`", key);
+        return Err(Diagnostic::error("missing-quote", line_span, message));
+    }
+    // inner quotes need no escaping, the text ends at the first quote followed by nothing but
+    // whitespace or a comment. `#` also starts formatting like `#R`, so a comment with an unpaired
+    // quote is part of the text
+    let end = text
+        .match_indices('"')
+        .skip(1)
+        .map(|(i, _)| i)
+        .find(|&i| {
+            let after = text[i + 1..].trim_start();
+            after.is_empty() || (after.starts_with('#') && after.matches('"').count().is_multiple_of(2))
+        })
+        .unwrap_or_else(|| text.rfind('"').unwrap());
+    if end == 0 {
+        let message = format!("the text of `{}` has no closing `\"`", key);
+        return Err(Diagnostic::error("unclosed-string", line_span, message));
+    }
+
+    let text_start = start + (content.len() - text.len());
+    Ok(Parsed::Line(Line::Entry(Entry {
+        key: key.to_string(),
+        version,
+        text: text[1..end].to_string(),
+        span: Span::new(start, text_start + end + 1),
+        indent: indent.to_string(),
+        gap: gap.to_string(),
+        trailing: text[end + 1..].to_string(),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &[u8] = b"\xEF\xBB\xBF# generated\nl_english:\n # events\n my_event.1.t:0 \"The \"Great\" Event\" # title\n my_event.1.d:   \"Line\\nbreak\"\r\n\n l_german:\n my_event.1.t:12 \"Das Ereignis\"\n";
+
+    #[test]
+    fn test_parse() {
+        let (localisation, diagnostics) = Localisation::parse(FILE);
+        assert_eq!(diagnostics, vec![]);
+        assert!(localisation.bom);
+        let names: Vec<_> = localisation.languages.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["english", "german"]);
+
+        let english = localisation.language("english").unwrap();
+        let entries: Vec<_> = english
+            .entries()
+            .map(|e| (e.key.as_str(), e.version, e.text.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("my_event.1.t", Some(0), "The \"Great\" Event"),
+                ("my_event.1.d", None, "Line\\nbreak"),
+            ]
+        );
+        let entry = english.get("my_event.1.t").unwrap();
+        assert_eq!(&FILE[entry.span.start..entry.span.end], &b"my_event.1.t:0 \"The \"Great\" Event\""[..]);
+        assert_eq!(localisation.language("german").unwrap().get("my_event.1.t").unwrap().version, Some(12));
+    }
+
+    #[test]
+    fn test_comment_with_quotes() {
+        let buf = b"\xEF\xBB\xBFl_english:\n a:0 \"Text\" # see \"b\"\n b:0 \"Say \"hi\" #R now#!\"\n";
+        let mut localisation = Localisation::from_slice(buf).unwrap();
+        let english = localisation.language("english").unwrap();
+        assert_eq!(english.get("a").unwrap().text, "Text");
+        assert_eq!(english.get("b").unwrap().text, "Say \"hi\" #R now#!");
+        assert_eq!(localisation.write(), &buf[..]);
+
+        localisation.language_mut("english").unwrap().insert("a", Some(0), "New");
+        assert_eq!(
+            String::from_utf8(localisation.write()).unwrap(),
+            "\u{feff}l_english:\n a:0 \"New\" # see \"b\"\n b:0 \"Say \"hi\" #R now#!\"\n"
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let mut localisation = Localisation::from_slice(FILE).unwrap();
+        assert_eq!(localisation.write(), FILE);
+
+        let english = localisation.language_mut("english").unwrap();
+        english.insert("my_event.1.t", Some(1), "The Event");
+        english.insert("my_event.1.a", Some(0), "OK");
+        assert_eq!(english.remove("my_event.1.d").unwrap().text, "Line\\nbreak");
+        assert_eq!(
+            String::from_utf8(localisation.write()).unwrap(),
+            "\u{feff}# generated\nl_english:\n # events\n my_event.1.t:1 \"The Event\" # title\n my_event.1.a:0 \"OK\"\n\n l_german:\n my_event.1.t:12 \"Das Ereignis\"\n"
+        );
+
+        let mut localisation = Localisation::new();
+        localisation.languages.push(Language::new("french"));
+        localisation.languages[0].insert("key", Some(0), "Texte");
+        assert_eq!(localisation.write(), &b"\xEF\xBB\xBFl_french:\n key:0 \"Texte\"\n"[..]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let buf = b"key:0 \"outside\"\nl_english:\n a:0 \"x\"\n a:1 \"y\"\n b \"no colon\"\n c:0 no quote\n d:0 \"open\n e:99999999999 \"x\"\n";
+        let (localisation, diagnostics) = Localisation::parse(buf);
+        let diagnostics: Vec<_> = diagnostics
+            .iter()
+            .map(|d| format!("{} {}", String::from_utf8_lossy(&buf[d.span.start..d.span.end]), d))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                " warning[missing-bom]: localisation files must start with a UTF-8 BOM",
+                "key:0 \"outside\" error[missing-language]: `key` is outside of a language like `l_english:`",
+                "a:1 \"y\" warning[duplicate-key]: duplicate key `a`",
+                "b \"no colon\" error[invalid-entry]: expected an entry like `key:0 \"text\"`",
+                "c:0 no quote error[missing-quote]: expected `\"` after `c:`",
+                "d:0 \"open error[unclosed-string]: the text of `d` has no closing `\"`",
+                "99999999999 error[invalid-version]: invalid version: number too large to fit in target type",
+            ]
+        );
+        // broken lines are kept
+        assert_eq!(localisation.write(), &buf[..]);
+
+        // lines which aren't UTF-8 are kept as they are and don't shift the following spans
+        let buf = b"\xEF\xBB\xBFl_english:\n a:0 \"Arm\xe9e \xe9\"\n b:0 \"ok\"\n";
+        let (localisation, diagnostics) = Localisation::parse(buf);
+        let diagnostics: Vec<_> = diagnostics.iter().map(|d| (d.span.start, d.to_string())).collect();
+        assert_eq!(
+            diagnostics,
+            vec![(23, "error[invalid-utf8]: invalid UTF-8, localisation files must be UTF-8".to_string())]
+        );
+        let b = localisation.language("english").unwrap().get("b").unwrap();
+        assert_eq!(&buf[b.span.start..b.span.end], &b"b:0 \"ok\""[..]);
+        assert_eq!(localisation.write(), &buf[..]);
+
+        assert_eq!(
+            Localisation::from_slice(b"\xEF\xBB\xBFl_english:\n a \"x\"").unwrap_err().to_string(),
+            "error[invalid-entry]: expected an entry like `key:0 \"text\"`"
+        );
+    }
+}
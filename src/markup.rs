@@ -0,0 +1,391 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The markup of localisation texts
+//!
+//! [`parse`](fn.parse.html) turns a text like `§YThe $COUNTRY$§! gains £gold£ [Root.GetName]`
+//! into [**Nodes**](enum.Node.html), a [**Renderer**](struct.Renderer.html) substitutes the
+//! variables and strips the colours or converts them to ANSI escape codes or HTML.
+//!
+//! Markup which is not closed, like a single `$`, is plain text. Colours which are not closed
+//! run until the end of the text.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::mem;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A part of a localisation text
+pub enum Node {
+    Text(String),
+    /// `$NAME$` or `$NAME|format$`
+    Variable { name: String, format: Option<String> },
+    /// `§Y...§!`
+    Color { color: char, children: Vec<Node> },
+    /// `£icon£`
+    Icon(String),
+    /// A data function like `[Root.GetName]`
+    Function(String),
+    /// The `\n` escape
+    NewLine,
+}
+
+impl Display for Node {
+    /// Write the node as markup
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Node::Text(text) => f.write_str(text),
+            Node::Variable { name, format: None } => write!(f, "${}$", name),
+            Node::Variable {
+                name,
+                format: Some(format),
+            } => write!(f, "${}|{}$", name, format),
+            Node::Color { color, children } => {
+                write!(f, "§{}", color)?;
+                for child in children {
+                    child.fmt(f)?;
+                }
+                f.write_str("§!")
+            }
+            Node::Icon(icon) => write!(f, "£{}£", icon),
+            Node::Function(function) => write!(f, "[{}]", function),
+            Node::NewLine => f.write_str("\\n"),
+        }
+    }
+}
+
+/// Parse the markup of a text as written in a localisation file
+pub fn parse(text: &str) -> Vec<Node> {
+    let mut parser = MarkupParser::default();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        rest = match c {
+            '$' => parser.enclosed(after, '$', |inner| {
+                let mut parts = inner.splitn(2, '|');
+                Node::Variable {
+                    name: parts.next().unwrap().to_string(),
+                    format: parts.next().map(str::to_string),
+                }
+            }),
+            '£' => parser.enclosed(after, '£', |inner| Node::Icon(inner.to_string())),
+            '[' => parser.enclosed(after, ']', |inner| Node::Function(inner.to_string())),
+            '§' => match after.chars().next() {
+                Some('!') => {
+                    parser.close();
+                    Some(&after[1..])
+                }
+                Some(color) => {
+                    parser.open(color);
+                    Some(&after[color.len_utf8()..])
+                }
+                None => None,
+            },
+            '\\' if after.starts_with('n') => {
+                parser.push(Node::NewLine);
+                Some(&after[1..])
+            }
+            _ => None,
+        }
+        .unwrap_or_else(|| {
+            parser.text.push(c);
+            after
+        });
+    }
+
+    while !parser.open.is_empty() {
+        parser.close();
+    }
+    parser.flush();
+    parser.nodes
+}
+
+#[derive(Default)]
+struct MarkupParser {
+    text: String,
+    nodes: Vec<Node>,
+    /// The open colours with the nodes before them
+    open: Vec<(char, Vec<Node>)>,
+}
+
+impl MarkupParser {
+    fn flush(&mut self) {
+        if !self.text.is_empty() {
+            let text = mem::take(&mut self.text);
+            self.nodes.push(Node::Text(text));
+        }
+    }
+
+    fn push(&mut self, node: Node) {
+        self.flush();
+        self.nodes.push(node);
+    }
+
+    fn open(&mut self, color: char) {
+        self.flush();
+        let before = mem::take(&mut self.nodes);
+        self.open.push((color, before));
+    }
+
+    /// Close the innermost colour, a `§!` without one is dropped
+    fn close(&mut self) {
+        self.flush();
+        if let Some((color, before)) = self.open.pop() {
+            let children = mem::replace(&mut self.nodes, before);
+            self.nodes.push(Node::Color { color, children });
+        }
+    }
+
+    /// Parse markup ending with `end`, returns the text after it
+    ///
+    /// The markup must not be empty or contain whitespace.
+    fn enclosed<'t, F: Fn(&str) -> Node>(&mut self, text: &'t str, end: char, node: F) -> Option<&'t str> {
+        let len = text.find(end)?;
+        let inner = &text[..len];
+        if inner.is_empty() || inner.contains(char::is_whitespace) {
+            return None;
+        }
+        self.push(node(inner));
+        Some(&text[len + end.len_utf8()..])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How the [**Renderer**](struct.Renderer.html) handles colours
+pub enum Colors {
+    /// Plain text without colours
+    Strip,
+    /// ANSI escape codes for terminals
+    Ansi,
+    /// HTML, colours are `<span>`s with a `color` style
+    Html,
+}
+
+/// The ANSI code and the CSS colour of a colour code
+fn color(color: char) -> Option<(u8, &'static str)> {
+    match color {
+        'R' => Some((31, "red")),
+        'G' => Some((32, "green")),
+        'Y' => Some((33, "yellow")),
+        'O' => Some((33, "orange")),
+        'B' => Some((34, "blue")),
+        'M' => Some((35, "magenta")),
+        'C' => Some((36, "cyan")),
+        'W' => Some((37, "white")),
+        'g' => Some((90, "grey")),
+        _ => None,
+    }
+}
+
+/// Renders localisation texts
+///
+/// # Example
+///
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::markup::{Colors, Renderer};
+///
+/// fn main() {
+///     let renderer = Renderer::new().colors(Colors::Html).variable("COUNTRY", "Sweden");
+///     assert_eq!(
+///         renderer.render_str("§Y$COUNTRY$§! <wins>"),
+///         "<span style=\"color: yellow\">Sweden</span> &lt;wins&gt;"
+///     );
+/// }
+/// ```
+pub struct Renderer {
+    colors: Colors,
+    variables: HashMap<String, String>,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer {
+            colors: Colors::Strip,
+            variables: HashMap::new(),
+        }
+    }
+}
+
+impl Renderer {
+    /// A renderer stripping colours, without variables
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn colors(mut self, colors: Colors) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /// Substitute `$name$` and a data function like `[Root.GetName]` with a value
+    ///
+    /// Variables and functions without a value are rendered as written.
+    pub fn variable(mut self, name: &str, value: &str) -> Self {
+        self.variables.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Parse and render a text
+    pub fn render_str(&self, text: &str) -> String {
+        self.render(&parse(text))
+    }
+
+    pub fn render(&self, nodes: &[Node]) -> String {
+        let mut out = String::new();
+        self.render_nodes(nodes, &mut Vec::new(), &mut out);
+        out
+    }
+
+    /// Render nodes inside of the ANSI codes of the `open` colours
+    fn render_nodes(&self, nodes: &[Node], open: &mut Vec<u8>, out: &mut String) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => self.text(text, out),
+                Node::Variable { name, .. } | Node::Function(name) => match self.variables.get(name) {
+                    Some(value) => self.text(value, out),
+                    None => self.text(&node.to_string(), out),
+                },
+                Node::Icon(icon) if self.colors == Colors::Html => {
+                    out.push_str("<span class=\"icon icon-");
+                    self.text(icon, out);
+                    out.push_str("\"></span>");
+                }
+                Node::Icon(_) => self.text(&node.to_string(), out),
+                Node::NewLine if self.colors == Colors::Html => out.push_str("<br>"),
+                Node::NewLine => out.push('\n'),
+                Node::Color { color: c, children } => match (self.colors, color(*c)) {
+                    (Colors::Ansi, Some((code, _))) => {
+                        out.push_str(&format!("\x1b[{}m", code));
+                        open.push(code);
+                        self.render_nodes(children, open, out);
+                        open.pop();
+                        out.push_str("\x1b[0m");
+                        if let Some(outer) = open.last() {
+                            out.push_str(&format!("\x1b[{}m", outer));
+                        }
+                    }
+                    (Colors::Html, Some((_, css))) => {
+                        out.push_str(&format!("<span style=\"color: {}\">", css));
+                        self.render_nodes(children, open, out);
+                        out.push_str("</span>");
+                    }
+                    _ => self.render_nodes(children, open, out),
+                },
+            }
+        }
+    }
+
+    fn text(&self, text: &str, out: &mut String) {
+        if self.colors != Colors::Html {
+            out.push_str(text);
+            return;
+        }
+        for c in text.chars() {
+            match c {
+                '&' => out.push_str("&amp;"),
+                '<' => out.push_str("&lt;"),
+                '>' => out.push_str("&gt;"),
+                '"' => out.push_str("&quot;"),
+                c => out.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Node {
+        Node::Text(text.to_string())
+    }
+
+    #[test]
+    fn test_parse() {
+        let nodes = parse("§YThe $COUNTRY$ §Ggains§! £gold£\\n[Root.GetName]§! $VAL|Y$ costs $5 and 5£ [ ]");
+        assert_eq!(
+            nodes,
+            vec![
+                Node::Color {
+                    color: 'Y',
+                    children: vec![
+                        text("The "),
+                        Node::Variable {
+                            name: "COUNTRY".to_string(),
+                            format: None
+                        },
+                        text(" "),
+                        Node::Color {
+                            color: 'G',
+                            children: vec![text("gains")]
+                        },
+                        text(" "),
+                        Node::Icon("gold".to_string()),
+                        Node::NewLine,
+                        Node::Function("Root.GetName".to_string()),
+                    ]
+                },
+                text(" "),
+                Node::Variable {
+                    name: "VAL".to_string(),
+                    format: Some("Y".to_string())
+                },
+                text(" costs $5 and 5£ [ ]"),
+            ]
+        );
+        let markup: String = nodes.iter().map(Node::to_string).collect();
+        assert_eq!(markup, "§YThe $COUNTRY$ §Ggains§! £gold£\\n[Root.GetName]§! $VAL|Y$ costs $5 and 5£ [ ]");
+    }
+
+    #[test]
+    fn test_parse_unbalanced() {
+        assert_eq!(parse("a§!b"), vec![text("a"), text("b")]);
+        assert_eq!(
+            parse("§Ropen"),
+            vec![Node::Color {
+                color: 'R',
+                children: vec![text("open")]
+            }]
+        );
+        assert_eq!(parse("$ §"), vec![text("$ §")]);
+    }
+
+    #[test]
+    fn test_render() {
+        let text = "§YThe $COUNTRY$ §Rloses§! £gold£§!\\n[Root.GetName] & $MISSING$";
+        let renderer = Renderer::new().variable("COUNTRY", "<Sweden>").variable("Root.GetName", "Gustav");
+        assert_eq!(renderer.render_str(text), "The <Sweden> loses £gold£\nGustav & $MISSING$");
+
+        let renderer = renderer.colors(Colors::Ansi);
+        assert_eq!(
+            renderer.render_str(text),
+            "\x1b[33mThe <Sweden> \x1b[31mloses\x1b[0m\x1b[33m £gold£\x1b[0m\nGustav & $MISSING$"
+        );
+
+        let renderer = renderer.colors(Colors::Html);
+        assert_eq!(
+            renderer.render_str(text),
+            "<span style=\"color: yellow\">The &lt;Sweden&gt; <span style=\"color: red\">loses</span> \
+             <span class=\"icon icon-gold\"></span></span><br>Gustav &amp; $MISSING$"
+        );
+        // unknown colours are dropped
+        assert_eq!(renderer.render_str("§Xplain§!"), "plain");
+    }
+}
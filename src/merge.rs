@@ -0,0 +1,356 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Three-way merge of `ClVal` trees
+//!
+//! Two trees derived from a common base, like two mods editing the same vanilla file, are merged
+//! key by key: a value changed on one side only takes that change, a value changed the same way
+//! on both sides is taken once, and everything else is a conflict. Lists are merged item by item
+//! if they have the same length, items appended on both sides are all kept, and with
+//! [**DiffOptions::match_by**](../diff/struct.DiffOptions.html#method.match_by) the dicts in lists
+//! are merged by their id.
+//!
+//! ```
+//! extern crate clausewitz_parser;
+//!
+//! use clausewitz_parser::merge::merge;
+//! use clausewitz_parser::{parse, Writer};
+//!
+//! fn main() {
+//!     let base = parse(b"PRU = { treasury = 10 add_core = PRU }").unwrap();
+//!     let ours = parse(b"PRU = { treasury = 20 add_core = PRU }").unwrap();
+//!     let theirs = parse(b"PRU = { treasury = 10 add_core = PRU add_core = BRA }").unwrap();
+//!     let merge = merge(&base, &ours, &theirs);
+//!
+//!     assert!(merge.conflicts.is_empty());
+//!     assert_eq!(merge.merged, parse(b"PRU = { treasury = 20 add_core = PRU add_core = BRA }").unwrap());
+//!     let text = Writer::new().repeated_keys(vec!["add_core"]).write(&merge.merged).unwrap();
+//!     assert_eq!(text, b"PRU={\n\tadd_core=PRU\n\tadd_core=BRA\n\ttreasury=20\n}\n");
+//! }
+//! ```
+
+use clval::{ClKey, ClVal};
+use diff::{ids, DiffOptions};
+use query::{Path, PathSegment};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::slice;
+use writer::write_value;
+
+#[derive(Debug, Clone, PartialEq)]
+/// A value changed differently on both sides, `None` means the value doesn't exist
+pub struct Conflict {
+    pub path: Path,
+    pub base: Option<ClVal>,
+    pub ours: Option<ClVal>,
+    pub theirs: Option<ClVal>,
+}
+
+impl Display for Conflict {
+    /// Like `path: ours = value, theirs = value`
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: ours = {}, theirs = {}",
+            self.path,
+            text(self.ours.as_ref()),
+            text(self.theirs.as_ref())
+        )
+    }
+}
+
+fn text(value: Option<&ClVal>) -> String {
+    let mut out = String::new();
+    match value {
+        Some(value) => write_value(&mut out, value, 0),
+        None => out.push_str("(removed)"),
+    }
+    out
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The result of a merge
+///
+/// Conflicting values are taken from ours, so `merged` is always a complete tree.
+pub struct Merge {
+    pub merged: ClVal,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Merge {
+    /// Whether the merge had no conflicts
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merge the changes from `base` to `ours` and from `base` to `theirs`, with the default options
+pub fn merge(base: &ClVal, ours: &ClVal, theirs: &ClVal) -> Merge {
+    merge_with(base, ours, theirs, &DiffOptions::default())
+}
+
+/// Merge the changes from `base` to `ours` and from `base` to `theirs`
+pub fn merge_with(base: &ClVal, ours: &ClVal, theirs: &ClVal, options: &DiffOptions) -> Merge {
+    let mut merger = Merger {
+        options,
+        conflicts: Vec::new(),
+    };
+    let merged = merger.value(&Path::new(), Some(base), Some(ours), Some(theirs));
+    Merge {
+        // only a conflict can remove the root and then ours is kept
+        merged: merged.unwrap_or_else(|| ours.clone()),
+        conflicts: merger.conflicts,
+    }
+}
+
+struct Merger<'a> {
+    options: &'a DiffOptions,
+    conflicts: Vec<Conflict>,
+}
+
+impl<'a> Merger<'a> {
+    fn value(
+        &mut self,
+        path: &Path,
+        base: Option<&ClVal>,
+        ours: Option<&ClVal>,
+        theirs: Option<&ClVal>,
+    ) -> Option<ClVal> {
+        if ours == theirs || theirs == base {
+            return ours.cloned();
+        }
+        if ours == base {
+            return theirs.cloned();
+        }
+
+        match (ours, theirs) {
+            (Some(ClVal::Dict(ours)), Some(ClVal::Dict(theirs))) => {
+                let base = match base {
+                    Some(ClVal::Dict(base)) => Some(base),
+                    _ => None,
+                };
+                return Some(ClVal::Dict(self.dict(path, base, ours, theirs)));
+            }
+            (Some(ours), Some(theirs)) if [base, Some(ours), Some(theirs)].iter().any(is_list) => {
+                if let Some(list) = self.list(path, base.map(items).unwrap_or(&[]), items(ours), items(theirs)) {
+                    return Some(ClVal::List(list));
+                }
+            }
+            _ => {}
+        }
+
+        self.conflicts.push(Conflict {
+            path: path.clone(),
+            base: base.cloned(),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+        });
+        ours.cloned()
+    }
+
+    fn dict(
+        &mut self,
+        path: &Path,
+        base: Option<&HashMap<ClKey, ClVal>>,
+        ours: &HashMap<ClKey, ClVal>,
+        theirs: &HashMap<ClKey, ClVal>,
+    ) -> HashMap<ClKey, ClVal> {
+        let mut keys: Vec<_> = base
+            .into_iter()
+            .flat_map(|base| base.keys())
+            .chain(ours.keys())
+            .chain(theirs.keys())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        keys.sort();
+
+        let mut merged = HashMap::new();
+        for key in keys {
+            let path = path.join(PathSegment::Key(key.clone()));
+            let base = base.and_then(|base| base.get(key));
+            if let Some(value) = self.value(&path, base, ours.get(key), theirs.get(key)) {
+                merged.insert(key.clone(), value);
+            }
+        }
+        merged
+    }
+
+    /// Merge lists, `None` if they can't be merged
+    fn list(&mut self, path: &Path, base: &[ClVal], ours: &[ClVal], theirs: &[ClVal]) -> Option<Vec<ClVal>> {
+        if let Some(key) = &self.options.id_key {
+            if let (Some(base_ids), Some(our_ids), Some(their_ids)) = (ids(base, key), ids(ours, key), ids(theirs, key)) {
+                return Some(self.list_by_id(path, key, (base, &base_ids), (ours, &our_ids), (theirs, &their_ids)));
+            }
+        }
+
+        if base.len() == ours.len() && base.len() == theirs.len() {
+            let mut merged = Vec::with_capacity(base.len());
+            for (index, base) in base.iter().enumerate() {
+                let path = path.join(PathSegment::Index(index));
+                merged.extend(self.value(&path, Some(base), Some(&ours[index]), Some(&theirs[index])));
+            }
+            Some(merged)
+        } else if ours.starts_with(base) && theirs.starts_with(base) {
+            // items appended on both sides
+            let mut merged = ours.to_vec();
+            merged.extend_from_slice(&theirs[base.len()..]);
+            Some(merged)
+        } else {
+            None
+        }
+    }
+
+    fn list_by_id(
+        &mut self,
+        path: &Path,
+        key: &str,
+        base: (&[ClVal], &[String]),
+        ours: (&[ClVal], &[String]),
+        theirs: (&[ClVal], &[String]),
+    ) -> Vec<ClVal> {
+        // base order first, then the items added by ours and by theirs
+        let mut order: Vec<&String> = Vec::new();
+        for id in base.1.iter().chain(ours.1).chain(theirs.1) {
+            if !order.contains(&id) {
+                order.push(id);
+            }
+        }
+
+        let mut merged = Vec::with_capacity(order.len());
+        for id in order {
+            let path = path.join(PathSegment::Match {
+                key: key.to_string(),
+                value: id.clone(),
+            });
+            merged.extend(self.value(&path, find(base, id), find(ours, id), find(theirs, id)));
+        }
+        merged
+    }
+}
+
+/// The item with the id `id`
+fn find<'v>((items, ids): (&'v [ClVal], &[String]), id: &str) -> Option<&'v ClVal> {
+    ids.iter().position(|i| i == id).map(|position| &items[position])
+}
+
+fn is_list(value: &Option<&ClVal>) -> bool {
+    matches!(value, Some(ClVal::List(_)))
+}
+
+/// The items of a list, a single value counts as a list with one item
+fn items(value: &ClVal) -> &[ClVal] {
+    match value {
+        ClVal::List(list) => list,
+        value => slice::from_ref(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge_str(base: &str, ours: &str, theirs: &str, options: &DiffOptions) -> Merge {
+        merge_with(
+            &::parse(base.as_bytes()).unwrap(),
+            &::parse(ours.as_bytes()).unwrap(),
+            &::parse(theirs.as_bytes()).unwrap(),
+            options,
+        )
+    }
+
+    fn assert_merged(base: &str, ours: &str, theirs: &str, expected: &str, options: &DiffOptions) {
+        let merge = merge_str(base, ours, theirs, options);
+        assert_eq!(merge.conflicts, vec![]);
+        assert_eq!(merge.merged, ::parse(expected.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_merge_dict() {
+        assert_merged(
+            "a = { b = 1 c = 2 d = 3 }",
+            "a = { b = 5 c = 2 d = 3 e = yes }",
+            "a = { b = 1 c = 7 f = { g = 1 } }",
+            "a = { b = 5 c = 7 e = yes f = { g = 1 } }",
+            &DiffOptions::new(),
+        );
+    }
+
+    #[test]
+    fn test_merge_same_change() {
+        assert_merged("a = { b = 1 }", "a = { b = 2 }", "a = { b = 2 }", "a = { b = 2 }", &DiffOptions::new());
+    }
+
+    #[test]
+    fn test_merge_lists() {
+        assert_merged(
+            "a = { l = { 1 2 3 } }",
+            "a = { l = { 5 2 3 } }",
+            "a = { l = { 1 2 4 } }",
+            "a = { l = { 5 2 4 } }",
+            &DiffOptions::new(),
+        );
+        assert_merged(
+            "a = { core = PRU }",
+            "a = { core = PRU core = BRA }",
+            "a = { core = PRU core = HAB core = BOH }",
+            "a = { core = PRU core = BRA core = HAB core = BOH }",
+            &DiffOptions::new(),
+        );
+    }
+
+    #[test]
+    fn test_merge_by_id() {
+        assert_merged(
+            "a = { u = { id = 1 s = 10 } u = { id = 2 s = 20 } }",
+            "a = { u = { id = 2 s = 25 } u = { id = 3 s = 30 } }",
+            "a = { u = { id = 2 s = 20 } u = { id = 1 s = 10 } u = { id = 4 s = 40 } }",
+            "a = { u = { id = 2 s = 25 } u = { id = 3 s = 30 } u = { id = 4 s = 40 } }",
+            &DiffOptions::new().match_by("id"),
+        );
+        let merge = merge_str(
+            "a = { u = { id = 1 s = 10 } u = { id = 2 s = 20 } }",
+            "a = { u = { id = 2 s = 20 } }",
+            "a = { u = { id = 1 s = 15 } u = { id = 2 s = 20 } }",
+            &DiffOptions::new().match_by("id"),
+        );
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(merge.conflicts[0].path.to_string(), "a.u[id=1]");
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let merge = merge_str(
+            "a = { b = 1 c = 1 l = { 1 2 } }",
+            "a = { b = 2 l = { 1 } }",
+            "a = { b = 3 c = 2 l = { 3 } }",
+            &DiffOptions::new(),
+        );
+        let conflicts: Vec<_> = merge.conflicts.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            conflicts,
+            vec![
+                "a.b: ours = 2, theirs = 3",
+                "a.c: ours = (removed), theirs = 2",
+                "a.l: ours = { 1 }, theirs = { 3 }",
+            ]
+        );
+        assert!(!merge.is_clean());
+        assert_eq!(merge.merged, ::parse(b"a = { b = 2 l = { 1 } }").unwrap());
+    }
+}
@@ -0,0 +1,257 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The game directory overlaid with mods
+//!
+//! A [**ModStack**](struct.ModStack.html) resolves logical paths like
+//! `common/ideas/00_country_ideas.txt` the way the game does: mods are loaded in order, a file
+//! of a later mod overrides the file with the same path of the game and earlier mods, and the
+//! `replace_path` directories of a mod hide the files of the layers below it. `replace_path`
+//! hides the files directly in the directory, not the ones in its subdirectories.
+
+use clval::ClVal;
+use descriptor::ModDescriptor;
+use error::Error;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The game directory or a mod
+pub struct Layer {
+    pub name: String,
+    pub root: PathBuf,
+    /// Logical directories hiding the files of the layers below
+    pub replace_path: Vec<String>,
+}
+
+impl Layer {
+    fn replaces(&self, dir: &str) -> bool {
+        self.replace_path.iter().any(|p| normalize(p) == dir)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The file winning a logical path
+pub struct ResolvedFile {
+    /// The logical path, like `common/ideas/00_country_ideas.txt`
+    pub path: String,
+    /// The physical file
+    pub file: PathBuf,
+    /// The name of the layer of the file
+    pub layer: String,
+}
+
+#[derive(Debug, Clone)]
+/// The game directory and the mods in load order
+pub struct ModStack {
+    layers: Vec<Layer>,
+}
+
+impl ModStack {
+    /// A stack with only the game directory
+    pub fn new<P: AsRef<Path>>(game: P) -> Self {
+        ModStack {
+            layers: vec![Layer {
+                name: "game".to_string(),
+                root: game.as_ref().to_path_buf(),
+                replace_path: Vec::new(),
+            }],
+        }
+    }
+
+    /// Add a mod directory with a `descriptor.mod` or `.metadata/metadata.json` on top
+    pub fn add_mod<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        let descriptor = ModDescriptor::load(dir)?;
+        self.push(Layer {
+            name: descriptor.name,
+            root: dir.to_path_buf(),
+            replace_path: descriptor.replace_path,
+        });
+        Ok(())
+    }
+
+    /// Add a layer on top
+    pub fn push(&mut self, layer: Layer) {
+        self.layers.push(layer);
+    }
+
+    /// The game directory followed by the mods in load order
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// The file the game loads for a logical path
+    pub fn resolve(&self, path: &str) -> Option<ResolvedFile> {
+        let path = normalize(path);
+        let dir = parent(path);
+        for layer in self.layers.iter().rev() {
+            let file = layer.root.join(path);
+            if file.is_file() {
+                return Some(ResolvedFile {
+                    path: path.to_string(),
+                    file,
+                    layer: layer.name.clone(),
+                });
+            }
+            if layer.replaces(dir) {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// The files the game loads from a logical directory, sorted by name
+    pub fn list(&self, dir: &str) -> Vec<ResolvedFile> {
+        let dir = normalize(dir);
+        let mut files = BTreeMap::new();
+        for layer in &self.layers {
+            if layer.replaces(dir) {
+                files.clear();
+            }
+            let entries = match fs::read_dir(layer.root.join(dir)) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for file in entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_file()) {
+                let name = file.file_name().unwrap().to_string_lossy().into_owned();
+                let path = if dir.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}/{}", dir, name)
+                };
+                files.insert(
+                    name,
+                    ResolvedFile {
+                        path,
+                        file,
+                        layer: layer.name.clone(),
+                    },
+                );
+            }
+        }
+        files.into_values().collect()
+    }
+
+    /// Read the file of a logical path
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let resolved = self
+            .resolve(path)
+            .ok_or_else(|| format!("{}: no such file in the game or a mod", path))?;
+        fs::read(&resolved.file).map_err(|e| format!("{}: {}", resolved.file.display(), e).into())
+    }
+
+    /// Parse the file of a logical path with [`parse`](../fn.parse.html)
+    pub fn parse(&self, path: &str) -> Result<ClVal, Error> {
+        let buf = self.read(path)?;
+        ::parse(&buf).map_err(|e| format!("{}: {}", path, e).into())
+    }
+}
+
+/// A logical path without leading and trailing slashes
+fn normalize(path: &str) -> &str {
+    path.trim_matches('/')
+}
+
+fn parent(path: &str) -> &str {
+    path.rfind('/').map_or("", |i| &path[..i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/mod_stack")
+    }
+
+    fn stack() -> ModStack {
+        let mut stack = ModStack::new(fixtures().join("game"));
+        stack.add_mod(fixtures().join("mod_a")).unwrap();
+        stack.add_mod(fixtures().join("mod_b")).unwrap();
+        stack
+    }
+
+    fn resolve(stack: &ModStack, path: &str) -> Option<(String, PathBuf)> {
+        stack
+            .resolve(path)
+            .map(|r| (r.layer, r.file.strip_prefix(fixtures()).unwrap().to_path_buf()))
+    }
+
+    #[test]
+    fn test_layers() {
+        let names: Vec<_> = stack().layers().iter().map(|l| l.name.clone()).collect();
+        assert_eq!(names, vec!["game", "Mod A", "Mod B"]);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let stack = stack();
+        assert_eq!(
+            resolve(&stack, "common/ideas/00_country_ideas.txt"),
+            Some(("Mod A".to_string(), PathBuf::from("mod_a/common/ideas/00_country_ideas.txt")))
+        );
+        assert_eq!(
+            resolve(&stack, "/common/ideas/zz_generic_ideas.txt"),
+            Some(("game".to_string(), PathBuf::from("game/common/ideas/zz_generic_ideas.txt")))
+        );
+        // replaced by mod A
+        assert_eq!(resolve(&stack, "common/units/swedish_tercio.txt"), None);
+        assert_eq!(
+            resolve(&stack, "common/units/mod_a_infantry.txt"),
+            Some(("Mod A".to_string(), PathBuf::from("mod_a/common/units/mod_a_infantry.txt")))
+        );
+        // replaced by mod B
+        assert_eq!(resolve(&stack, "events/vanilla.txt"), None);
+        assert_eq!(resolve(&stack, "common/ideas/missing.txt"), None);
+    }
+
+    #[test]
+    fn test_list() {
+        let stack = stack();
+        let list = |dir: &str| -> Vec<(String, String)> {
+            stack.list(dir).into_iter().map(|f| (f.path, f.layer)).collect()
+        };
+        let entry = |path: &str, layer: &str| (path.to_string(), layer.to_string());
+        assert_eq!(
+            list("common/ideas"),
+            vec![
+                entry("common/ideas/00_country_ideas.txt", "Mod A"),
+                entry("common/ideas/mod_a_ideas.txt", "Mod A"),
+                entry("common/ideas/mod_b_ideas.txt", "Mod B"),
+                entry("common/ideas/zz_generic_ideas.txt", "game"),
+            ]
+        );
+        assert_eq!(list("common/units/"), vec![entry("common/units/mod_a_infantry.txt", "Mod A")]);
+        assert_eq!(list("events"), vec![entry("events/mod_b.txt", "Mod B")]);
+        assert_eq!(list("common"), vec![]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let stack = stack();
+        let ideas = stack.parse("common/ideas/00_country_ideas.txt").unwrap();
+        assert_eq!(ideas.get_path(&["SWE_ideas", "start", "army_morale"]), Some(&ClVal::Float(0.15)));
+        assert!(ideas.get("FRA_ideas").is_none());
+        assert_eq!(
+            stack.parse("events/vanilla.txt").unwrap_err().to_string(),
+            "events/vanilla.txt: no such file in the game or a mod"
+        );
+    }
+}
@@ -0,0 +1,324 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Object level overrides across the game and mods
+//!
+//! The games merge the files of most `common/` directories by their top level keys: a
+//! `my_idea_group` in a mod replaces the one of the game even in a file with another name.
+//! Whether the last or the first definition wins depends on the directory, see
+//! [**MergePolicy**](enum.MergePolicy.html). A [**Resolver**](struct.Resolver.html) applies the
+//! policies to the files a [**ModStack**](../mod_stack/struct.ModStack.html) loads, in the order
+//! the game loads them: sorted by file name, then in the order of the file.
+
+use clval::ClVal;
+use cst::SyntaxTree;
+use diagnostic::{Diagnostic, Problem, Severity};
+use error::{Error, ErrorKind};
+use mod_stack::{ModStack, ResolvedFile};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::str::FromStr;
+use token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How the definitions of an object in several files are merged
+pub enum MergePolicy {
+    /// Last in, only served: the last definition wins
+    #[default]
+    Lios,
+    /// First in, only served: the first definition wins
+    Fios,
+    /// Only one definition is allowed, the first one is used
+    DuplicateError,
+}
+
+impl FromStr for MergePolicy {
+    type Err = Error;
+
+    /// Parse `lios`, `fios` or `duplicate-error`, ignoring the case
+    fn from_str(s: &str) -> Result<MergePolicy, Error> {
+        match s.to_ascii_lowercase().as_str() {
+            "lios" => Ok(MergePolicy::Lios),
+            "fios" => Ok(MergePolicy::Fios),
+            "duplicate-error" => Ok(MergePolicy::DuplicateError),
+            _ => bail!(ErrorKind::InvalidValue(format!("unknown merge policy `{}`", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A top level entry of a file
+pub struct Definition {
+    pub file: ResolvedFile,
+    /// The span of the entry in the file
+    pub span: Span,
+    pub value: ClVal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// An object with the definition the game uses
+pub struct Object {
+    pub name: String,
+    pub definition: Definition,
+    /// The definitions which lost, in load order
+    pub shadowed: Vec<Definition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// The objects of a directory
+pub struct Resolution {
+    /// Sorted by name
+    pub objects: Vec<Object>,
+    /// Syntax errors and duplicates of the `DuplicateError` policy
+    pub problems: Vec<Problem>,
+}
+
+impl Resolution {
+    pub fn get(&self, name: &str) -> Option<&Object> {
+        self.objects
+            .binary_search_by(|object| object.name.as_str().cmp(name))
+            .ok()
+            .map(|i| &self.objects[i])
+    }
+}
+
+/// Resolves the objects of directories of a mod stack
+pub struct Resolver<'s> {
+    stack: &'s ModStack,
+    policies: HashMap<String, MergePolicy>,
+    default_policy: MergePolicy,
+}
+
+impl<'s> Resolver<'s> {
+    /// A resolver using `Lios` for every directory
+    pub fn new(stack: &'s ModStack) -> Self {
+        Resolver {
+            stack,
+            policies: HashMap::new(),
+            default_policy: MergePolicy::default(),
+        }
+    }
+
+    /// Set the policy of a logical directory, like `common/ideas`
+    pub fn policy(mut self, dir: &str, policy: MergePolicy) -> Self {
+        self.policies.insert(dir.trim_matches('/').to_string(), policy);
+        self
+    }
+
+    /// Set the policy of directories without one
+    pub fn default_policy(mut self, policy: MergePolicy) -> Self {
+        self.default_policy = policy;
+        self
+    }
+
+    /// Resolve the objects of the `.txt` files of a logical directory
+    pub fn resolve(&self, dir: &str) -> Result<Resolution, Error> {
+        let policy = self
+            .policies
+            .get(dir.trim_matches('/'))
+            .cloned()
+            .unwrap_or(self.default_policy);
+
+        let mut objects: BTreeMap<String, Object> = BTreeMap::new();
+        let mut problems = Vec::new();
+        for file in self.stack.list(dir) {
+            if !file.path.ends_with(".txt") {
+                continue;
+            }
+            let buf = fs::read(&file.file).map_err(|e| format!("{}: {}", file.file.display(), e))?;
+            let tree = SyntaxTree::parse(&buf);
+            let errors: Vec<_> = tree.diagnostics().into_iter().filter(|d| d.severity == Severity::Error).collect();
+            // files which don't parse are skipped, their syntax errors are reported
+            if !errors.is_empty() {
+                problems.extend(errors.into_iter().map(|diagnostic| Problem {
+                    file: file.file.clone(),
+                    diagnostic,
+                }));
+                continue;
+            }
+            // a key repeated in a file is merged like one repeated in another file
+            for entry in tree.root().entries() {
+                let name = match entry.key() {
+                    Some(key) => tree.scalar(key).into_owned(),
+                    None => continue,
+                };
+                let span = entry.span();
+                let value = match tree.entry_to_clval(entry).ok().and_then(|mut v| v.remove_path(&[&name])) {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let definition = Definition {
+                    file: file.clone(),
+                    span,
+                    value,
+                };
+
+                let object = match objects.get_mut(&name) {
+                    Some(object) => object,
+                    None => {
+                        objects.insert(
+                            name.clone(),
+                            Object {
+                                name,
+                                definition,
+                                shadowed: Vec::new(),
+                            },
+                        );
+                        continue;
+                    }
+                };
+                match policy {
+                    MergePolicy::Lios => {
+                        let first = ::std::mem::replace(&mut object.definition, definition);
+                        object.shadowed.push(first);
+                    }
+                    MergePolicy::Fios => object.shadowed.push(definition),
+                    MergePolicy::DuplicateError => {
+                        let first = &object.definition.file;
+                        let message = format!(
+                            "`{}` is already defined in {} ({})",
+                            object.name, first.path, first.layer
+                        );
+                        problems.push(Problem {
+                            file: file.file.clone(),
+                            diagnostic: Diagnostic::error("duplicate-object", span, message),
+                        });
+                        object.shadowed.push(definition);
+                    }
+                }
+            }
+        }
+
+        Ok(Resolution {
+            objects: objects.into_values().collect(),
+            problems,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn stack() -> ModStack {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/mod_stack");
+        let mut stack = ModStack::new(fixtures.join("game"));
+        stack.add_mod(fixtures.join("mod_a")).unwrap();
+        stack.add_mod(fixtures.join("mod_b")).unwrap();
+        stack
+    }
+
+    /// The name, file and army morale of the objects and the files of the shadowed definitions
+    fn summary(resolution: &Resolution) -> Vec<(String, String, Option<f32>, Vec<String>)> {
+        resolution
+            .objects
+            .iter()
+            .map(|o| {
+                let morale = match o.definition.value.get_path(&["start", "army_morale"]) {
+                    Some(ClVal::Float(f)) => Some(*f),
+                    _ => None,
+                };
+                let shadowed = o.shadowed.iter().map(|d| d.file.path.clone()).collect();
+                (o.name.clone(), o.definition.file.path.clone(), morale, shadowed)
+            })
+            .collect()
+    }
+
+    fn object(name: &str, path: &str, morale: Option<f32>, shadowed: &[&str]) -> (String, String, Option<f32>, Vec<String>) {
+        let path = format!("common/ideas/{}", path);
+        let shadowed = shadowed.iter().map(|p| format!("common/ideas/{}", p)).collect();
+        (name.to_string(), path, morale, shadowed)
+    }
+
+    #[test]
+    fn test_lios() {
+        let stack = stack();
+        let resolution = Resolver::new(&stack).resolve("common/ideas").unwrap();
+        assert_eq!(
+            summary(&resolution),
+            vec![
+                object("FRA_ideas", "mod_a_ideas.txt", Some(0.5), &["mod_a_ideas.txt"]),
+                object("SWE_ideas", "mod_b_ideas.txt", Some(0.3), &["00_country_ideas.txt"]),
+                object("generic_ideas", "zz_generic_ideas.txt", None, &[]),
+                object("mod_a_ideas", "mod_a_ideas.txt", None, &[]),
+            ]
+        );
+        assert_eq!(resolution.problems, vec![]);
+        let swe = resolution.get("SWE_ideas").unwrap();
+        assert_eq!(swe.definition.file.layer, "Mod B");
+        assert!(resolution.get("ENG_ideas").is_none());
+    }
+
+    #[test]
+    fn test_fios() {
+        let stack = stack();
+        let resolution = Resolver::new(&stack)
+            .policy("common/ideas/", MergePolicy::Fios)
+            .resolve("common/ideas")
+            .unwrap();
+        assert_eq!(
+            summary(&resolution)[..2].to_vec(),
+            vec![
+                object("FRA_ideas", "mod_a_ideas.txt", Some(0.25), &["mod_a_ideas.txt"]),
+                object("SWE_ideas", "00_country_ideas.txt", Some(0.15), &["mod_b_ideas.txt"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_error() {
+        let stack = stack();
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/mod_stack");
+        let resolution = Resolver::new(&stack)
+            .default_policy("duplicate-error".parse().unwrap())
+            .resolve("common/ideas")
+            .unwrap();
+        assert_eq!(
+            summary(&resolution)[..2].to_vec(),
+            vec![
+                object("FRA_ideas", "mod_a_ideas.txt", Some(0.25), &["mod_a_ideas.txt"]),
+                object("SWE_ideas", "00_country_ideas.txt", Some(0.15), &["mod_b_ideas.txt"]),
+            ]
+        );
+        let problems: Vec<_> = resolution
+            .problems
+            .iter()
+            .map(|p| (p.file.strip_prefix(&fixtures).unwrap().to_path_buf(), p.diagnostic.to_string()))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    Path::new("mod_a/common/ideas/mod_a_ideas.txt").to_path_buf(),
+                    "error[duplicate-object]: `FRA_ideas` is already defined in common/ideas/mod_a_ideas.txt (Mod A)"
+                        .to_string()
+                ),
+                (
+                    Path::new("mod_b/common/ideas/mod_b_ideas.txt").to_path_buf(),
+                    "error[duplicate-object]: `SWE_ideas` is already defined in common/ideas/00_country_ideas.txt (Mod A)"
+                        .to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            "last".parse::<MergePolicy>().unwrap_err().to_string(),
+            "invalid value type: unknown merge policy `last`"
+        );
+    }
+}
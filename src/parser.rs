@@ -20,12 +20,24 @@
 
 use regex::Regex;
 use std::collections::HashMap;
+use std::mem;
 use std::num::ParseIntError;
 use std::str::{from_utf8_unchecked, FromStr};
 
-use clval::{ClKey, ClVal, Date};
+use clval::{insert_or_append, ClKey, ClVal, Date};
+use diagnostic::{Diagnostic, Severity};
 use error::{Error, ErrorKind};
-use token::LexerToken;
+use token::{LexerToken, Span};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Options of the [**Parser**](struct.Parser.html)
+pub struct ParserOptions {
+    /// Fail on everything the parser tolerates otherwise: missing equals signs, stray tokens like
+    /// `)`, trailing commas, blocks still open at EOF and duplicate keys at the top level
+    ///
+    /// The error is an `ErrorKind::Syntax` with the located diagnostic.
+    pub strict: bool,
+}
 
 #[derive(Default)]
 /// The Parser
@@ -47,20 +59,42 @@ use token::LexerToken;
 /// ```
 pub struct Parser<'buf> {
     tokens: Vec<LexerToken<'buf>>,
+    spans: Vec<Span>,
     current_indent: u32,
     position: usize,
+    options: ParserOptions,
+    recover: bool,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<'buf> Parser<'buf> {
     /// Construct a new `Parser`
 
     pub fn new(tokens: Vec<LexerToken<'buf>>) -> Self {
+        Self::with_spans(tokens.into_iter().map(|token| (token, Span::default())).collect())
+    }
+
+    /// Construct a new `Parser` from tokens with their spans, which locate the diagnostics
+    ///
+    /// See [`Tokenizer::tokenize_with_spans`](../token/struct.Tokenizer.html#method.tokenize_with_spans).
+    pub fn with_spans(tokens: Vec<(LexerToken<'buf>, Span)>) -> Self {
+        let (tokens, spans) = tokens
+            .into_iter()
+            .filter(|(token, _)| *token != LexerToken::Comment)
+            .unzip();
         Self {
             tokens,
+            spans,
             ..Default::default()
         }
     }
 
+    /// Set the options
+    pub fn options(mut self, options: ParserOptions) -> Self {
+        self.options = options;
+        self
+    }
+
     /// Parse the provided [**LexerTokens**](../token/enum.LexerToken.html) into [**ClVals**](../clval/enum.ClVal.html)
     ///
     /// The returned `ClVal` is always a `Dict`
@@ -69,35 +103,55 @@ impl<'buf> Parser<'buf> {
         debug!("got {} tokens to parse", self.tokens.len());
 
         while self.position < self.tokens.len() {
-            let key = self.parse_key()?;
+            let start = self.position;
+            let key = match self.parse_key() {
+                Ok(key) => key,
+                Err(e) => {
+                    self.skip(e, start, false)?;
+                    continue;
+                }
+            };
             debug!("[parse] got key: {:?}", key);
-            {
-                let token = &self.tokens[self.position];
-                debug!("[parse] next token: {:?}", token);
-                // equals is optional for dicts
-                if token.is_equals() {
-                    self.position += 1;
-                } else {
-                    info!("expected equals, but found: {:?}", token);
+            let key_end = self.position;
+            self.parse_equals(start, &key)?;
+            let value = match self.parse_value() {
+                Ok(value) => value,
+                Err(e) => {
+                    self.missing_value(e, start, &key, false)?;
+                    continue;
                 }
-            }
-            let value = self.parse_value()?;
+            };
             debug!("[parse] got value: {:?}", value);
+            if dict.contains_key(&key) {
+                let message = format!("duplicate key `{}`", key);
+                self.report(Severity::Warning, "duplicate-key", start, key_end, message)?;
+            }
             dict.insert(key, value);
         }
 
         Ok(ClVal::Dict(dict))
     }
 
+    /// Parse like [`parse`](#method.parse), but recover from every error
+    ///
+    /// Returns the values which could be parsed together with a diagnostic for every recovery
+    /// of the parser: skipped tokens, missing equals signs, missing values and unclosed blocks.
+    pub fn parse_with_diagnostics(&mut self) -> (ClVal, Vec<Diagnostic>) {
+        self.recover = true;
+        // the parser does not fail while recovering
+        let values = self.parse().unwrap_or_else(|_| ClVal::Dict(HashMap::new()));
+        (values, mem::take(&mut self.diagnostics))
+    }
+
     fn parse_key(&mut self) -> Result<ClKey, Error> {
-        let token = &self.tokens[self.position];
+        let token = self.peek()?;
         debug!("[key] pos: {} - token: {:?}", self.position, token);
         self.position += 1;
         let key = match token {
             // Quoted string:  QUOTE UNTYPED QUOTE
             LexerToken::Quote => {
+                let token = self.peek()?;
                 debug!("[key] quoted string pos: {} - token: {:?}", self.position, token);
-                let token = &self.tokens[self.position];
                 let s = self.parse_quoted_str(token.as_untyped()?);
                 self.position += 2;
                 debug!("[key] quoted string: {:?}", s);
@@ -105,17 +159,7 @@ impl<'buf> Parser<'buf> {
             }
             LexerToken::Untyped(b) => {
                 debug!("[key] untyped");
-                if let Ok(val) = self.parse_int(b) {
-                    debug!("[key] int: {:?}", val);
-                    return Ok(val);
-                }
-                if let Ok(val) = self.parse_date(b) {
-                    debug!("[key] date: {:?}", val);
-                    return Ok(val);
-                }
-                let val = self.parse_identifier(b);
-                debug!("[key] identifier: {:?}", val);
-                val
+                self.parse_untyped_key(b)
             }
             _ => bail!(ErrorKind::InvalidToken),
         };
@@ -123,15 +167,49 @@ impl<'buf> Parser<'buf> {
         Ok(key)
     }
 
+    fn parse_untyped_key(&self, b: &[u8]) -> ClKey {
+        if let Ok(val) = self.parse_int(b) {
+            debug!("[key] int: {:?}", val);
+            return val;
+        }
+        if let Ok(val) = self.parse_date(b) {
+            debug!("[key] date: {:?}", val);
+            return val;
+        }
+        let val = self.parse_identifier(b);
+        debug!("[key] identifier: {:?}", val);
+        val
+    }
+
+    /// Skip the equals sign after the key starting at `start`
+    fn parse_equals(&mut self, start: usize, key: &ClKey) -> Result<(), Error> {
+        match self.tokens.get(self.position) {
+            Some(LexerToken::Equals) => self.position += 1,
+            // equals is optional for dicts
+            Some(token) => {
+                debug!("expected equals, but found: {:?}", token);
+                let message = format!("missing `=` after `{}`", key);
+                self.report(Severity::Warning, "missing-equals", start, self.position, message)?;
+            }
+            // the missing value is reported
+            None => {}
+        }
+        Ok(())
+    }
+
     fn parse_value(&mut self) -> Result<ClVal, Error> {
-        let token = self.tokens[self.position].clone();
+        let token = self.peek()?;
         debug!("[value] pos: {} - token: {:?}", self.position, token);
+        // a right curly closes the surrounding block
+        if token.is_right_curly() {
+            bail!(ErrorKind::InvalidToken);
+        }
         self.position += 1;
 
         let value = match token {
             // Quoted string:  QUOTE UNTYPED QUOTE
             LexerToken::Quote => {
-                let token = &self.tokens[self.position];
+                let token = self.peek()?;
                 debug!("[value] string token at {}: {:?}", self.position, token);
                 let s = self.parse_quoted_str_v(token.as_untyped()?);
                 self.position += 2;
@@ -140,25 +218,7 @@ impl<'buf> Parser<'buf> {
             }
             LexerToken::Untyped(b) => {
                 debug!("[value] untyped");
-                if let Ok(val) = self.parse_int_v(b) {
-                    debug!("[value] int: {:?}", val);
-                    return Ok(val);
-                }
-                if let Ok(val) = self.parse_float(b) {
-                    debug!("[value] float: {:?}", val);
-                    return Ok(val);
-                }
-                if let Ok(val) = self.parse_bool(b) {
-                    debug!("[value] bool: {:?}", val);
-                    return Ok(val);
-                }
-                if let Ok(val) = self.parse_date_v(b) {
-                    debug!("[value] date: {:?}", val);
-                    return Ok(val);
-                }
-                let val = self.parse_identifier_v(b);
-                debug!("[value] identifier: {:?}", val);
-                val
+                self.parse_untyped_value(b)
             }
             // Lists / Dicts: LEFTCURLY [VALUE..][COMMA] RIGHTCURLY
             LexerToken::LeftCurly => {
@@ -173,172 +233,234 @@ impl<'buf> Parser<'buf> {
         Ok(value)
     }
 
-    fn parse_dict(&mut self) -> Result<ClVal, Error> {
+    fn parse_untyped_value(&self, b: &[u8]) -> ClVal {
+        if let Ok(val) = self.parse_int_v(b) {
+            debug!("[value] int: {:?}", val);
+            return val;
+        }
+        if let Ok(val) = self.parse_float(b) {
+            debug!("[value] float: {:?}", val);
+            return val;
+        }
+        if let Ok(val) = self.parse_bool(b) {
+            debug!("[value] bool: {:?}", val);
+            return val;
+        }
+        if let Ok(val) = self.parse_date_v(b) {
+            debug!("[value] date: {:?}", val);
+            return val;
+        }
+        let val = self.parse_identifier_v(b);
+        debug!("[value] identifier: {:?}", val);
+        val
+    }
+
+    /// Parse the entries of a block opened by the left curly at `open`
+    fn parse_dict(&mut self, open: usize) -> Result<ClVal, Error> {
         let mut dict = HashMap::new();
 
-        while self.position < self.tokens.len() {
+        while self.parse_block_end(open)? {
+            let start = self.position;
             let key = match self.parse_key() {
                 Ok(key) => key,
-                Err(e) => match e.kind() {
-                    ErrorKind::InvalidToken => {
-                        info!("[parse_dict] got an invalid token for key");
-                        continue;
-                    }
-                    _ => bail!(e),
-                },
+                Err(e) => {
+                    self.skip(e, start, true)?;
+                    continue;
+                }
             };
             debug!("[parse_dict] got key: {:?}", key);
-            {
-                let token = &self.tokens[self.position];
-                debug!("[parse_dict] next token at {}: {:?}", self.position, token);
-                // equals is optional for dicts
-                if token.is_equals() {
-                    self.position += 1;
-                } else {
-                    info!("expected equals, but found: {:?}", token);
-                }
-            }
+            self.parse_equals(start, &key)?;
             let value = match self.parse_value() {
                 Ok(value) => value,
-                Err(e) => match e.kind() {
-                    ErrorKind::InvalidToken => {
-                        info!("[parse_dict] got an invalid token for value: {:?}", self.tokens.get(self.position - 1));
-                        continue;
-                    }
-                    _ => bail!(e),
-                },
+                Err(e) => {
+                    self.missing_value(e, start, &key, true)?;
+                    continue;
+                }
             };
             debug!("[parse_dict] got value: {:?}", value);
 
             // check for implicit lists
-            if dict.contains_key(&key) {
-                debug!("[parse_dict] key already exists");
-                if let ClVal::List(_) = dict.get(&key).unwrap() {
-                    // append to the existing list
-                    let list = dict.get_mut(&key).unwrap();
-                    debug!("[parse_dict] pushing to list");
-                    list.as_list_mut().unwrap().push(value);
-                } else {
-                    // create a new list from the stored value
-                    let dict_value = dict.remove(&key).unwrap();
-                    debug!("[parse_dict] converting to a list");
-                    let mut list = vec![dict_value];
-                    list.push(value);
-                    dict.insert(key, ClVal::List(list));
-                }
-            } else {
-                dict.insert(key, value);
-            }
-            if self.position >= self.tokens.len() {
-                debug!("[parse_dict] reached EOF");
-                break;
-            }
-
-            debug!("[parse_dict] next token at {}: {:?}", self.position, self.tokens[self.position]);
-            // peek the next token
-            match self.tokens[self.position] {
-                // right curly -> end dict
-                LexerToken::RightCurly => {
-                    self.position += 1;
-                    self.current_indent -= 1;
-                    debug!("[parse_dict] got right curly -> return");
-                    debug!("[parse_dict] indent now {}", self.current_indent);
-                    break;
-                }
-                // Optional comma
-                LexerToken::Comma => {
-                    self.position += 1;
-                }
-                _ => {}
-            }
+            insert_or_append(&mut dict, key, value);
+            self.parse_comma()?;
         }
 
         Ok(ClVal::Dict(dict))
     }
 
-    fn parse_list(&mut self, first: Option<ClVal>) -> Result<ClVal, Error> {
+    /// Parse the values of a block opened by the left curly at `open`
+    fn parse_list(&mut self, open: usize, first: Option<ClVal>) -> Result<ClVal, Error> {
         let mut list = Vec::new();
         if let Some(first) = first {
             debug!("[parse_list] got first value: {:?}", first);
             list.push(first);
+            self.parse_comma()?;
         }
 
-        while self.position < self.tokens.len() {
+        while self.parse_block_end(open)? {
+            let start = self.position;
             let value = match self.parse_value() {
                 Ok(value) => value,
-                Err(e) => match e.kind() {
-                    ErrorKind::InvalidToken => {
-                        info!("[parse_list] got an invalid token");
-                        continue;
-                    }
-                    _ => bail!(e),
-                },
+                Err(e) => {
+                    self.skip(e, start, true)?;
+                    continue;
+                }
             };
             debug!("[parse_list] got value: {:?}", value);
             list.push(value);
-            if self.position >= self.tokens.len() {
-                debug!("[parse_list] reached EOF");
-                break;
+            self.parse_comma()?;
+        }
+
+        Ok(ClVal::List(list))
+    }
+
+    /// Consume the right curly closing a block, returns `false` at the end of the block
+    ///
+    /// A block still open at EOF ends there.
+    fn parse_block_end(&mut self, open: usize) -> Result<bool, Error> {
+        match self.tokens.get(self.position) {
+            Some(LexerToken::RightCurly) => {
+                self.position += 1;
+                self.current_indent -= 1;
+                debug!("[block] got right curly, indent now {}", self.current_indent);
+                Ok(false)
             }
+            Some(_) => Ok(true),
+            None => {
+                debug!("[block] reached EOF");
+                self.report(Severity::Error, "unclosed-block", open, open + 1, "unclosed block".to_string())?;
+                Ok(false)
+            }
+        }
+    }
 
-            debug!("[parse_list] next token at {}: {:?}", self.position, self.tokens[self.position]);
-            // peek the next token
-            match self.tokens[self.position] {
-                // right curly -> end dict
-                LexerToken::RightCurly => {
-                    self.position += 1;
-                    self.current_indent -= 1;
-                    debug!("[parse_list] got right curly -> return");
-                    debug!("[parse_list] indent now {}", self.current_indent);
-                    break;
-                }
-                // optional comma
-                LexerToken::Comma => {
-                    self.position += 1;
+    /// Skip an optional comma between values
+    fn parse_comma(&mut self) -> Result<(), Error> {
+        if let Some(LexerToken::Comma) = self.tokens.get(self.position) {
+            self.position += 1;
+            match self.tokens.get(self.position) {
+                Some(LexerToken::Comma) | Some(LexerToken::RightCurly) | None => {
+                    let pos = self.position - 1;
+                    self.report(Severity::Warning, "trailing-comma", pos, pos + 1, "trailing `,`".to_string())?;
                 }
                 _ => {}
             }
         }
-
-        Ok(ClVal::List(list))
+        Ok(())
     }
 
     fn parse_collection(&mut self) -> Result<ClVal, Error> {
-        // the first value for the list
-        let first;
-        let old_pos;
-        // peek the next token and value to check if it's empty, a list or a dict
-        let is_dict = {
-            old_pos = self.position;
-            debug!("[collection] next token: {:?}", self.tokens[self.position]);
-            // check for empty collections
-            if let LexerToken::RightCurly = self.tokens[self.position] {
-                self.position += 1;
-                return Ok(ClVal::List(Vec::new()));
+        let open = self.position - 1;
+        // skip everything which can't start a value
+        while let Some(token) = self.tokens.get(self.position) {
+            match token {
+                LexerToken::Quote | LexerToken::Untyped(_) | LexerToken::LeftCurly | LexerToken::RightCurly => break,
+                _ => {
+                    self.position += 1;
+                    let pos = self.position - 1;
+                    self.skip(ErrorKind::InvalidToken.into(), pos, true)?;
+                }
             }
-            // parse the next value
-            let value = self.parse_value()?;
-            debug!("[collection] next entry: {:?}", value);
-            let token = &self.tokens[self.position];
-            first = Some(value);
-
-            debug!("[collection] next token: {:?}", token);
-            // check if the next token after the value is an equals
-            token.is_equals()
-        };
+        }
+        // check for empty collections
+        if !self.parse_block_end(open)? {
+            return Ok(ClVal::List(Vec::new()));
+        }
 
-        if is_dict {
+        // peek the next token and value to check if it's a list or a dict
+        let old_pos = self.position;
+        let first = match self.parse_value() {
+            Ok(value) => value,
+            Err(e) => {
+                self.skip(e, old_pos, true)?;
+                return self.parse_list(open, None);
+            }
+        };
+        debug!("[collection] next entry: {:?}", first);
+        // check if the next token after the value is an equals
+        if self.tokens.get(self.position).map(LexerToken::is_equals).unwrap_or(false) {
             // reset the position for dicts, since the first parsed value must ne a ClKey
             // and we parsed a ClVal
             self.position = old_pos;
             debug!("[collection] dict");
-            self.parse_dict()
+            self.parse_dict(open)
         } else {
             debug!("[collection] list");
-            self.parse_list(first)
+            self.parse_list(open, Some(first))
+        }
+    }
+
+    /// The token at the current position
+    fn peek(&self) -> Result<LexerToken<'buf>, Error> {
+        match self.tokens.get(self.position) {
+            Some(token) => Ok(token.clone()),
+            None => bail!(ErrorKind::UnexpectedEof),
+        }
+    }
+
+    /// Skip the token at `pos` which could not be parsed
+    fn skip(&mut self, e: Error, pos: usize, tolerated: bool) -> Result<(), Error> {
+        if !self.handles(&e, tolerated) {
+            bail!(e);
+        }
+        match self.tokens.get(pos) {
+            Some(token) => {
+                // the game ignores parentheses outside of values
+                let severity = match token {
+                    LexerToken::LeftParanthesis | LexerToken::RightParanthesis => Severity::Warning,
+                    _ => Severity::Error,
+                };
+                let message = format!("unexpected `{}`", token_text(token));
+                self.report(severity, "unexpected-token", pos, pos + 1, message)
+            }
+            None => self.report(Severity::Error, "unexpected-eof", pos, pos, e.to_string()),
+        }
+    }
+
+    /// Drop the entry starting at `start` whose value could not be parsed
+    fn missing_value(&mut self, e: Error, start: usize, key: &ClKey, tolerated: bool) -> Result<(), Error> {
+        if !self.handles(&e, tolerated) {
+            bail!(e);
+        }
+        let message = format!("`{}` has no value", key);
+        let end = self.position.max(start + 1);
+        self.report(Severity::Error, "missing-value", start, end, message)
+    }
+
+    /// Whether an error becomes a diagnostic, invalid tokens inside of blocks may be `tolerated`
+    ///
+    /// Every error does when recovering or in strict mode, except for the located errors of
+    /// nested blocks.
+    fn handles(&self, e: &Error, tolerated: bool) -> bool {
+        match e.kind() {
+            ErrorKind::Syntax(_) => false,
+            ErrorKind::InvalidToken if tolerated => true,
+            _ => self.recover || self.options.strict,
         }
     }
 
+    /// Record a diagnostic for the tokens from `from` to `to` (exclusive)
+    ///
+    /// Everything is an error in strict mode, which fails unless the parser recovers.
+    fn report(&mut self, severity: Severity, code: &str, from: usize, to: usize, message: String) -> Result<(), Error> {
+        info!("{}", message);
+        let span = match (self.spans.get(from), self.spans.get(to.max(from + 1) - 1)) {
+            (Some(first), Some(last)) => Span::new(first.start, last.end),
+            (Some(first), None) => *first,
+            // at EOF
+            _ => self.spans.last().map(|last| Span::new(last.end, last.end)).unwrap_or_default(),
+        };
+        if self.options.strict {
+            let diagnostic = Diagnostic::error(code, span, message);
+            if !self.recover {
+                bail!(ErrorKind::Syntax(diagnostic));
+            }
+            self.diagnostics.push(diagnostic);
+        } else {
+            self.diagnostics.push(Diagnostic::new(severity, code, span, message));
+        }
+        Ok(())
+    }
+
     fn parse_identifier(&self, buf: &[u8]) -> ClKey {
         ClKey::Identifier(to_string(buf).to_string())
     }
@@ -397,6 +519,31 @@ impl<'buf> Parser<'buf> {
     }
 }
 
+/// Type an unquoted key the way the parser does: integer, date or identifier
+pub(crate) fn untyped_key(buf: &[u8]) -> ClKey {
+    Parser::default().parse_untyped_key(buf)
+}
+
+/// Type an unquoted value the way the parser does: integer, float, bool, date or identifier
+pub(crate) fn untyped_value(buf: &[u8]) -> ClVal {
+    Parser::default().parse_untyped_value(buf)
+}
+
+/// The text of a token in messages
+fn token_text(token: &LexerToken) -> String {
+    match token {
+        LexerToken::Equals => "=".to_string(),
+        LexerToken::Quote => "\"".to_string(),
+        LexerToken::LeftCurly => "{".to_string(),
+        LexerToken::RightCurly => "}".to_string(),
+        LexerToken::LeftParanthesis => "(".to_string(),
+        LexerToken::RightParanthesis => ")".to_string(),
+        LexerToken::Comment => "#".to_string(),
+        LexerToken::Comma => ",".to_string(),
+        LexerToken::Untyped(buf) => String::from_utf8_lossy(buf).into_owned(),
+    }
+}
+
 fn to_string(b: &[u8]) -> &str {
     unsafe { from_utf8_unchecked(b) }
 }
@@ -572,6 +719,27 @@ mod tests {
         assert_eq!(parser.parse().unwrap(), val_dict(dict));
     }
 
+    #[test]
+    fn test_parse_single_item_list() {
+        let tokens = vec![
+            untyped(b"key"),
+            equals(),
+            c_left(),
+            quote(),
+            untyped(b"1.24.1.0"),
+            quote(),
+            c_right(),
+            untyped(b"key2"),
+            equals(),
+            untyped(b"value"),
+        ];
+        let mut parser = Parser::new(tokens);
+        let mut dict = HashMap::new();
+        dict.insert(key_id("key"), val_list(vec![val_s("1.24.1.0")]));
+        dict.insert(key_id("key2"), val_id("value"));
+        assert_eq!(parser.parse().unwrap(), val_dict(dict));
+    }
+
     #[test]
     fn test_parse_dict() {
         let tokens = vec![
@@ -620,4 +788,119 @@ mod tests {
         dict.insert(key_id("key"), val_dict(dict2));
         assert_eq!(parser.parse().unwrap(), val_dict(dict));
     }
+
+    fn parse_diagnostics(buf: &[u8]) -> (ClVal, Vec<String>) {
+        let mut parser = Parser::with_spans(Tokenizer::new(buf).tokenize_with_spans());
+        let (values, diagnostics) = parser.parse_with_diagnostics();
+        let diagnostics = diagnostics
+            .iter()
+            .map(|d| format!("{} {}", String::from_utf8_lossy(&buf[d.span.start..d.span.end]), d))
+            .collect();
+        (values, diagnostics)
+    }
+
+    #[test]
+    fn test_parse_with_diagnostics() {
+        let (values, diagnostics) = parse_diagnostics(b"a = { b = ) c { d = 1 } e = 2, , f = }\ng = h )\ni = { 1, 2 = }\n} j = {");
+        let mut a = HashMap::new();
+        a.insert(key_id("c"), val_dict([(key_id("d"), val_i(1))].iter().cloned().collect()));
+        a.insert(key_id("e"), val_i(2));
+        let mut dict = HashMap::new();
+        dict.insert(key_id("a"), val_dict(a));
+        dict.insert(key_id("g"), val_id("h"));
+        dict.insert(key_id("i"), val_list(vec![val_i(1), val_i(2)]));
+        dict.insert(key_id("j"), val_list(vec![]));
+        assert_eq!(values, val_dict(dict));
+        assert_eq!(
+            diagnostics,
+            vec![
+                "b = ) error[missing-value]: `b` has no value",
+                "c warning[missing-equals]: missing `=` after `c`",
+                ", warning[trailing-comma]: trailing `,`",
+                ", error[unexpected-token]: unexpected `,`",
+                "f = error[missing-value]: `f` has no value",
+                ") warning[unexpected-token]: unexpected `)`",
+                "= error[unexpected-token]: unexpected `=`",
+                "} error[unexpected-token]: unexpected `}`",
+                "{ error[unclosed-block]: unclosed block",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_eof() {
+        for buf in &[&b"foo"[..], b"foo =", b"foo = \"", b"foo = { bar ="] {
+            let tokens = Tokenizer::new(buf).tokenize();
+            assert_eq!(
+                Parser::new(tokens).parse().unwrap_err().to_string(),
+                "unexpected end of input"
+            );
+        }
+        let (values, diagnostics) = parse_diagnostics(b"foo = { bar =");
+        assert_eq!(values, val_dict([(key_id("foo"), val_dict(HashMap::new()))].iter().cloned().collect()));
+        assert_eq!(
+            diagnostics,
+            vec![
+                "bar = error[missing-value]: `bar` has no value",
+                "{ error[unclosed-block]: unclosed block"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let tokens = Tokenizer::new(b"# header\nkey = { # first\n\ta = b # trailing\n}").tokenize();
+        let mut dict = HashMap::new();
+        dict.insert(key_id("key"), val_dict([(key_id("a"), val_id("b"))].iter().cloned().collect()));
+        assert_eq!(Parser::new(tokens).parse().unwrap(), val_dict(dict));
+    }
+
+    fn parse_strict(buf: &[u8]) -> Result<ClVal, String> {
+        let tokens = Tokenizer::new(buf).tokenize_with_spans();
+        let mut parser = Parser::with_spans(tokens).options(ParserOptions { strict: true });
+        parser.parse().map_err(|e| match e.kind() {
+            ErrorKind::Syntax(d) => format!("{} {}", String::from_utf8_lossy(&buf[d.span.start..d.span.end]), d),
+            _ => e.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        assert!(parse_strict(b"a = { b = 1, c = 2 } d = { 1, 2 }\ne = \"f\" # comment").is_ok());
+        let errors = [
+            (&b"a { b = 1 }"[..], "a error[missing-equals]: missing `=` after `a`"),
+            (b"a = { b = 1 ) }", ") error[unexpected-token]: unexpected `)`"),
+            (b"a = { 1, 2, }", ", error[trailing-comma]: trailing `,`"),
+            (b"a = { b = 1, , c = 2 }", ", error[trailing-comma]: trailing `,`"),
+            (b"a = { b = { c = 1 }", "{ error[unclosed-block]: unclosed block"),
+            (b"a = 1\nb = 2\na = 3", "a error[duplicate-key]: duplicate key `a`"),
+            (b"a = { b = }", "b = error[missing-value]: `b` has no value"),
+            (b"a = 1 }", "} error[unexpected-token]: unexpected `}`"),
+        ];
+        for (buf, error) in errors.iter() {
+            assert_eq!(parse_strict(buf).unwrap_err(), *error);
+        }
+
+        // the same input is tolerated otherwise
+        for (buf, _) in errors[..6].iter() {
+            assert!(Parser::new(Tokenizer::new(buf).tokenize()).parse().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_parse_strict_diagnostics() {
+        let tokens = Tokenizer::new(b"a { b = 1, } a = 2").tokenize_with_spans();
+        let mut parser = Parser::with_spans(tokens).options(ParserOptions { strict: true });
+        let (values, diagnostics) = parser.parse_with_diagnostics();
+        assert_eq!(values, val_dict([(key_id("a"), val_i(2))].iter().cloned().collect()));
+        let diagnostics: Vec<_> = diagnostics.iter().map(Diagnostic::to_string).collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                "error[missing-equals]: missing `=` after `a`",
+                "error[trailing-comma]: trailing `,`",
+                "error[duplicate-key]: duplicate key `a`",
+            ]
+        );
+    }
 }
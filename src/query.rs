@@ -0,0 +1,497 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Key path queries
+//!
+//! A small language to select values from a [**ClVal**](../clval/enum.ClVal.html) tree:
+//!
+//! | Syntax          | Selects                                                          |
+//! |-----------------|------------------------------------------------------------------|
+//! | `key`, `"key"`  | the value of `key` in a dict                                     |
+//! | `*`             | all values of a dict or all items of a list                      |
+//! | `[2]`           | the item at index 2 of a list, a single value counts as index 0  |
+//! | `[*]`           | all items of a list or all values of a dict                      |
+//! | `[owner=PRU]`   | only values which are dicts with `owner` set to `PRU`            |
+//! | `[owner!=PRU]`  | only values which don't have `owner` set to `PRU`                |
+//!
+//! Keys are separated by dots: `countries.PRU.ledger.income`, `provinces.*[owner=PRU].name`.
+//! Keys containing dots or brackets, like dates, have to be quoted: `"1444.11.11".event`.
+//! Wildcards return the values of a dict in key order.
+//!
+//! Repeated keys are parsed into lists, so a key or filter applied to a list is applied to each
+//! of its items. That way `add_core` selects the same way whether it occurs once or more often.
+//!
+//! Keys match independently of how they were parsed: `foo` matches `foo` and `"foo"`,
+//! `12` matches the integer key `12` and the string key `"12"`.
+
+use clval::{ClKey, ClVal, Date};
+use error::{Error, ErrorKind};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single step of a [**Query**](struct.Query.html)
+pub enum Selector {
+    Key(String),
+    Wildcard,
+    Index(usize),
+    All,
+    Filter { key: String, value: String, negate: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A parsed query
+///
+/// # Example
+///
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::parse;
+/// use clausewitz_parser::query::Query;
+///
+/// fn main() {
+///     let values = parse(b"provinces={ -1={ owner=PRU } -2={ owner=FRA } -3={ owner=PRU } }").unwrap();
+///     let query: Query = "provinces.*[owner=PRU]".parse().unwrap();
+///
+///     assert_eq!(query.select(&values).len(), 2);
+/// }
+/// ```
+pub struct Query {
+    selectors: Vec<Selector>,
+}
+
+impl Query {
+    /// Construct a `Query` from selectors
+    pub fn new(selectors: Vec<Selector>) -> Self {
+        Self { selectors }
+    }
+
+    pub fn selectors(&self) -> &[Selector] {
+        &self.selectors
+    }
+
+    /// Evaluate the query, returning all matching values
+    pub fn select<'a>(&self, root: &'a ClVal) -> Vec<&'a ClVal> {
+        let mut current = vec![root];
+        for selector in &self.selectors {
+            let mut next = Vec::new();
+            for value in current {
+                apply(selector, value, &mut next);
+            }
+            current = next;
+        }
+        current
+    }
+}
+
+fn apply<'a>(selector: &Selector, value: &'a ClVal, out: &mut Vec<&'a ClVal>) {
+    match (selector, value) {
+        // keys and filters look through implicit lists
+        (Selector::Key(_), ClVal::List(list)) | (Selector::Filter { .. }, ClVal::List(list)) => {
+            for item in list {
+                apply(selector, item, out);
+            }
+        }
+        (Selector::Key(key), ClVal::Dict(dict)) => {
+            out.extend(dict.iter().filter(|(k, _)| k.matches(key)).map(|(_, v)| v));
+        }
+        (Selector::Key(_), _) => {}
+        (Selector::Wildcard, ClVal::Dict(dict)) | (Selector::All, ClVal::Dict(dict)) => {
+            let mut entries: Vec<_> = dict.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.extend(entries.into_iter().map(|(_, v)| v));
+        }
+        (Selector::Wildcard, ClVal::List(list)) | (Selector::All, ClVal::List(list)) => out.extend(list.iter()),
+        (Selector::Wildcard, _) => {}
+        (Selector::All, _) => out.push(value),
+        (Selector::Index(index), ClVal::List(list)) => out.extend(list.get(*index)),
+        (Selector::Index(0), _) => out.push(value),
+        (Selector::Index(_), _) => {}
+        (Selector::Filter { key, value: expected, negate }, _) => {
+            if filter_matches(value, key, expected) != *negate {
+                out.push(value);
+            }
+        }
+    }
+}
+
+fn filter_matches(value: &ClVal, key: &str, expected: &str) -> bool {
+    let dict = match value {
+        ClVal::Dict(dict) => dict,
+        _ => return false,
+    };
+    dict.iter()
+        .filter(|(k, _)| k.matches(key))
+        .any(|(_, v)| match v {
+            ClVal::List(list) => list.iter().any(|v| scalar_matches(v, expected)),
+            _ => scalar_matches(v, expected),
+        })
+}
+
+fn scalar_matches(value: &ClVal, s: &str) -> bool {
+    match value {
+        ClVal::Identifier(v) | ClVal::String(v) => v == s,
+        ClVal::Integer(i) => s.parse::<i32>().map(|s| s == *i).unwrap_or(false),
+        ClVal::Float(f) => s.parse::<f32>().map(|s| s == *f).unwrap_or(false),
+        ClVal::Date(d) => s.parse().map(|s: Date| s == *d).unwrap_or(false),
+        ClVal::Bool(b) => s == if *b { "yes" } else { "no" },
+        ClVal::List(_) | ClVal::Dict(_) => false,
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        QueryParser { s, position: 0 }.parse()
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Selector::Key(key) => write_key(f, key),
+            Selector::Wildcard => write!(f, "*"),
+            Selector::Index(index) => write!(f, "[{}]", index),
+            Selector::All => write!(f, "[*]"),
+            Selector::Filter { key, value, negate } => {
+                write!(f, "[")?;
+                write_key(f, key)?;
+                write!(f, "{}", if *negate { "!=" } else { "=" })?;
+                write_key(f, value)?;
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, selector) in self.selectors.iter().enumerate() {
+            match selector {
+                Selector::Key(_) | Selector::Wildcard if i > 0 => write!(f, ".{}", selector)?,
+                _ => write!(f, "{}", selector)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write a key, quoted if it contains characters with a meaning in queries
+fn write_key(f: &mut Formatter, key: &str) -> fmt::Result {
+    if key.is_empty() || key == "*" || key.chars().any(is_special) {
+        write!(f, "\"{}\"", key)
+    } else {
+        write!(f, "{}", key)
+    }
+}
+
+fn is_special(c: char) -> bool {
+    match c {
+        '.' | '[' | ']' | '=' | '!' | '"' => true,
+        c => c.is_whitespace(),
+    }
+}
+
+struct QueryParser<'a> {
+    s: &'a str,
+    position: usize,
+}
+
+impl<'a> QueryParser<'a> {
+    fn error(&self, message: &str) -> Error {
+        ErrorKind::InvalidQuery(format!("{} at {}: {}", message, self.position, self.s)).into()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.s[self.position..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse(mut self) -> Result<Query, Error> {
+        let mut selectors = Vec::new();
+        while self.position < self.s.len() {
+            if self.eat('[') {
+                selectors.push(self.bracket()?);
+                continue;
+            }
+            if !selectors.is_empty() && !self.eat('.') {
+                return Err(self.error("expected . or ["));
+            }
+            if self.eat('*') {
+                selectors.push(Selector::Wildcard);
+            } else {
+                selectors.push(Selector::Key(self.key()?));
+            }
+        }
+        Ok(Query::new(selectors))
+    }
+
+    fn key(&mut self) -> Result<String, Error> {
+        if self.eat('"') {
+            let end = self.s[self.position..]
+                .find('"')
+                .ok_or_else(|| self.error("unclosed quote"))?;
+            let key = self.s[self.position..self.position + end].to_string();
+            self.position += end + 1;
+            return Ok(key);
+        }
+        let len = self.s[self.position..]
+            .find(is_special)
+            .unwrap_or(self.s.len() - self.position);
+        if len == 0 {
+            return Err(self.error("expected a key"));
+        }
+        let key = self.s[self.position..self.position + len].to_string();
+        self.position += len;
+        Ok(key)
+    }
+
+    fn bracket(&mut self) -> Result<Selector, Error> {
+        let selector = if self.eat('*') {
+            Selector::All
+        } else {
+            let key = self.key()?;
+            if self.eat('=') {
+                let value = self.key()?;
+                Selector::Filter { key, value, negate: false }
+            } else if self.eat('!') && self.eat('=') {
+                let value = self.key()?;
+                Selector::Filter { key, value, negate: true }
+            } else {
+                let index = key.parse().map_err(|_| self.error("expected an index or a filter"))?;
+                Selector::Index(index)
+            }
+        };
+        if !self.eat(']') {
+            return Err(self.error("expected ]"));
+        }
+        Ok(selector)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// A single step of a [**Path**](struct.Path.html)
+pub enum PathSegment {
+    /// The value of a key in a dict
+    Key(ClKey),
+    /// An item of a list
+    Index(usize),
+    /// The item of a list which is a dict with `key` set to `value`
+    Match { key: String, value: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+/// The location of a value in a `ClVal` tree
+///
+/// Paths are displayed as queries, so they can be used to look the value up again.
+pub struct Path {
+    segments: Vec<PathSegment>,
+}
+
+impl Path {
+    /// Construct an empty `Path`, pointing to the root
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// Append a segment
+    pub fn push(&mut self, segment: PathSegment) {
+        self.segments.push(segment);
+    }
+
+    /// Remove the last segment
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.segments.pop()
+    }
+
+    /// A new path with `segment` appended
+    pub fn join(&self, segment: PathSegment) -> Path {
+        let mut segments = self.segments.clone();
+        segments.push(segment);
+        Path { segments }
+    }
+
+    /// The query selecting the value at this path
+    pub fn to_query(&self) -> Query {
+        let selectors = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                PathSegment::Key(key) => Selector::Key(key.to_string()),
+                PathSegment::Index(index) => Selector::Index(*index),
+                PathSegment::Match { key, value } => Selector::Filter {
+                    key: key.clone(),
+                    value: value.clone(),
+                    negate: false,
+                },
+            })
+            .collect();
+        Query::new(selectors)
+    }
+}
+
+impl From<Vec<PathSegment>> for Path {
+    fn from(segments: Vec<PathSegment>) -> Self {
+        Path { segments }
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_query())
+    }
+}
+
+/// Parse and evaluate a query
+pub fn query<'a>(root: &'a ClVal, query: &str) -> Result<Vec<&'a ClVal>, Error> {
+    let query: Query = query.parse()?;
+    Ok(query.select(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAVE: &[u8] = b"
+        countries={
+            PRU={ ledger={ income=12.5 } add_core=PRU add_core=BRA }
+            FRA={ ledger={ income=20.0 } add_core=FRA }
+        }
+        provinces={
+            -1={ owner=PRU name=\"Berlin\" }
+            -2={ owner=FRA name=\"Paris\" }
+            -3={ owner=PRU name=\"Koenigsberg\" }
+        }
+        \"quoted.key\"=yes
+        1444.11.11={ event=foo }
+    ";
+
+    fn select<'a>(values: &'a ClVal, q: &str) -> Vec<&'a ClVal> {
+        query(values, q).unwrap()
+    }
+
+    fn ids(v: &[&str]) -> Vec<ClVal> {
+        v.iter().map(|s| ClVal::Identifier(s.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query: Query = "a.*[2][*][b=c][\"d e\"!=f].\"g.h\"".parse().unwrap();
+        assert_eq!(
+            query.selectors(),
+            &[
+                Selector::Key("a".to_string()),
+                Selector::Wildcard,
+                Selector::Index(2),
+                Selector::All,
+                Selector::Filter { key: "b".to_string(), value: "c".to_string(), negate: false },
+                Selector::Filter { key: "d e".to_string(), value: "f".to_string(), negate: true },
+                Selector::Key("g.h".to_string()),
+            ]
+        );
+        assert_eq!(query.to_string(), "a.*[2][*][b=c][\"d e\"!=f].\"g.h\"");
+        assert_eq!("[0].a".parse::<Query>().unwrap().to_string(), "[0].a");
+    }
+
+    #[test]
+    fn test_parse_query_errors() {
+        assert_eq!(
+            "a..b".parse::<Query>().unwrap_err().to_string(),
+            "invalid query: expected a key at 2: a..b"
+        );
+        assert_eq!(
+            "a[x]".parse::<Query>().unwrap_err().to_string(),
+            "invalid query: expected an index or a filter at 3: a[x]"
+        );
+        assert_eq!(
+            "a[1".parse::<Query>().unwrap_err().to_string(),
+            "invalid query: expected ] at 3: a[1"
+        );
+        assert!("\"a".parse::<Query>().is_err());
+        assert!("a b".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn test_path() {
+        let path = Path::new()
+            .join(PathSegment::Key(ClKey::Identifier("countries".to_string())))
+            .join(PathSegment::Key(ClKey::Date(Date::new(1444, 11, 11))))
+            .join(PathSegment::Index(2))
+            .join(PathSegment::Match { key: "id".to_string(), value: "5".to_string() })
+            .join(PathSegment::Key(ClKey::Integer(-1)));
+        assert_eq!(path.to_string(), "countries.\"1444.11.11\"[2][id=5].-1");
+        assert_eq!(path.to_string().parse::<Query>().unwrap(), path.to_query());
+        assert_eq!(Path::new().to_string(), "");
+    }
+
+    #[test]
+    fn test_select_keys() {
+        let values = ::parse(SAVE).unwrap();
+        assert_eq!(select(&values, "countries.PRU.ledger.income"), vec![&ClVal::Float(12.5)]);
+        assert_eq!(select(&values, "\"quoted.key\""), vec![&ClVal::Bool(true)]);
+        assert_eq!(select(&values, "provinces.-2.owner"), ids(&["FRA"]).iter().collect::<Vec<_>>());
+        assert_eq!(select(&values, "1444.11.11.event"), Vec::<&ClVal>::new());
+        assert_eq!(select(&values, "\"1444.11.11\".event"), ids(&["foo"]).iter().collect::<Vec<_>>());
+        assert!(select(&values, "countries.SWE").is_empty());
+    }
+
+    #[test]
+    fn test_select_wildcards() {
+        let values = ::parse(SAVE).unwrap();
+        assert_eq!(
+            select(&values, "countries.*.add_core[*]"),
+            ids(&["FRA", "PRU", "BRA"]).iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            select(&values, "provinces[*].owner"),
+            ids(&["PRU", "FRA", "PRU"]).iter().collect::<Vec<_>>()
+        );
+        assert_eq!(select(&values, "countries.PRU.add_core[1]"), ids(&["BRA"]).iter().collect::<Vec<_>>());
+        assert_eq!(select(&values, "countries.FRA.add_core[0]"), ids(&["FRA"]).iter().collect::<Vec<_>>());
+        assert!(select(&values, "countries.FRA.add_core[1]").is_empty());
+    }
+
+    #[test]
+    fn test_select_filters() {
+        let values = ::parse(SAVE).unwrap();
+        assert_eq!(
+            select(&values, "provinces.*[owner=PRU].name"),
+            vec![&ClVal::String("Koenigsberg".to_string()), &ClVal::String("Berlin".to_string())]
+        );
+        assert_eq!(
+            select(&values, "provinces.*[owner!=PRU].name"),
+            vec![&ClVal::String("Paris".to_string())]
+        );
+        assert_eq!(select(&values, "countries.*[add_core=BRA]").len(), 1);
+        assert_eq!(select(&values, "countries.*[ledger=yes]").len(), 0);
+    }
+}
@@ -0,0 +1,714 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Schema based validation of script files
+//!
+//! A schema describes which keys are allowed in a block, the types of their values and how
+//! often they may occur. Schemas are written in Clausewitz syntax:
+//!
+//! ```text
+//! # the top level of a file
+//! root = {
+//!     namespace = identifier
+//!     country_event = { type = block rule = event }
+//! }
+//!
+//! # named block rules
+//! rules = {
+//!     event = {
+//!         id = { type = identifier min = 1 max = 1 }
+//!         is_triggered_only = { type = bool max = 1 }
+//!         difficulty = { type = enum values = { easy normal hard } }
+//!         option = { type = block min = 1 fields = { name = string "*" = any } }
+//!     }
+//! }
+//!
+//! # named lists of allowed identifiers
+//! enums = {
+//!     continents = { europe asia africa }
+//! }
+//!
+//! # the top level of the files in a directory and its subdirectories, in addition to `root`
+//! paths = {
+//!     "common/scripted_triggers" = { "*" = { type = block fields = { "*" = any } } }
+//! }
+//! ```
+//!
+//! A field is either a type name or a block with the `type` and these optional settings:
+//!
+//! * `min`, `max`: how often the key may occur, by default 0 and unlimited
+//! * `min_value`, `max_value`: the range of an `int`
+//! * `values`, `enum`: the allowed identifiers of an `enum`, inline or by the name of an enum
+//! * `rule`, `fields`: the rule of a `block`, by name or inline
+//! * `items`: the type of the items of a `list`, `scalar` by default
+//!
+//! The types are `any`, `scalar`, `bool`, `int`, `float`, `date`, `string` (quoted or not),
+//! `identifier`, `scope` (`ROOT`, `FROM`, `PREV`, `THIS`, country tags and province ids),
+//! `enum`, `block` and `list`. Repeating a key declares alternatives, the value has to match
+//! one of them. The key `"*"` matches every key without its own field.
+//!
+//! CWTools rule files can be used as well, see the [`cwt` module](../cwt/index.html).
+//!
+//! Validation works on a [**SyntaxTree**](../cst/struct.SyntaxTree.html), so every
+//! [**Diagnostic**](../diagnostic/struct.Diagnostic.html) points to its source location.
+
+use clval::{ClKey, ClVal};
+use cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxTree, SyntaxToken};
+use cwt::{from_cwt, resolve};
+use diagnostic::Diagnostic;
+use diff::scalar_text;
+use error::{Error, ErrorKind};
+use parser::untyped_value;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+use token::Span;
+use walk::files;
+
+/// The key matching every key without its own field
+pub const ANY_KEY: &str = "*";
+
+#[derive(Debug, Clone, PartialEq)]
+/// The expected type of a value
+pub enum ValueType {
+    Any,
+    /// Any value which is not a block
+    Scalar,
+    Bool,
+    Int { min: Option<i64>, max: Option<i64> },
+    /// An integer or a float
+    Float,
+    Date,
+    /// A quoted string or an identifier
+    String,
+    Identifier,
+    Scope,
+    /// One of the listed identifiers
+    Enum(Vec<String>),
+    /// One of the identifiers of a named enum of the schema
+    EnumRef(String),
+    /// A block of entries with an inline rule
+    Block(Rule),
+    /// A block of entries with a named rule of the schema
+    RuleRef(String),
+    /// A block of scalar items
+    List(Box<ValueType>),
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ValueType::Any => f.write_str("any value"),
+            ValueType::Scalar => f.write_str("a scalar"),
+            ValueType::Bool => f.write_str("bool"),
+            ValueType::Int { min: None, max: None } => f.write_str("int"),
+            ValueType::Int { min, max } => write!(
+                f,
+                "int[{}..{}]",
+                min.map(|m| m.to_string()).unwrap_or_default(),
+                max.map(|m| m.to_string()).unwrap_or_default()
+            ),
+            ValueType::Float => f.write_str("float"),
+            ValueType::Date => f.write_str("date"),
+            ValueType::String => f.write_str("string"),
+            ValueType::Identifier => f.write_str("identifier"),
+            ValueType::Scope => f.write_str("scope"),
+            ValueType::Enum(values) => {
+                let values: Vec<_> = values.iter().map(|v| format!("`{}`", v)).collect();
+                write!(f, "one of {}", values.join(", "))
+            }
+            ValueType::EnumRef(name) => write!(f, "{}", name),
+            ValueType::Block(_) | ValueType::RuleRef(_) => f.write_str("a block"),
+            ValueType::List(items) => write!(f, "a list of {}", items),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// An allowed key of a block
+pub struct Field {
+    pub key: String,
+    pub value: ValueType,
+    /// The minimum number of occurrences
+    pub min: usize,
+    /// The maximum number of occurrences, `None` for unlimited
+    pub max: Option<usize>,
+}
+
+impl Field {
+    /// A field which may occur any number of times
+    pub fn new(key: &str, value: ValueType) -> Self {
+        Self {
+            key: key.to_string(),
+            value,
+            min: 0,
+            max: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// The allowed keys of a block
+pub struct Rule {
+    pub fields: Vec<Field>,
+}
+
+impl Rule {
+    /// The fields for a key, falling back to the `"*"` fields
+    fn fields_for(&self, key: &str) -> Vec<&Field> {
+        let fields: Vec<_> = self.fields.iter().filter(|f| f.key == key).collect();
+        if fields.is_empty() {
+            self.fields.iter().filter(|f| f.key == ANY_KEY).collect()
+        } else {
+            fields
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// A set of rules for validating script files
+pub struct Schema {
+    /// The rule of the top level of a file
+    pub root: Rule,
+    /// The rules of the top level of the files in a directory, like `events`
+    pub paths: HashMap<String, Rule>,
+    pub rules: HashMap<String, Rule>,
+    pub enums: HashMap<String, Vec<String>>,
+}
+
+impl Schema {
+    /// Load a schema file, or all `.rules` and `.cwt` files in a directory and its subdirectories
+    ///
+    /// `.cwt` files are CWTools rule files, see the [`cwt` module](../cwt/index.html).
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Schema, Error> {
+        let mut schema = Schema::default();
+        let mut cwt = false;
+        let path = path.as_ref();
+        let files = if path.is_dir() {
+            files(path, &["rules", "cwt"], &[]).map_err(|e| invalid(e.to_string()))?
+        } else {
+            vec![path.to_path_buf()]
+        };
+        for file in files {
+            let buf = fs::read(&file).map_err(|e| invalid(format!("{}: {}", file.display(), e)))?;
+            let other = if file.extension().map(|e| e == "cwt").unwrap_or(false) {
+                cwt = true;
+                from_cwt(&buf)
+            } else {
+                Schema::from_slice(&buf)
+            };
+            schema.extend(other.map_err(|e| invalid(format!("{}: {}", file.display(), e)))?);
+        }
+        if cwt {
+            resolve(&mut schema);
+        }
+        schema.check()?;
+        Ok(schema)
+    }
+
+    /// Parse a schema
+    ///
+    /// References to named rules and enums aren't checked, as they may be defined in another
+    /// file. Use [`check`](#method.check) after all files are loaded.
+    pub fn from_slice(buf: &[u8]) -> Result<Schema, Error> {
+        let values = SyntaxTree::parse(buf).to_clval()?;
+        let mut schema = Schema::default();
+        for (key, value) in sorted(&values)? {
+            match key.to_string().as_str() {
+                "root" => schema.root = parse_rule(value)?,
+                "rules" => {
+                    for (name, rule) in sorted(value)? {
+                        schema.rules.insert(name.to_string(), parse_rule(rule)?);
+                    }
+                }
+                "enums" => {
+                    for (name, values) in sorted(value)? {
+                        schema.enums.insert(name.to_string(), scalars(values)?);
+                    }
+                }
+                "paths" => {
+                    for (path, rule) in sorted(value)? {
+                        schema.paths.insert(path.to_string(), parse_rule(rule)?);
+                    }
+                }
+                key => bail!(invalid(format!("unknown section `{}`", key))),
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Add the rules and enums of another schema, its root fields are appended to the root rules
+    pub fn extend(&mut self, other: Schema) {
+        self.root.fields.extend(other.root.fields);
+        for (path, rule) in other.paths {
+            self.paths.entry(path).or_default().fields.extend(rule.fields);
+        }
+        self.rules.extend(other.rules);
+        self.enums.extend(other.enums);
+    }
+
+    /// Check that all referenced rules and enums exist
+    pub fn check(&self) -> Result<(), Error> {
+        let rules = Some(&self.root).into_iter().chain(self.paths.values()).chain(self.rules.values());
+        for field in rules.flat_map(|rule| all_fields(rule)) {
+            match &field.value {
+                ValueType::RuleRef(name) if !self.rules.contains_key(name) => {
+                    bail!(invalid(format!("unknown rule `{}`", name)))
+                }
+                ValueType::EnumRef(name) if !self.enums.contains_key(name) => {
+                    bail!(invalid(format!("unknown enum `{}`", name)))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate a file with the root rule, the diagnostics are in source order
+    pub fn validate(&self, tree: &SyntaxTree) -> Vec<Diagnostic> {
+        self.validate_with(&self.root, tree)
+    }
+
+    /// Validate the file at `path`, the rules of the directories it is in apply as well
+    pub fn validate_file<P: AsRef<Path>>(&self, path: P, tree: &SyntaxTree) -> Vec<Diagnostic> {
+        let mut paths: Vec<_> = self.paths.iter().collect();
+        paths.sort_by_key(|(path, _)| *path);
+        let mut root = self.root.clone();
+        for (dir, rule) in paths {
+            let parent = path.as_ref().parent();
+            if parent.map(|p| p.ancestors().any(|a| a.ends_with(dir))).unwrap_or(false) {
+                root.fields.extend(rule.fields.iter().cloned());
+            }
+        }
+        self.validate_with(&root, tree)
+    }
+
+    fn validate_with(&self, root: &Rule, tree: &SyntaxTree) -> Vec<Diagnostic> {
+        let mut validator = Validator {
+            schema: self,
+            tree,
+            diagnostics: Vec::new(),
+        };
+        validator.block(root, tree.root(), Span::new(0, 0));
+        validator.diagnostics.sort_by_key(|d| d.span.start);
+        validator.diagnostics
+    }
+}
+
+fn invalid(message: String) -> Error {
+    ErrorKind::InvalidSchema(message).into()
+}
+
+/// All fields of a rule and of its inline block rules
+fn all_fields(rule: &Rule) -> Vec<&Field> {
+    let mut fields = Vec::new();
+    for field in &rule.fields {
+        fields.push(field);
+        if let ValueType::Block(rule) = &field.value {
+            fields.extend(all_fields(rule));
+        }
+    }
+    fields
+}
+
+fn sorted(value: &ClVal) -> Result<Vec<(&ClKey, &ClVal)>, Error> {
+    let dict = value
+        .as_dict()
+        .map_err(|_| invalid("expected a block of entries".to_string()))?;
+    let mut entries: Vec<_> = dict.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    Ok(entries)
+}
+
+fn scalars(value: &ClVal) -> Result<Vec<String>, Error> {
+    value
+        .as_list_or_single()
+        .map(|v| scalar_text(v).ok_or_else(|| invalid("expected a list of values".to_string())))
+        .collect()
+}
+
+fn parse_rule(value: &ClVal) -> Result<Rule, Error> {
+    let mut fields = Vec::new();
+    for (key, spec) in sorted(value)? {
+        let key = key.to_string();
+        // a repeated key declares alternatives
+        for spec in spec.as_list_or_single() {
+            fields.push(parse_field(&key, spec)?);
+        }
+    }
+    Ok(Rule { fields })
+}
+
+fn parse_field(key: &str, spec: &ClVal) -> Result<Field, Error> {
+    let spec = match spec {
+        ClVal::Dict(_) => spec,
+        _ => {
+            let name = spec.to_str().ok_or_else(|| invalid(format!("invalid field `{}`", key)))?;
+            return Ok(Field::new(key, parse_type(key, name, &ClVal::Dict(HashMap::new()))?));
+        }
+    };
+    let name = spec
+        .get("type")
+        .and_then(ClVal::to_str)
+        .ok_or_else(|| invalid(format!("field `{}` has no type", key)))?;
+    let count = |name: &str| -> Result<Option<usize>, Error> {
+        match spec.get(name) {
+            Some(value) => match value.to_i64() {
+                Some(n) if n >= 0 => Ok(Some(n as usize)),
+                _ => Err(invalid(format!("invalid `{}` of `{}`", name, key))),
+            },
+            None => Ok(None),
+        }
+    };
+    Ok(Field {
+        key: key.to_string(),
+        value: parse_type(key, name, spec)?,
+        min: count("min")?.unwrap_or(0),
+        max: count("max")?,
+    })
+}
+
+fn parse_type(key: &str, name: &str, spec: &ClVal) -> Result<ValueType, Error> {
+    Ok(match name {
+        "any" => ValueType::Any,
+        "scalar" => ValueType::Scalar,
+        "bool" => ValueType::Bool,
+        "int" => ValueType::Int {
+            min: spec.get("min_value").and_then(ClVal::to_i64),
+            max: spec.get("max_value").and_then(ClVal::to_i64),
+        },
+        "float" => ValueType::Float,
+        "date" => ValueType::Date,
+        "string" => ValueType::String,
+        "identifier" => ValueType::Identifier,
+        "scope" => ValueType::Scope,
+        "enum" => match (spec.get("values"), spec.get("enum").and_then(ClVal::to_str)) {
+            (Some(values), _) => ValueType::Enum(scalars(values)?),
+            (None, Some(name)) => ValueType::EnumRef(name.to_string()),
+            (None, None) => bail!(invalid(format!("enum `{}` needs `values` or `enum`", key))),
+        },
+        "block" => match (spec.get("fields"), spec.get("rule").and_then(ClVal::to_str)) {
+            (Some(fields), _) => ValueType::Block(parse_rule(fields)?),
+            (None, Some(name)) => ValueType::RuleRef(name.to_string()),
+            (None, None) => bail!(invalid(format!("block `{}` needs `fields` or `rule`", key))),
+        },
+        "list" => {
+            let items = spec.get("items").and_then(ClVal::to_str);
+            let items = items.unwrap_or("scalar");
+            ValueType::List(Box::new(parse_type(key, items, &ClVal::Dict(HashMap::new()))?))
+        }
+        name => bail!(invalid(format!("unknown type `{}` of `{}`", name, key))),
+    })
+}
+
+struct Validator<'s, 't, 'buf: 't> {
+    schema: &'s Schema,
+    tree: &'t SyntaxTree<'buf>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'s, 't, 'buf> Validator<'s, 't, 'buf> {
+    /// Validate the entries of the document or a block, `open` is the span missing keys are reported at
+    fn block(&mut self, rule: &Rule, node: &SyntaxNode, open: Span) {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for entry in node.entries() {
+            let key_token = match entry.key() {
+                Some(key) => key,
+                None => continue,
+            };
+            let key = self.tree.scalar(key_token);
+            let fields = rule.fields_for(&key);
+            if fields.is_empty() {
+                self.diagnostics.push(Diagnostic::warning(
+                    "unknown-key",
+                    key_token.span,
+                    format!("unknown key `{}`", key),
+                ));
+                continue;
+            }
+
+            let count = counts.entry(&fields[0].key).or_insert(0);
+            *count += 1;
+            if fields[0].key != ANY_KEY {
+                if let Some(max) = max_count(&fields) {
+                    if *count == max + 1 {
+                        self.diagnostics.push(Diagnostic::error(
+                            "too-many",
+                            key_token.span,
+                            format!("`{}` may occur at most {} time(s)", key, max),
+                        ));
+                    }
+                }
+            }
+
+            match entry.value() {
+                Some(value) => {
+                    let types: Vec<_> = fields.iter().map(|f| &f.value).collect();
+                    self.value(&types, value);
+                }
+                None => self.diagnostics.push(Diagnostic::error(
+                    "missing-value",
+                    entry.span(),
+                    format!("`{}` has no value", key),
+                )),
+            }
+        }
+
+        let mut checked: Vec<&str> = Vec::new();
+        for field in &rule.fields {
+            if field.key == ANY_KEY || checked.contains(&field.key.as_str()) {
+                continue;
+            }
+            checked.push(&field.key);
+            let min = rule.fields.iter().filter(|f| f.key == field.key).map(|f| f.min).min().unwrap_or(0);
+            if counts.get(field.key.as_str()).cloned().unwrap_or(0) < min {
+                self.diagnostics.push(Diagnostic::error(
+                    "missing-key",
+                    open,
+                    format!("missing `{}`", field.key),
+                ));
+            }
+        }
+    }
+
+    /// Validate a value against the first matching type
+    fn value(&mut self, types: &[&ValueType], value: &SyntaxElement) {
+        match value {
+            SyntaxElement::Token(token) => {
+                if !types.iter().any(|t| self.scalar_matches(t, token)) {
+                    let found = format!("`{}`", self.tree.scalar(token));
+                    self.mismatch(types, token.span, &found);
+                }
+            }
+            SyntaxElement::Node(node) => {
+                let is_dict = node.items().all(|i| i.kind() == SyntaxKind::Entry);
+                let is_list = node.items().all(|i| i.kind().is_scalar());
+                let matching = types.iter().find(|t| match t {
+                    ValueType::Any => true,
+                    ValueType::Block(_) | ValueType::RuleRef(_) => is_dict,
+                    ValueType::List(_) => is_list,
+                    _ => false,
+                });
+                let open = node.children().first().map(|c| c.span()).unwrap_or_else(|| node.span());
+                match matching {
+                    Some(ValueType::Block(rule)) => self.block(rule, node, open),
+                    Some(ValueType::RuleRef(name)) => {
+                        if let Some(rule) = self.schema.rules.get(name) {
+                            self.block(rule, node, open)
+                        }
+                    }
+                    Some(ValueType::List(items)) => {
+                        for item in node.items() {
+                            self.value(&[items], item);
+                        }
+                    }
+                    Some(_) => {}
+                    None => self.mismatch(types, open, "a block"),
+                }
+            }
+        }
+    }
+
+    fn mismatch(&mut self, types: &[&ValueType], span: Span, found: &str) {
+        let expected: Vec<_> = types.iter().map(|t| t.to_string()).collect();
+        self.diagnostics.push(Diagnostic::error(
+            "type-mismatch",
+            span,
+            format!("expected {}, found {}", expected.join(" or "), found),
+        ));
+    }
+
+    fn scalar_matches(&self, value_type: &ValueType, token: &SyntaxToken) -> bool {
+        let text = self.tree.scalar(token);
+        let value = if token.kind == SyntaxKind::Quoted {
+            ClVal::String(text.to_string())
+        } else {
+            untyped_value(text.as_bytes())
+        };
+        match value_type {
+            ValueType::Any | ValueType::Scalar => true,
+            ValueType::Bool => value.as_bool().is_ok(),
+            ValueType::Int { min, max } => match value {
+                ClVal::Integer(i) => {
+                    min.map(|min| i64::from(i) >= min).unwrap_or(true) && max.map(|max| i64::from(i) <= max).unwrap_or(true)
+                }
+                _ => false,
+            },
+            ValueType::Float => matches!(value, ClVal::Integer(_) | ClVal::Float(_)),
+            ValueType::Date => value.as_date().is_ok(),
+            ValueType::String => matches!(value, ClVal::String(_) | ClVal::Identifier(_)),
+            ValueType::Identifier => matches!(value, ClVal::Identifier(_)),
+            ValueType::Scope => match value {
+                ClVal::Integer(_) => true,
+                ClVal::Identifier(ref s) => SCOPE_RE.is_match(s),
+                _ => false,
+            },
+            ValueType::Enum(values) => values.iter().any(|v| *v == text),
+            ValueType::EnumRef(name) => match self.schema.enums.get(name) {
+                Some(values) => values.iter().any(|v| *v == text),
+                None => true,
+            },
+            ValueType::Block(_) | ValueType::RuleRef(_) | ValueType::List(_) => false,
+        }
+    }
+}
+
+fn max_count(fields: &[&Field]) -> Option<usize> {
+    fields.iter().map(|f| f.max).max().unwrap_or(None)
+}
+
+lazy_static! {
+    static ref SCOPE_RE: Regex = Regex::new(r"^(ROOT|THIS|(PREV)+|(FROM)+|[A-Z][A-Z0-9]{2})$").unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &[u8] = br#"
+# a test schema
+root = {
+    namespace = identifier
+    country_event = { type = block rule = event }
+}
+rules = {
+    event = {
+        id = { type = identifier min = 1 max = 1 }
+        is_triggered_only = { type = bool max = 1 }
+        difficulty = { type = enum enum = difficulty }
+        weight = { type = int min_value = 0 max_value = 100 }
+        weight = float
+        tags = { type = list items = scope }
+        option = { type = block min = 1 fields = { name = string "*" = any } }
+    }
+}
+enums = {
+    difficulty = { easy normal hard }
+}
+"#;
+
+    fn validate(buf: &[u8]) -> Vec<String> {
+        let schema = Schema::from_slice(SCHEMA).unwrap();
+        schema.check().unwrap();
+        let tree = SyntaxTree::parse(buf);
+        schema
+            .validate(&tree)
+            .iter()
+            .map(|d| format!("{}-{} {}", d.span.start, d.span.end, d))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_schema() {
+        let schema = Schema::from_slice(SCHEMA).unwrap();
+        assert_eq!(
+            schema.root.fields,
+            vec![
+                Field::new("country_event", ValueType::RuleRef("event".to_string())),
+                Field::new("namespace", ValueType::Identifier),
+            ]
+        );
+        let event = &schema.rules["event"];
+        assert_eq!(event.fields_for("id")[0].min, 1);
+        assert_eq!(event.fields_for("id")[0].max, Some(1));
+        assert_eq!(event.fields_for("weight").len(), 2);
+        assert_eq!(event.fields_for("tags")[0].value, ValueType::List(Box::new(ValueType::Scope)));
+        assert_eq!(schema.enums["difficulty"], vec!["easy", "normal", "hard"]);
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let err = |buf: &[u8]| Schema::from_slice(buf).unwrap_err().to_string();
+        assert_eq!(err(b"root = { a = number }"), "invalid schema: unknown type `number` of `a`");
+        assert_eq!(err(b"root = { a = { min = 1 } }"), "invalid schema: field `a` has no type");
+        assert_eq!(err(b"root = { a = { type = block } }"), "invalid schema: block `a` needs `fields` or `rule`");
+        assert_eq!(err(b"types = { }"), "invalid schema: unknown section `types`");
+        let schema = Schema::from_slice(b"root = { a = { type = block rule = b } }").unwrap();
+        assert_eq!(schema.check().unwrap_err().to_string(), "invalid schema: unknown rule `b`");
+    }
+
+    #[test]
+    fn test_validate() {
+        let buf = b"namespace = test
+country_event = {
+\tid = test.1
+\tis_triggered_only = yes
+\tdifficulty = hard
+\tweight = 2.5
+\ttags = { ROOT PRU 123 }
+\toption = { name = \"A\" ai_chance = { factor = 1 } }
+}
+";
+        assert!(validate(buf).is_empty(), "{:?}", validate(buf));
+    }
+
+    #[test]
+    fn test_validate_errors() {
+        let buf = b"namespace = \"x\"
+foo = 1
+country_event = {
+\tis_triggered_only = maybe
+\tis_triggered_only = no
+\tdifficulty = extreme
+\tweight = abc
+\ttags = { ROOT foo }
+\toption = 1
+}
+";
+        assert_eq!(
+            validate(buf),
+            vec![
+                "12-15 error[type-mismatch]: expected identifier, found `x`",
+                "16-19 warning[unknown-key]: unknown key `foo`",
+                "40-41 error[missing-key]: missing `id`",
+                "63-68 error[type-mismatch]: expected bool, found `maybe`",
+                "70-87 error[too-many]: `is_triggered_only` may occur at most 1 time(s)",
+                "107-114 error[type-mismatch]: expected difficulty, found `extreme`",
+                "125-128 error[type-mismatch]: expected int[0..100] or float, found `abc`",
+                "144-147 error[type-mismatch]: expected scope, found `foo`",
+                "160-161 error[type-mismatch]: expected a block, found `1`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_paths() {
+        let schema = Schema::from_slice(
+            b"root = { namespace = identifier }\npaths = { \"common/scripted_triggers\" = { \"*\" = { type = block fields = { \"*\" = any } } } }",
+        )
+        .unwrap();
+        schema.check().unwrap();
+        let validate = |path: &str| -> Vec<String> {
+            let tree = SyntaxTree::parse(b"namespace = a is_rich = { treasury = 1000 }");
+            schema.validate_file(path, &tree).iter().map(|d| d.to_string()).collect()
+        };
+        assert!(validate("mod/common/scripted_triggers/triggers.txt").is_empty());
+        assert_eq!(validate("events/a.txt"), vec!["warning[unknown-key]: unknown key `is_rich`"]);
+        assert_eq!(validate("-"), vec!["warning[unknown-key]: unknown key `is_rich`"]);
+    }
+
+    #[test]
+    fn test_load() {
+        let schema = Schema::load(Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/schema")).unwrap();
+        assert_eq!(schema.root.fields.len(), 2);
+        assert!(schema.rules.contains_key("event"));
+        assert!(schema.rules.contains_key("decision"));
+    }
+}
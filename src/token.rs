@@ -18,9 +18,15 @@
 
 //! The Tokenizer
 
+use cst::{SyntaxKind, SyntaxToken};
 use error::{Error, ErrorKind};
 use std::convert::TryFrom;
 
+/// The UTF-8 byte order mark
+///
+/// Localisation files and many mod files start with it; CK3 and HOI4 even require it.
+pub const BOM: &[u8] = b"\xEF\xBB\xBF";
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 /// The lexer tokens
 pub enum LexerToken<'buf> {
@@ -87,6 +93,40 @@ impl<'buf> TryFrom<&'buf u8> for LexerToken<'buf> {
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, Default)]
+/// A byte range in the tokenized buffer
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Construct a new `Span`
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// The length of the span in bytes
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Returns `true` if the span is empty
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns `true` if `offset` lies within the span
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+}
+
+/// Returns `true` if `buf` is read back as a single unquoted token
+pub(crate) fn is_bare(buf: &[u8]) -> bool {
+    !buf.is_empty() && buf.iter().all(|c| !is_whitespace(c) && LexerToken::try_from(c).is_err())
+}
+
 fn is_whitespace(chr: &u8) -> bool {
     match *chr {
         b' ' => true,
@@ -108,14 +148,29 @@ impl<'buf> Tokenizer<'buf> {
         Self { buf }
     }
 
+    /// Returns `true` if the buffer starts with a UTF-8 [`BOM`](constant.BOM.html)
+    pub fn has_bom(&self) -> bool {
+        self.buf.starts_with(BOM)
+    }
+
     /// Tokenize the provided buffer
-    pub fn tokenize(&self) -> Vec<LexerToken> {
+    ///
+    /// A leading UTF-8 BOM is skipped and never becomes part of a token.
+    pub fn tokenize(&self) -> Vec<LexerToken<'buf>> {
+        self.tokenize_with_spans().into_iter().map(|(token, _)| token).collect()
+    }
+
+    /// Tokenize the provided buffer, keeping the span of every token
+    ///
+    /// The span of an untyped token inside a quoted string excludes the quotes.
+    pub fn tokenize_with_spans(&self) -> Vec<(LexerToken<'buf>, Span)> {
         let mut untyped_start = None;
         let mut in_quote = false;
         let mut in_comment = false;
         let mut tokens = Vec::with_capacity(4096);
+        let skip = if self.has_bom() { BOM.len() } else { 0 };
 
-        for (pos, chr) in self.buf.iter().enumerate() {
+        for (pos, chr) in self.buf.iter().enumerate().skip(skip) {
             // if in a comment, advance until newline
             if in_comment {
                 if chr == &b'\n' {
@@ -136,11 +191,11 @@ impl<'buf> Tokenizer<'buf> {
                                     "push untyped to list: {}",
                                     String::from_utf8_lossy(&self.buf[untyped_start.unwrap()..pos])
                                 );
-                                let untyped = LexerToken::Untyped(&self.buf[untyped_start.take().unwrap()..pos]);
-                                tokens.push(untyped);
+                                let start = untyped_start.take().unwrap();
+                                tokens.push((LexerToken::Untyped(&self.buf[start..pos]), Span::new(start, pos)));
                             } else {
                                 // push an empty string
-                                tokens.push(LexerToken::Untyped(b""));
+                                tokens.push((LexerToken::Untyped(b""), Span::new(pos, pos)));
                             }
                         } else {
                             continue;
@@ -153,8 +208,8 @@ impl<'buf> Tokenizer<'buf> {
                                 "push untyped to list: {}",
                                 String::from_utf8_lossy(&self.buf[untyped_start.unwrap()..pos])
                             );
-                            let untyped = LexerToken::Untyped(&self.buf[untyped_start.take().unwrap()..pos]);
-                            tokens.push(untyped);
+                            let start = untyped_start.take().unwrap();
+                            tokens.push((LexerToken::Untyped(&self.buf[start..pos]), Span::new(start, pos)));
                         }
                     }
 
@@ -164,7 +219,7 @@ impl<'buf> Tokenizer<'buf> {
                     } else if let LexerToken::Comment = t {
                         in_comment = true;
                     }
-                    tokens.push(t)
+                    tokens.push((t, Span::new(pos, pos + 1)))
                 }
                 Err(_) => {
                     // ignore every whitespace as long as we're not in a quoted string
@@ -175,8 +230,8 @@ impl<'buf> Tokenizer<'buf> {
                                 "push untyped to list: {}",
                                 String::from_utf8_lossy(&self.buf[untyped_start.unwrap()..pos])
                             );
-                            let untyped = LexerToken::Untyped(&self.buf[untyped_start.take().unwrap()..pos]);
-                            tokens.push(untyped);
+                            let start = untyped_start.take().unwrap();
+                            tokens.push((LexerToken::Untyped(&self.buf[start..pos]), Span::new(start, pos)));
                         }
                     } else if untyped_start.is_none() {
                         // All characters until whitespace or a token is considered untyped
@@ -192,8 +247,83 @@ impl<'buf> Tokenizer<'buf> {
                 "EOF. Push remaining untyped: {}",
                 String::from_utf8_lossy(&self.buf[untyped_start.unwrap()..])
             );
-            let untyped = LexerToken::Untyped(&self.buf[untyped_start.take().unwrap()..]);
-            tokens.push(untyped);
+            let start = untyped_start.take().unwrap();
+            tokens.push((LexerToken::Untyped(&self.buf[start..]), Span::new(start, self.buf.len())));
+        }
+
+        tokens
+    }
+
+    /// Tokenize the provided buffer without losing a single byte
+    ///
+    /// Unlike [`tokenize`](#method.tokenize) this keeps the BOM, whitespace, newlines and the text
+    /// of comments. Quoted strings are a single token including their quotes.
+    /// Concatenating the spans of all tokens reproduces the buffer.
+    pub fn tokenize_lossless(&self) -> Vec<SyntaxToken> {
+        let buf = self.buf;
+        let mut tokens = Vec::with_capacity(4096);
+        let mut pos = 0;
+
+        if self.has_bom() {
+            tokens.push(SyntaxToken::new(SyntaxKind::Bom, Span::new(0, BOM.len())));
+            pos = BOM.len();
+        }
+
+        while pos < buf.len() {
+            let start = pos;
+            let kind = match buf[pos] {
+                b'\n' => {
+                    pos += 1;
+                    SyntaxKind::Newline
+                }
+                b'\r' if buf.get(pos + 1) == Some(&b'\n') => {
+                    pos += 2;
+                    SyntaxKind::Newline
+                }
+                b'#' => {
+                    // a comment runs until the end of the line, the line break is not part of it
+                    while pos < buf.len() && buf[pos] != b'\n' {
+                        pos += 1;
+                    }
+                    if pos < buf.len() && buf[pos - 1] == b'\r' {
+                        pos -= 1;
+                    }
+                    SyntaxKind::Comment
+                }
+                b'"' => {
+                    // everything until the closing quote (or EOF) is part of the string
+                    pos += 1;
+                    while pos < buf.len() && buf[pos] != b'"' {
+                        pos += 1;
+                    }
+                    if pos < buf.len() {
+                        pos += 1;
+                    }
+                    SyntaxKind::Quoted
+                }
+                chr if is_whitespace(&chr) => {
+                    while pos < buf.len() && is_whitespace(&buf[pos]) && buf[pos] != b'\n' {
+                        if buf[pos] == b'\r' && buf.get(pos + 1) == Some(&b'\n') {
+                            break;
+                        }
+                        pos += 1;
+                    }
+                    SyntaxKind::Whitespace
+                }
+                ref chr => match LexerToken::try_from(chr) {
+                    Ok(token) => {
+                        pos += 1;
+                        SyntaxKind::from(&token)
+                    }
+                    Err(_) => {
+                        while pos < buf.len() && !is_whitespace(&buf[pos]) && LexerToken::try_from(&buf[pos]).is_err() {
+                            pos += 1;
+                        }
+                        SyntaxKind::Untyped
+                    }
+                },
+            };
+            tokens.push(SyntaxToken::new(kind, Span::new(start, pos)));
         }
 
         tokens
@@ -299,4 +429,61 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_tokenizer_bom() {
+        let buf = b"\xEF\xBB\xBFl_english=yes";
+        let tokenizer = Tokenizer::new(buf);
+        assert!(tokenizer.has_bom());
+        assert_eq!(
+            tokenizer.tokenize(),
+            vec![
+                LexerToken::Untyped(b"l_english"),
+                LexerToken::Equals,
+                LexerToken::Untyped(b"yes"),
+            ]
+        );
+
+        let tokenizer = Tokenizer::new(b"foo=bar");
+        assert!(!tokenizer.has_bom());
+    }
+
+    #[test]
+    fn test_tokenize_lossless() {
+        let buf = b"\xEF\xBB\xBFkey = \"a # b\" # comment\r\n\tlist={1,2}";
+        let tokenizer = Tokenizer::new(buf);
+        let tokens = tokenizer.tokenize_lossless();
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                SyntaxKind::Bom,
+                SyntaxKind::Untyped,
+                SyntaxKind::Whitespace,
+                SyntaxKind::Equals,
+                SyntaxKind::Whitespace,
+                SyntaxKind::Quoted,
+                SyntaxKind::Whitespace,
+                SyntaxKind::Comment,
+                SyntaxKind::Newline,
+                SyntaxKind::Whitespace,
+                SyntaxKind::Untyped,
+                SyntaxKind::Equals,
+                SyntaxKind::LeftCurly,
+                SyntaxKind::Untyped,
+                SyntaxKind::Comma,
+                SyntaxKind::Untyped,
+                SyntaxKind::RightCurly,
+            ]
+        );
+        assert_eq!(&buf[tokens[5].span.start..tokens[5].span.end], b"\"a # b\"");
+        assert_eq!(&buf[tokens[7].span.start..tokens[7].span.end], b"# comment");
+
+        let mut end = 0;
+        for token in &tokens {
+            assert_eq!(token.span.start, end);
+            end = token.span.end;
+        }
+        assert_eq!(end, buf.len());
+    }
 }
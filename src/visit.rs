@@ -0,0 +1,226 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Walking a `ClVal` tree with a visitor
+//!
+//! [**walk**](fn.walk.html) calls the [**Visitor**](trait.Visitor.html) for every dict entry,
+//! list item and scalar with the path of the value and its depth, the number of segments in the
+//! path. Dict entries are visited in key order.
+//!
+//! ```
+//! extern crate clausewitz_parser;
+//!
+//! use clausewitz_parser::query::Path;
+//! use clausewitz_parser::visit::{walk, Visitor};
+//! use clausewitz_parser::{parse, ClKey, ClVal};
+//!
+//! #[derive(Default)]
+//! struct Triggers(Vec<String>);
+//!
+//! impl Visitor for Triggers {
+//!     fn visit_entry(&mut self, path: &Path, _depth: usize, key: &ClKey, _value: &ClVal) -> bool {
+//!         if key.matches("trigger") {
+//!             self.0.push(path.to_string());
+//!         }
+//!         true
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let values = parse(b"e = { trigger = { tag = PRU } option = { trigger = { ai = yes } } }").unwrap();
+//!     let mut triggers = Triggers::default();
+//!     walk(&values, &mut triggers);
+//!     assert_eq!(triggers.0, vec!["e.option.trigger", "e.trigger"]);
+//! }
+//! ```
+
+use clval::{ClKey, ClVal};
+use query::{Path, PathSegment};
+
+/// Callbacks for [**walk**](fn.walk.html), all of them do nothing by default
+pub trait Visitor {
+    /// Called for every dict entry before its value is visited, return `false` to skip the value
+    fn visit_entry(&mut self, _path: &Path, _depth: usize, _key: &ClKey, _value: &ClVal) -> bool {
+        true
+    }
+
+    /// Called for every list item before it is visited, return `false` to skip the item
+    fn visit_item(&mut self, _path: &Path, _depth: usize, _index: usize, _value: &ClVal) -> bool {
+        true
+    }
+
+    /// Called for every value which is neither a dict nor a list
+    fn visit_scalar(&mut self, _path: &Path, _depth: usize, _value: &ClVal) {}
+}
+
+/// Callbacks for [**walk_mut**](fn.walk_mut.html), all of them do nothing by default
+///
+/// Values can be replaced in place, a replaced value is walked after the callback returns.
+pub trait VisitorMut {
+    /// Called for every dict entry before its value is visited, return `false` to skip the value
+    fn visit_entry(&mut self, _path: &Path, _depth: usize, _key: &ClKey, _value: &mut ClVal) -> bool {
+        true
+    }
+
+    /// Called for every list item before it is visited, return `false` to skip the item
+    fn visit_item(&mut self, _path: &Path, _depth: usize, _index: usize, _value: &mut ClVal) -> bool {
+        true
+    }
+
+    /// Called for every value which is neither a dict nor a list
+    fn visit_scalar(&mut self, _path: &Path, _depth: usize, _value: &mut ClVal) {}
+}
+
+/// Visit every value of a tree, depth first
+pub fn walk<V: Visitor + ?Sized>(value: &ClVal, visitor: &mut V) {
+    walk_value(&mut Path::new(), value, visitor);
+}
+
+fn walk_value<V: Visitor + ?Sized>(path: &mut Path, value: &ClVal, visitor: &mut V) {
+    let depth = path.segments().len() + 1;
+    match value {
+        ClVal::Dict(dict) => {
+            let mut entries: Vec<_> = dict.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, value) in entries {
+                path.push(PathSegment::Key(key.clone()));
+                if visitor.visit_entry(path, depth, key, value) {
+                    walk_value(path, value, visitor);
+                }
+                path.pop();
+            }
+        }
+        ClVal::List(list) => {
+            for (index, item) in list.iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                if visitor.visit_item(path, depth, index, item) {
+                    walk_value(path, item, visitor);
+                }
+                path.pop();
+            }
+        }
+        scalar => visitor.visit_scalar(path, depth - 1, scalar),
+    }
+}
+
+/// Visit every value of a tree for modification, depth first
+pub fn walk_mut<V: VisitorMut + ?Sized>(value: &mut ClVal, visitor: &mut V) {
+    walk_value_mut(&mut Path::new(), value, visitor);
+}
+
+fn walk_value_mut<V: VisitorMut + ?Sized>(path: &mut Path, value: &mut ClVal, visitor: &mut V) {
+    let depth = path.segments().len() + 1;
+    match value {
+        ClVal::Dict(dict) => {
+            let mut entries: Vec<_> = dict.iter_mut().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, value) in entries {
+                path.push(PathSegment::Key(key.clone()));
+                if visitor.visit_entry(path, depth, key, value) {
+                    walk_value_mut(path, value, visitor);
+                }
+                path.pop();
+            }
+        }
+        ClVal::List(list) => {
+            for (index, item) in list.iter_mut().enumerate() {
+                path.push(PathSegment::Index(index));
+                if visitor.visit_item(path, depth, index, item) {
+                    walk_value_mut(path, item, visitor);
+                }
+                path.pop();
+            }
+        }
+        scalar => visitor.visit_scalar(path, depth - 1, scalar),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Visitor for Recorder {
+        fn visit_entry(&mut self, path: &Path, depth: usize, key: &ClKey, _value: &ClVal) -> bool {
+            self.0.push(format!("entry {} {} {}", path, depth, key));
+            !key.matches("skip")
+        }
+
+        fn visit_item(&mut self, path: &Path, depth: usize, index: usize, _value: &ClVal) -> bool {
+            self.0.push(format!("item {} {} {}", path, depth, index));
+            true
+        }
+
+        fn visit_scalar(&mut self, path: &Path, depth: usize, value: &ClVal) {
+            self.0.push(format!("scalar {} {} {:?}", path, depth, value));
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let values = ::parse(b"b = { c = 1 c = 2 } a = yes skip = { x = 1 } l = { { 1 } }").unwrap();
+        let mut recorder = Recorder::default();
+        walk(&values, &mut recorder);
+        assert_eq!(
+            recorder.0,
+            vec![
+                "entry a 1 a",
+                "scalar a 1 Bool(true)",
+                "entry b 1 b",
+                "entry b.c 2 c",
+                "item b.c[0] 3 0",
+                "scalar b.c[0] 3 Integer(1)",
+                "item b.c[1] 3 1",
+                "scalar b.c[1] 3 Integer(2)",
+                "entry l 1 l",
+                "item l[0] 2 0",
+                "item l[0][0] 3 0",
+                "scalar l[0][0] 3 Integer(1)",
+                "entry skip 1 skip",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walk_scalar_root() {
+        let mut recorder = Recorder::default();
+        walk(&ClVal::Integer(1), &mut recorder);
+        assert_eq!(recorder.0, vec!["scalar  0 Integer(1)"]);
+    }
+
+    struct Rename;
+
+    impl VisitorMut for Rename {
+        fn visit_scalar(&mut self, _path: &Path, _depth: usize, value: &mut ClVal) {
+            if let ClVal::Identifier(tag) = value {
+                if tag == "PRU" {
+                    *tag = "GER".to_string();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk_mut() {
+        let mut values = ::parse(b"a = { owner = PRU cores = { PRU BRA } }").unwrap();
+        walk_mut(&mut values, &mut Rename);
+        assert_eq!(values, ::parse(b"a = { owner = GER cores = { GER BRA } }").unwrap());
+    }
+}
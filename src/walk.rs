@@ -0,0 +1,45 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Directory walking
+
+use error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// All files with one of `extensions` in a directory and its subdirectories except `skip`, sorted
+///
+/// A path that isn't a directory has no files.
+pub(crate) fn files(dir: &Path, extensions: &[&str], skip: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    for entry in fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.is_dir() {
+            if !skip.contains(&path) {
+                files.extend(self::files(&path, extensions, skip)?);
+            }
+        } else if path.extension().map(|e| extensions.iter().any(|x| e == *x)).unwrap_or(false) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,365 @@
+/*
+ * clausewitz_parser, a Clausewitz file parser
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The Writer
+//!
+//! Serializes [**ClVals**](../clval/enum.ClVal.html) back into Clausewitz text.
+//!
+//! Dict keys are written in sorted order, so the output is stable between runs.
+//! Implicit lists (repeated keys) can not be told apart from explicit lists once parsed,
+//! so every `List` is written as a `{ ... }` block, except under the keys passed to
+//! [`repeated_keys`](struct.Writer.html#method.repeated_keys).
+//!
+//! Clausewitz strings have no escapes, so strings and keys containing a quote can't be written
+//! and are rejected.
+
+use clval::{ClKey, ClVal};
+use error::{Error, ErrorKind};
+use std::collections::HashSet;
+use std::slice;
+use token::BOM;
+
+#[derive(Debug, Clone, Default)]
+/// The Writer
+///
+/// # Example
+///
+/// ```
+/// extern crate clausewitz_parser;
+///
+/// use clausewitz_parser::{parse, Writer};
+///
+/// fn main() {
+///     let values = parse(b"foo=bar").unwrap();
+///     let buf = Writer::new().write(&values).unwrap();
+///
+///     assert_eq!(buf, b"foo=bar\n");
+/// }
+/// ```
+pub struct Writer {
+    bom: bool,
+    repeated_keys: HashSet<String>,
+}
+
+impl Writer {
+    /// Construct a new `Writer`
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Emit a UTF-8 BOM in front of the output
+    pub fn bom(mut self, bom: bool) -> Self {
+        self.bom = bom;
+        self
+    }
+
+    /// Write lists under these keys as repeated keys (`add_core=PRU add_core=BRA`)
+    ///
+    /// Both forms are parsed into the same `List`, but the game reads `color={ 23 50 150 }`
+    /// differently from `color=23 color=50 color=150`, so only pass keys which are meant to
+    /// repeat. Lists at the top level and lists of lists are still written as a block, the parser
+    /// would overwrite or merge them otherwise.
+    pub fn repeated_keys<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.repeated_keys = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Write the provided `ClVal`
+    ///
+    /// A `Dict` is written as a document of `key=value` pairs, every other value as is.
+    /// Fails if a string or key contains a quote.
+    pub fn write(&self, values: &ClVal) -> Result<Vec<u8>, Error> {
+        check_quotes(values)?;
+        let mut out = String::new();
+        match values {
+            ClVal::Dict(dict) => {
+                let mut entries: Vec<_> = dict.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                for (key, value) in entries {
+                    self.write_entry(&mut out, key, value, 0);
+                }
+            }
+            _ => {
+                self.write_value(&mut out, values, 0);
+                out.push('\n');
+            }
+        }
+
+        let mut buf = Vec::with_capacity(out.len() + BOM.len());
+        if self.bom {
+            buf.extend_from_slice(BOM);
+        }
+        buf.extend_from_slice(out.as_bytes());
+        Ok(buf)
+    }
+
+    fn write_entry(&self, out: &mut String, key: &ClKey, value: &ClVal, indent: usize) {
+        // top level keys overwrite each other and a repeated list would be appended to the first one
+        // when parsed, so those stay blocks
+        let items = match value {
+            ClVal::List(list) if indent > 0 && self.repeats(key) && list.len() > 1 && !list.iter().any(is_list) => {
+                &list[..]
+            }
+            value => slice::from_ref(value),
+        };
+        for item in items {
+            write_indent(out, indent);
+            write_key(out, key);
+            out.push('=');
+            self.write_value(out, item, indent);
+            out.push('\n');
+        }
+    }
+
+    fn repeats(&self, key: &ClKey) -> bool {
+        self.repeated_keys.iter().any(|k| key.matches(k))
+    }
+
+    /// Append the textual representation of a value
+    ///
+    /// `indent` is the indentation of the line the value starts on.
+    fn write_value(&self, out: &mut String, value: &ClVal, indent: usize) {
+        match value {
+            ClVal::Integer(i) => out.push_str(&i.to_string()),
+            ClVal::Float(f) => write_float(out, *f),
+            ClVal::String(s) => write_quoted(out, s),
+            ClVal::Date(d) => out.push_str(&d.to_string()),
+            ClVal::Bool(b) => out.push_str(if *b { "yes" } else { "no" }),
+            ClVal::Identifier(i) => out.push_str(i),
+            ClVal::List(list) => {
+                if list.is_empty() {
+                    out.push_str("{ }");
+                } else if list.iter().all(is_scalar) {
+                    // lists of plain values stay on a single line
+                    out.push('{');
+                    for item in list {
+                        out.push(' ');
+                        self.write_value(out, item, indent);
+                    }
+                    out.push_str(" }");
+                } else {
+                    out.push_str("{\n");
+                    for item in list {
+                        write_indent(out, indent + 1);
+                        self.write_value(out, item, indent + 1);
+                        out.push('\n');
+                    }
+                    write_indent(out, indent);
+                    out.push('}');
+                }
+            }
+            ClVal::Dict(dict) => {
+                let mut entries: Vec<_> = dict.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                out.push_str("{\n");
+                for (key, value) in entries {
+                    self.write_entry(out, key, value, indent + 1);
+                }
+                write_indent(out, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push('\t');
+    }
+}
+
+/// Append the textual representation of a key
+pub(crate) fn write_key(out: &mut String, key: &ClKey) {
+    match key {
+        ClKey::Integer(i) => out.push_str(&i.to_string()),
+        ClKey::String(s) => write_quoted(out, s),
+        ClKey::Date(d) => out.push_str(&d.to_string()),
+        ClKey::Identifier(i) => out.push_str(i),
+    }
+}
+
+/// The tokenizer ends a quoted string at the next quote, so strings can't contain one
+pub(crate) fn without_quotes(s: &str) -> Result<&str, Error> {
+    if s.contains('"') {
+        bail!(ErrorKind::InvalidValue(format!("string containing a quote: {}", s)));
+    }
+    Ok(s)
+}
+
+/// Fail on strings and keys which can't be written, see `without_quotes`
+pub(crate) fn check_quotes(value: &ClVal) -> Result<(), Error> {
+    match value {
+        ClVal::String(s) => without_quotes(s).map(|_| ()),
+        ClVal::List(list) => list.iter().try_for_each(check_quotes),
+        ClVal::Dict(dict) => dict.iter().try_for_each(|(key, value)| {
+            if let ClKey::String(s) = key {
+                without_quotes(s)?;
+            }
+            check_quotes(value)
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn write_quoted(out: &mut String, s: &str) {
+    out.push('"');
+    out.push_str(s);
+    out.push('"');
+}
+
+fn write_float(out: &mut String, f: f32) {
+    let s = f.to_string();
+    out.push_str(&s);
+    // a float without a dot would be parsed as an integer
+    if !s.contains('.') {
+        out.push_str(".0");
+    }
+}
+
+fn is_list(value: &ClVal) -> bool {
+    matches!(value, ClVal::List(_))
+}
+
+fn is_scalar(value: &ClVal) -> bool {
+    !matches!(value, ClVal::List(_) | ClVal::Dict(_))
+}
+
+/// Append the textual representation of a value with the default settings
+///
+/// `indent` is the indentation of the line the value starts on.
+pub(crate) fn write_value(out: &mut String, value: &ClVal, indent: usize) {
+    Writer::new().write_value(out, value, indent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clval::Date;
+    use std::collections::HashMap;
+    use std::str::from_utf8;
+
+    fn write(values: &ClVal) -> String {
+        from_utf8(&Writer::new().write(values).unwrap()).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_write_scalars() {
+        let mut dict = HashMap::new();
+        dict.insert(ClKey::Identifier("a".to_string()), ClVal::Integer(-1));
+        dict.insert(ClKey::Identifier("b".to_string()), ClVal::Float(5.0));
+        dict.insert(ClKey::Identifier("c".to_string()), ClVal::Float(12.34));
+        dict.insert(ClKey::Identifier("d".to_string()), ClVal::String("Rights of Man".to_string()));
+        dict.insert(ClKey::Identifier("e".to_string()), ClVal::Bool(false));
+        dict.insert(ClKey::Date(Date::new(1444, 11, 11)), ClVal::Date(Date::new(1597, 1, 1)));
+        dict.insert(ClKey::Integer(12), ClVal::Identifier("PRU".to_string()));
+        dict.insert(ClKey::String("f".to_string()), ClVal::Bool(true));
+
+        assert_eq!(
+            write(&ClVal::Dict(dict)),
+            "12=PRU\n\"f\"=yes\n1444.11.11=1597.1.1\na=-1\nb=5.0\nc=12.34\nd=\"Rights of Man\"\ne=no\n"
+        );
+    }
+
+    #[test]
+    fn test_write_collections() {
+        let mut inner = HashMap::new();
+        inner.insert(ClKey::Identifier("first".to_string()), ClVal::Integer(1));
+        let mut dict = HashMap::new();
+        dict.insert(ClKey::Identifier("empty".to_string()), ClVal::List(Vec::new()));
+        dict.insert(
+            ClKey::Identifier("list".to_string()),
+            ClVal::List(vec![ClVal::Integer(1), ClVal::Integer(2)]),
+        );
+        dict.insert(
+            ClKey::Identifier("nested".to_string()),
+            ClVal::List(vec![ClVal::Dict(inner.clone()), ClVal::Dict(inner.clone())]),
+        );
+        dict.insert(ClKey::Identifier("version".to_string()), ClVal::Dict(inner));
+
+        assert_eq!(
+            write(&ClVal::Dict(dict)),
+            "empty={ }\nlist={ 1 2 }\nnested={\n\t{\n\t\tfirst=1\n\t}\n\t{\n\t\tfirst=1\n\t}\n}\nversion={\n\tfirst=1\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_write_repeated_keys() {
+        let values = ::parse(b"c={ add_core=PRU add_core=BRA color={ 23 50 } l={ { 1 2 } { 3 } } o={ id=1 } o={ id=2 } }").unwrap();
+        let buf = Writer::new().repeated_keys(vec!["add_core", "l", "o"]).write(&values).unwrap();
+        assert_eq!(
+            from_utf8(&buf).unwrap(),
+            "c={\n\tadd_core=PRU\n\tadd_core=BRA\n\tcolor={ 23 50 }\n\tl={\n\t\t{ 1 2 }\n\t\t{ 3 }\n\t}\n\to={\n\t\tid=1\n\t}\n\to={\n\t\tid=2\n\t}\n}\n"
+        );
+        assert_eq!(::parse(&buf).unwrap(), values);
+    }
+
+    #[test]
+    fn test_write_repeated_keys_roundtrip() {
+        let writer = Writer::new().repeated_keys(vec!["add_core", "color"]);
+        // top level lists stay blocks, even under a repeated key
+        let values = ::parse(b"color={ 23 50 150 } capital=1").unwrap();
+        let buf = writer.write(&values).unwrap();
+        assert_eq!(buf, b"capital=1\ncolor={ 23 50 150 }\n".to_vec());
+        assert_eq!(::parse(&buf).unwrap(), values);
+
+        // nested lists only repeat under the keys passed to the writer
+        let values = ::parse(b"country={ add_core={ PRU BRA } color={ 23 50 150 } }").unwrap();
+        let buf = Writer::new().repeated_keys(vec!["add_core"]).write(&values).unwrap();
+        assert_eq!(buf, b"country={\n\tadd_core=PRU\n\tadd_core=BRA\n\tcolor={ 23 50 150 }\n}\n".to_vec());
+        assert_eq!(::parse(&buf).unwrap(), values);
+    }
+
+    #[test]
+    fn test_write_bom() {
+        let mut dict = HashMap::new();
+        dict.insert(ClKey::Identifier("foo".to_string()), ClVal::Identifier("bar".to_string()));
+        let values = ClVal::Dict(dict);
+
+        assert_eq!(Writer::new().bom(true).write(&values).unwrap(), b"\xEF\xBB\xBFfoo=bar\n".to_vec());
+        assert_eq!(Writer::new().write(&values).unwrap(), b"foo=bar\n".to_vec());
+    }
+
+    #[test]
+    fn test_write_quotes() {
+        let mut dict = HashMap::new();
+        dict.insert(ClKey::Identifier("name".to_string()), ClVal::String("Der \"Alte\" Fritz".to_string()));
+        assert_eq!(
+            Writer::new().write(&ClVal::Dict(dict)).unwrap_err().to_string(),
+            "invalid value type: string containing a quote: Der \"Alte\" Fritz"
+        );
+        let mut dict = HashMap::new();
+        let mut inner = HashMap::new();
+        inner.insert(ClKey::String("a\"b".to_string()), ClVal::Integer(1));
+        dict.insert(ClKey::Identifier("c".to_string()), ClVal::List(vec![ClVal::Dict(inner)]));
+        assert_eq!(
+            Writer::new().write(&ClVal::Dict(dict)).unwrap_err().to_string(),
+            "invalid value type: string containing a quote: a\"b"
+        );
+    }
+
+    #[test]
+    fn test_write_roundtrip() {
+        let values = ::parse(include_bytes!("../examples/test")).unwrap();
+        let buf = Writer::new().write(&values).unwrap();
+        assert_eq!(::parse(&buf).unwrap(), values);
+    }
+}